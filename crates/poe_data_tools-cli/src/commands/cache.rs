@@ -0,0 +1,69 @@
+use std::{
+    io::{self, BufWriter, Write},
+    path::Path,
+};
+
+use anyhow::{Context, Result};
+use poe_data_tools::fs::cdn::cache::CDNCache;
+
+fn gb(bytes: u64) -> f64 {
+    bytes as f64 / 1e9
+}
+
+/// Print each cached patch, its size, and how much of it is shared with other cached patches
+pub fn list_cache(cache_dir: &Path) -> Result<()> {
+    let cache = CDNCache::scan(cache_dir).context("Failed to scan cache")?;
+    let mut stdout = BufWriter::new(io::stdout().lock());
+
+    for patch in &cache.patches {
+        let (shared, shared_bytes) = cache
+            .shared(patch)
+            .fold((0, 0), |(n, bytes), f| (n + 1, bytes + f.size));
+
+        writeln!(
+            stdout,
+            "{:<12} {:>6} files {:>8.2} GB   {:>6} shared {:>8.2} GB   {}",
+            patch.version,
+            patch.files.len(),
+            gb(patch.size),
+            shared,
+            gb(shared_bytes),
+            patch.root.display()
+        )
+        .context("Failed to write to stdout")?;
+    }
+
+    stdout.flush().context("Failed to flush stdout")
+}
+
+/// Remove all but the newest `keep` patches downloaded from each CDN
+pub fn prune_cache(cache_dir: &Path, keep: usize, dry_run: bool) -> Result<()> {
+    let cache = CDNCache::scan(cache_dir).context("Failed to scan cache")?;
+    let candidates = cache.prune_candidates(keep);
+
+    let mut freed = 0;
+    for patch in candidates {
+        // Files only this patch has can't be reused by other patches once removed
+        let unique = patch.files.len() - cache.shared(patch).count();
+        log::info!(
+            "{} {} ({:.2} GB, {unique} files not cached for any other patch)",
+            if dry_run { "Would remove" } else { "Removing" },
+            patch.path.display(),
+            gb(patch.size),
+        );
+
+        if !dry_run {
+            patch
+                .remove()
+                .with_context(|| format!("Failed to remove {:?}", patch.path))?;
+        }
+        freed += patch.size;
+    }
+
+    log::info!(
+        "{} {:.2} GB",
+        if dry_run { "Would free" } else { "Freed" },
+        gb(freed)
+    );
+    Ok(())
+}
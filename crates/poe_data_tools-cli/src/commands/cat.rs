@@ -3,14 +3,12 @@ use std::io::{self, BufWriter, Write};
 use anyhow::{Context, Result};
 use poe_data_tools::fs::{FS, FileSystem};
 
-/// Write the contents of the file to stdout
+/// Stream the contents of the file to stdout
 pub fn cat_file(fs: &mut FS, path: &str) -> Result<()> {
-    let contents = fs.read(path).context("Failed to read file")?;
+    let mut file = fs.open(path).context("Failed to open file")?;
 
     let mut stdout = BufWriter::new(io::stdout().lock());
-    stdout
-        .write_all(&contents)
-        .context("Failed to write to stdout")?;
+    io::copy(&mut file, &mut stdout).context("Failed to write to stdout")?;
 
     stdout.flush().context("Failed to flush stdout")
 }
@@ -0,0 +1,58 @@
+use std::io::{self, BufWriter, Write};
+
+use anyhow::{Context, Result};
+use glob::Pattern;
+use poe_data_tools::fs::{
+    FS,
+    diff::{ChangeKind, diff},
+};
+
+/// How the differences are printed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiffFormat {
+    /// A line per changed file, prefixed with A (added), D (removed) or M (modified)
+    Text,
+    /// A single JSON object holding every change
+    Json,
+}
+
+/// Print the files added, removed and modified between two file systems
+pub fn diff_files(from: &FS, to: &FS, patterns: &[Pattern], format: DiffFormat) -> Result<()> {
+    let diff = diff(from, to, patterns).context("Failed to compare file systems")?;
+
+    // Use a buffered writer since there may be a lot of changes
+    let mut stdout = BufWriter::new(io::stdout().lock());
+
+    match format {
+        DiffFormat::Text => diff.changes.iter().try_for_each(|change| {
+            let size = |size: Option<u64>| size.map(|s| s.to_string()).unwrap_or_default();
+            match change.kind {
+                ChangeKind::Added => writeln!(stdout, "A {}", change.path),
+                ChangeKind::Removed => writeln!(stdout, "D {}", change.path),
+                ChangeKind::Modified => writeln!(
+                    stdout,
+                    "M {} ({} -> {} bytes)",
+                    change.path,
+                    size(change.from_size),
+                    size(change.to_size)
+                ),
+            }
+        }),
+        DiffFormat::Json => serde_json::to_writer_pretty(&mut stdout, &diff)
+            .map_err(io::Error::from)
+            .and_then(|_| writeln!(stdout)),
+    }
+    .context("Failed to write to stdout")?;
+
+    stdout.flush().context("Failed to flush stdout")?;
+
+    log::info!(
+        "{} added, {} removed, {} modified, {} unchanged",
+        diff.of_kind(ChangeKind::Added).count(),
+        diff.of_kind(ChangeKind::Removed).count(),
+        diff.of_kind(ChangeKind::Modified).count(),
+        diff.unchanged
+    );
+
+    Ok(())
+}
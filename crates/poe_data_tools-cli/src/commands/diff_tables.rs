@@ -0,0 +1,186 @@
+use std::{
+    collections::BTreeSet,
+    io::{self, BufWriter, Write},
+    path::Path,
+};
+
+use anyhow::{Context, Result, ensure};
+use glob::Pattern;
+use poe_data_tools::{
+    Patch,
+    dat::{
+        diff::{SchemaDrift, TableDiff, diff_table},
+        graph::TableGraph,
+        schema::{fetch_schema, load_schema},
+    },
+    file_parsers::dat::types::DatLayout,
+    fs::{FS, FileSystem},
+};
+
+use crate::VERBOSE;
+
+/// How the table changes are written out
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TableDiffFormat {
+    /// A JSON array with an object per changed table
+    Json,
+    /// A changelog with a section per changed table
+    Markdown,
+}
+
+/// Lowercase names of the tables matching the patterns
+fn table_names(fs: &FS, patterns: &[Pattern]) -> BTreeSet<String> {
+    fs.glob(patterns)
+        .filter_map(|path| {
+            let name = Path::new(&path).file_stem()?.to_str()?.to_lowercase();
+            Some(name)
+        })
+        .collect()
+}
+
+fn drift_warning(drift: &SchemaDrift) -> String {
+    match drift {
+        SchemaDrift::RowWidth { from, to, schema } => {
+            let schema = schema
+                .map(|width| format!(", the schema describes {width}"))
+                .unwrap_or_default();
+            format!("Row width changed from {from} to {to} bytes{schema}")
+        }
+        SchemaDrift::MissingColumn { column } => {
+            format!("Column {column:?} could only be parsed in one version")
+        }
+    }
+}
+
+/// Format a key or value as inline code for a changelog
+fn md_code(value: &str) -> String {
+    format!("`{}`", value.replace('`', "'"))
+}
+
+fn md_value(value: &Option<String>) -> String {
+    value
+        .as_deref()
+        .map(md_code)
+        .unwrap_or_else(|| "*null*".to_owned())
+}
+
+fn write_markdown(out: &mut impl Write, diffs: &[TableDiff]) -> io::Result<()> {
+    writeln!(out, "# Table changes")?;
+
+    for diff in diffs {
+        writeln!(out, "\n## {}", diff.table)?;
+
+        for drift in &diff.drift {
+            writeln!(
+                out,
+                "\n> **Warning:** {}. The schema may not match both versions, so some changes \
+                 may just be misaligned columns.",
+                drift_warning(drift)
+            )?;
+        }
+
+        if !diff.added.is_empty() {
+            writeln!(out, "\n### Added\n")?;
+            for key in &diff.added {
+                writeln!(out, "- {}", md_code(key))?;
+            }
+        }
+
+        if !diff.removed.is_empty() {
+            writeln!(out, "\n### Removed\n")?;
+            for key in &diff.removed {
+                writeln!(out, "- {}", md_code(key))?;
+            }
+        }
+
+        if !diff.modified.is_empty() {
+            writeln!(out, "\n### Modified\n")?;
+            for row in &diff.modified {
+                writeln!(out, "- {}", md_code(&row.key))?;
+                for change in &row.changes {
+                    writeln!(
+                        out,
+                        "  - {}: {} -> {}",
+                        change.column,
+                        md_value(&change.from),
+                        md_value(&change.to)
+                    )?;
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Compare the rows of tables between two file systems, matching them by key
+#[allow(clippy::too_many_arguments)]
+pub fn diff_tables(
+    from: &mut FS,
+    to: &mut FS,
+    from_version: &Patch,
+    to_version: &Patch,
+    patterns: &[Pattern],
+    cache_dir: &Path,
+    schema: Option<impl AsRef<Path>>,
+    format: TableDiffFormat,
+) -> Result<()> {
+    for pattern in patterns {
+        ensure!(
+            DatLayout::from_path(pattern.as_str()).is_some(),
+            "Only .dat, .dat64, .datl, .datl64 and .datc64 tables can be compared."
+        );
+    }
+
+    let schemas = if let Some(path) = schema {
+        load_schema(path.as_ref()).context("Failed to load schema file")?
+    } else {
+        fetch_schema(cache_dir).context("Failed to fetch schema file")?
+    };
+
+    let from_tables = table_names(from, patterns);
+    let to_tables = table_names(to, patterns);
+    from_tables
+        .symmetric_difference(&to_tables)
+        .for_each(|name| log::info!("Table only in one version, skipping: {name}"));
+
+    let mut from = TableGraph::new(from, &schemas, from_version);
+    let mut to = TableGraph::new(to, &schemas, to_version);
+
+    let diffs = from_tables
+        .intersection(&to_tables)
+        .filter_map(|name| match diff_table(&mut from, &mut to, name) {
+            Ok(diff) => Some(diff),
+            Err(e) => {
+                let error_message = if *VERBOSE.get().unwrap() {
+                    format!("{e:?}")
+                } else {
+                    format!("{e}")
+                };
+                log::error!("Failed to compare table {name:?}: {error_message}");
+                None
+            }
+        })
+        .filter(|diff| !diff.is_empty() || !diff.drift.is_empty())
+        .collect::<Vec<_>>();
+
+    for diff in &diffs {
+        diff.drift
+            .iter()
+            .for_each(|drift| log::warn!("{}: {}", diff.table, drift_warning(drift)));
+    }
+
+    let mut stdout = BufWriter::new(io::stdout().lock());
+    match format {
+        TableDiffFormat::Json => serde_json::to_writer_pretty(&mut stdout, &diffs)
+            .map_err(io::Error::from)
+            .and_then(|_| writeln!(stdout)),
+        TableDiffFormat::Markdown => write_markdown(&mut stdout, &diffs),
+    }
+    .context("Failed to write to stdout")?;
+    stdout.flush().context("Failed to flush stdout")?;
+
+    log::info!("{} tables changed", diffs.len());
+
+    Ok(())
+}
@@ -1,7 +1,7 @@
 use std::{fs, path::Path};
 
 use anyhow::{Context, Result, ensure};
-use glob::{MatchOptions, Pattern};
+use glob::Pattern;
 use poe_data_tools::fs::{FS, FileSystem};
 
 use crate::VERBOSE;
@@ -17,20 +17,7 @@ pub fn extract_art(fs: &mut FS, patterns: &[Pattern], output_folder: &Path) -> R
         );
     }
 
-    let filenames = fs
-        .list()
-        .filter(|filename| {
-            patterns.iter().any(|pattern| {
-                pattern.matches_with(
-                    filename,
-                    MatchOptions {
-                        require_literal_separator: true,
-                        ..Default::default()
-                    },
-                )
-            })
-        })
-        .collect::<Vec<_>>();
+    let filenames = fs.glob(patterns).collect::<Vec<_>>();
 
     fs.batch_read(&filenames)
         // Print and filter out errors
@@ -0,0 +1,154 @@
+use std::{fs, path::Path};
+
+use anyhow::{Context, Result, bail, ensure};
+use glob::Pattern;
+use poe_data_tools::{
+    file_parsers::{
+        FileParser,
+        ast::{ASTParser, types::ASTFile},
+        fmt::FMTParser,
+        sm::SMParser,
+        smd::SMDParser,
+        tgm::TGMParser,
+    },
+    fs::{FS, FileSystem},
+    gltf::{geometries_to_glb, skeleton_to_glb, skinned_mesh_to_glb},
+};
+
+use crate::VERBOSE;
+
+const MESH_EXTENSIONS: [&str; 5] = [".sm", ".smd", ".fmt", ".tgm", ".ast"];
+
+/// Look for the skeleton belonging to a skinned mesh. Skeletons either share the mesh's name, or
+/// are a "rig.ast" in the same folder.
+fn find_skeleton(fs: &FS, sm_path: &str) -> Option<ASTFile> {
+    let stem = sm_path.strip_suffix(".sm").unwrap_or(sm_path);
+    let folder = sm_path.rsplit_once('/').map(|(f, _)| f).unwrap_or_default();
+
+    [format!("{stem}.ast"), format!("{folder}/rig.ast")]
+        .iter()
+        .find_map(|path| {
+            let bytes = fs.read(path).ok()?;
+
+            match ASTParser.parse(&bytes) {
+                Ok(ast) => {
+                    log::debug!("Using skeleton {path:?} for {sm_path:?}");
+                    Some(ast)
+                }
+                Err(e) => {
+                    log::warn!("Failed to parse skeleton {path:?}: {e}");
+                    None
+                }
+            }
+        })
+}
+
+/// Convert a single mesh file to GLB bytes
+fn process_file(
+    fs: &FS,
+    filename: &str,
+    contents: &[u8],
+    lod: usize,
+    animations: bool,
+) -> Result<Vec<u8>> {
+    let extension = filename
+        .rsplit_once('.')
+        .map(|(_, e)| e)
+        .unwrap_or_default();
+
+    let glb = match extension {
+        "sm" => {
+            let sm = SMParser.parse(contents).context("Failed to parse .sm")?;
+
+            let smd_bytes = fs
+                .read(&sm.smd_file)
+                .with_context(|| format!("Failed to read mesh data: {:?}", sm.smd_file))?;
+            let smd = SMDParser
+                .parse(&smd_bytes)
+                .context("Failed to parse .smd")?;
+
+            let skeleton = find_skeleton(fs, filename);
+
+            skinned_mesh_to_glb(&sm, &smd, skeleton.as_ref(), lod, animations)?
+        }
+        "smd" => {
+            let smd = SMDParser.parse(contents).context("Failed to parse .smd")?;
+            geometries_to_glb(&[smd.geometry(lod)?])?
+        }
+        "fmt" => {
+            let fmt = FMTParser.parse(contents).context("Failed to parse .fmt")?;
+            geometries_to_glb(&[fmt.geometry(lod)?])?
+        }
+        "tgm" => {
+            let tgm = TGMParser.parse(contents).context("Failed to parse .tgm")?;
+            geometries_to_glb(&tgm.geometries(lod)?)?
+        }
+        "ast" => {
+            let ast = ASTParser.parse(contents).context("Failed to parse .ast")?;
+            skeleton_to_glb(&ast)?
+        }
+        ext => bail!("Unsupported mesh type: {ext:?}"),
+    };
+
+    Ok(glb)
+}
+
+/// Convert mesh files matching a glob pattern to binary glTF. Skeletons (.ast) are exported with
+/// all of their animations.
+pub fn dump_meshes(
+    fs: &mut FS,
+    patterns: &[Pattern],
+    output_folder: &Path,
+    lod: usize,
+    animations: bool,
+) -> Result<()> {
+    for pattern in patterns {
+        ensure!(
+            MESH_EXTENSIONS
+                .iter()
+                .any(|ext| pattern.as_str().ends_with(ext)),
+            "Only {MESH_EXTENSIONS:?} mesh export is supported."
+        );
+    }
+
+    let filenames = fs.glob(patterns).collect::<Vec<_>>();
+
+    let fs = &*fs;
+    fs.batch_read(&filenames)
+        // Print and filter out errors
+        .filter_map(|(path, res)| match res {
+            Ok(b) => Some((path, b)),
+            Err(e) => {
+                log::error!("Failed to extract file: {:?}: {:?}", path, e);
+                None
+            }
+        })
+        // Attempt to convert file contents
+        .map(|(filename, contents)| -> Result<_, anyhow::Error> {
+            let glb = process_file(fs, &filename, &contents, lod, animations)
+                .with_context(|| format!("Failed to convert mesh: {filename}"))?;
+
+            let out_filename = output_folder.join(filename.as_ref()).with_extension("glb");
+            fs::create_dir_all(out_filename.parent().unwrap())
+                .with_context(|| format!("Failed to create output folder: {out_filename:?}"))?;
+
+            fs::write(&out_filename, glb)
+                .with_context(|| format!("Failed to write file: {out_filename:?}"))?;
+
+            Ok(filename)
+        })
+        // Report results
+        .for_each(|result| match result {
+            Ok(filename) => log::info!("Extracted mesh: {}", filename),
+            Err(e) => {
+                let error_message = if *VERBOSE.get().unwrap() {
+                    format!("{e:?}")
+                } else {
+                    format!("{e}")
+                };
+                log::error!("Failed to extract mesh: {error_message}");
+            }
+        });
+
+    Ok(())
+}
@@ -0,0 +1,239 @@
+use std::{
+    fs::{File, create_dir_all},
+    io::BufWriter,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result, ensure};
+use arrow_array::RecordBatch;
+use arrow_ipc::writer::FileWriter;
+use bytes::Bytes;
+use glob::Pattern;
+use parquet::{arrow::ArrowWriter, basic::Compression, file::properties::WriterProperties};
+use poe_data_tools::{
+    Patch,
+    dat::{
+        localized::Language,
+        schema::{DatTableSchema, fetch_schema, load_schema},
+        table::{find_table, parse_table, table_name},
+    },
+    file_parsers::{
+        FileParser,
+        dat::{DatParser, types::DatLayout},
+    },
+    fs::{FS, FileSystem},
+};
+use serde::Serialize;
+
+use super::tables::{dump_localized_tables, log_result};
+
+/// Columnar file formats that keep Arrow types intact
+#[derive(Debug, Clone, Copy)]
+pub enum TableFormat {
+    Parquet,
+    /// Arrow IPC file, aka. Feather v2
+    Arrow,
+}
+
+impl TableFormat {
+    fn extension(&self) -> &'static str {
+        match self {
+            TableFormat::Parquet => "parquet",
+            TableFormat::Arrow => "arrow",
+        }
+    }
+}
+
+/// Describes the contents of an output folder
+#[derive(Debug, Serialize)]
+struct Manifest {
+    /// Game patch the tables were extracted from, eg. "3.25.1.2", if it's known
+    patch: Option<String>,
+    /// Major game version whose schema was used, ie. 1 or 2
+    schema_major: u32,
+    schema_version: Option<u32>,
+    /// Unix timestamp of when the schema was generated
+    schema_created_at: Option<u64>,
+    format: &'static str,
+    /// Codes of the languages merged into each table, if any
+    languages: Vec<&'static str>,
+    tables: Vec<ManifestEntry>,
+}
+
+#[derive(Debug, Serialize)]
+struct ManifestEntry {
+    name: String,
+    /// Virtual path of the source table
+    source: String,
+    /// Path of the output file, relative to the manifest
+    path: PathBuf,
+    rows: usize,
+    columns: usize,
+}
+
+fn save_table(table: &RecordBatch, path: &Path, format: TableFormat) -> Result<()> {
+    create_dir_all(path.parent().context("No parent directory")?)
+        .context("Failed to create output dirs")?;
+
+    let file = File::create(path).context("Failed to create output file")?;
+
+    match format {
+        TableFormat::Parquet => {
+            let props = WriterProperties::builder()
+                .set_compression(Compression::SNAPPY)
+                .build();
+            let mut writer = ArrowWriter::try_new(file, table.schema(), Some(props))
+                .context("Failed to create parquet writer")?;
+            writer.write(table).context("Failed to write table")?;
+            writer.close().context("Failed to finish parquet file")?;
+        }
+        TableFormat::Arrow => {
+            let mut writer = FileWriter::try_new(BufWriter::new(file), &table.schema())
+                .context("Failed to create arrow writer")?;
+            writer.write(table).context("Failed to write table")?;
+            writer.finish().context("Failed to finish arrow file")?;
+        }
+    }
+
+    Ok(())
+}
+
+fn process_file(
+    bytes: &Bytes,
+    layout: DatLayout,
+    output_path: &Path,
+    schema: &DatTableSchema,
+    format: TableFormat,
+) -> Result<RecordBatch> {
+    // Load dat file
+    let table = DatParser
+        .parse(bytes)
+        .context("Failed to parse table data")?
+        .with_layout(layout);
+
+    ensure!(!table.rows.is_empty(), "Empty table");
+
+    // Apply it
+    let df = parse_table(&table, schema).context("Failed to apply schema to table")?;
+
+    save_table(&df, output_path, format).context("Failed to write table")?;
+
+    Ok(df)
+}
+
+/// Convert tables into Parquet or Arrow IPC files, along with a manifest.json describing
+/// them. If any languages are given, localized copies of each table are merged into it, see
+/// [dump_localized_tables].
+#[allow(clippy::too_many_arguments)]
+pub fn dump_tables(
+    fs: &mut FS,
+    patterns: &[Pattern],
+    cache_dir: &Path,
+    output_folder: &Path,
+    patch: &Patch,
+    schema: Option<impl AsRef<Path>>,
+    format: TableFormat,
+    languages: &[Language],
+) -> Result<()> {
+    for pattern in patterns {
+        ensure!(
+            DatLayout::from_path(pattern.as_str()).is_some(),
+            "Only .dat, .dat64, .datl, .datl64 and .datc64 table export is supported."
+        );
+    }
+
+    let version = patch.major();
+
+    let schemas = if let Some(path) = schema {
+        load_schema(path.as_ref()).context("Failed to load schema file")?
+    } else {
+        fetch_schema(cache_dir).context("Failed to fetch schema file")?
+    };
+
+    let filenames = fs.glob(patterns).collect::<Vec<_>>();
+
+    let tables = if !languages.is_empty() {
+        dump_localized_tables(
+            fs,
+            &filenames,
+            &schemas,
+            patch,
+            languages,
+            |filename, schema, df| {
+                let path = PathBuf::from(filename).with_extension(format.extension());
+                save_table(df, &output_folder.join(&path), format)?;
+
+                Ok(ManifestEntry {
+                    name: schema.name.clone(),
+                    source: filename.to_owned(),
+                    path,
+                    rows: df.num_rows(),
+                    columns: df.num_columns(),
+                })
+            },
+        )
+    } else {
+        fs.batch_read(&filenames)
+            // Print and filter out errors
+            .filter_map(|(path, res)| match res {
+                Ok(b) => Some((path, b)),
+                Err(e) => {
+                    log::error!("Failed to extract file: {:?}: {:?}", path, e);
+                    None
+                }
+            })
+            // Attempt to read file contents
+            .map(|(filename, contents)| -> Result<_, anyhow::Error> {
+                let schema = find_table(&schemas, &table_name(&filename), version)
+                    .with_context(|| format!("Couldn't find schema for {:?}", filename))?;
+
+                // Convert the data table
+                let layout = DatLayout::from_path(filename.as_ref()).unwrap_or_default();
+                let path = PathBuf::from(filename.as_ref()).with_extension(format.extension());
+                let df = process_file(
+                    &contents,
+                    layout,
+                    &output_folder.join(&path),
+                    schema,
+                    format,
+                )
+                .with_context(|| format!("Failed to process file: {:?}", filename))?;
+
+                let entry = ManifestEntry {
+                    name: schema.name.clone(),
+                    source: filename.to_string(),
+                    path,
+                    rows: df.num_rows(),
+                    columns: df.num_columns(),
+                };
+                Ok((filename.into_owned(), entry))
+            })
+            // Report results
+            .filter_map(log_result)
+            .collect::<Vec<_>>()
+    };
+
+    let manifest = Manifest {
+        patch: fs
+            .patch_version()
+            .or(match patch {
+                Patch::Specific(v) => Some(v),
+                _ => None,
+            })
+            .map(str::to_owned),
+        schema_major: version,
+        schema_version: schemas.version,
+        schema_created_at: schemas.created_at,
+        format: format.extension(),
+        languages: languages.iter().map(|l| l.code()).collect(),
+        tables,
+    };
+
+    create_dir_all(output_folder).context("Failed to create output folder")?;
+    let manifest_path = output_folder.join("manifest.json");
+    let file = File::create(&manifest_path).context("Failed to create manifest file")?;
+    serde_json::to_writer_pretty(BufWriter::new(file), &manifest)
+        .context("Failed to write manifest")?;
+
+    Ok(())
+}
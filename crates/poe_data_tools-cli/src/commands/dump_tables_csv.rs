@@ -1,6 +1,6 @@
 use std::{
     fs::{File, create_dir_all},
-    path::{Path, PathBuf},
+    path::Path,
     sync::Arc,
 };
 
@@ -10,18 +10,22 @@ use arrow_cast::display::{ArrayFormatter, FormatOptions};
 use arrow_csv::Writer;
 use arrow_schema::{DataType, SchemaBuilder};
 use bytes::Bytes;
-use glob::{MatchOptions, Pattern};
+use glob::Pattern;
 use poe_data_tools::{
     Patch,
     dat::{
+        localized::Language,
         schema::{DatTableSchema, fetch_schema, load_schema},
-        table::parse_table,
+        table::{find_table, parse_table, table_name},
+    },
+    file_parsers::{
+        FileParser,
+        dat::{DatParser, types::DatLayout},
     },
-    file_parsers::{FileParser, dat::DatParser},
     fs::{FS, FileSystem},
 };
 
-use crate::VERBOSE;
+use super::tables::{dump_localized_tables, log_result};
 
 /// Save the dataframe to a table, handling list columns
 fn save_to_csv(table: &RecordBatch, path: &Path) -> Result<()> {
@@ -66,11 +70,17 @@ fn save_to_csv(table: &RecordBatch, path: &Path) -> Result<()> {
         .context("Failed to write DF to file")
 }
 
-fn process_file(bytes: &Bytes, output_path: &Path, schema: &DatTableSchema) -> Result<()> {
+fn process_file(
+    bytes: &Bytes,
+    layout: DatLayout,
+    output_path: &Path,
+    schema: &DatTableSchema,
+) -> Result<()> {
     // Load dat file
     let table = DatParser
         .parse(bytes)
-        .context("Failed to parse table data")?;
+        .context("Failed to parse table data")?
+        .with_layout(layout);
 
     ensure!(!table.rows.is_empty(), "Empty table");
 
@@ -83,23 +93,25 @@ fn process_file(bytes: &Bytes, output_path: &Path, schema: &DatTableSchema) -> R
     Ok(())
 }
 
-/// Convert datc64 tables into CSV files
+/// Convert tables into CSV files. If any languages are given, localized copies of each table are
+/// merged into it, see [dump_localized_tables].
 pub fn dump_tables(
     fs: &mut FS,
     patterns: &[Pattern],
     cache_dir: &Path,
     output_folder: &Path,
-    version: &Patch,
+    patch: &Patch,
     schema: Option<impl AsRef<Path>>,
+    languages: &[Language],
 ) -> Result<()> {
     for pattern in patterns {
         ensure!(
-            pattern.as_str().ends_with(".datc64"),
-            "Only .datc64 table export is supported."
+            DatLayout::from_path(pattern.as_str()).is_some(),
+            "Only .dat, .dat64, .datl, .datl64 and .datc64 table export is supported."
         );
     }
 
-    let version = match version {
+    let version = match patch {
         Patch::One => 1,
         Patch::Two => 2,
         _ => bail!("Only patch versions 1/2 supported for table extraction."),
@@ -112,20 +124,19 @@ pub fn dump_tables(
         fetch_schema(cache_dir).context("Failed to fetch schema file")?
     };
 
-    let filenames = fs
-        .list()
-        .filter(|filename| {
-            patterns.iter().any(|pattern| {
-                pattern.matches_with(
-                    filename,
-                    MatchOptions {
-                        require_literal_separator: true,
-                        ..Default::default()
-                    },
-                )
-            })
-        })
-        .collect::<Vec<_>>();
+    let filenames = fs.glob(patterns).collect::<Vec<_>>();
+
+    if !languages.is_empty() {
+        dump_localized_tables(
+            fs,
+            &filenames,
+            &schemas,
+            patch,
+            languages,
+            |filename, _, df| save_to_csv(df, &output_folder.join(filename).with_extension("csv")),
+        );
+        return Ok(());
+    }
 
     fs.batch_read(&filenames)
         // Print and filter out errors
@@ -138,35 +149,20 @@ pub fn dump_tables(
         })
         // Attempt to read file contents
         .map(|(filename, contents)| -> Result<_, anyhow::Error> {
-            // Load table schema - TODO: HashMap rather than vector
-            let schema = schemas
-                .tables
-                .iter()
-                // valid_for == 3 is common between both games
-                .filter(|t| t.valid_for == version || t.valid_for == 3)
-                .find(|t| {
-                    *t.name.to_lowercase() == *PathBuf::from(filename.as_ref()).file_stem().unwrap()
-                })
+            let schema = find_table(&schemas, &table_name(&filename), version)
                 .with_context(|| format!("Couldn't find schema for {:?}", filename))?;
 
             // Convert the data table
+            let layout = DatLayout::from_path(filename.as_ref()).unwrap_or_default();
             let output_path = output_folder.join(filename.as_ref()).with_extension("csv");
-            process_file(&contents, &output_path, schema)
+            process_file(&contents, layout, &output_path, schema)
                 .with_context(|| format!("Failed to process file: {:?}", filename))?;
 
-            Ok(filename)
+            Ok((filename.into_owned(), ()))
         })
         // Report results
-        .for_each(|result| match result {
-            Ok(filename) => log::info!("Extracted table: {}", filename),
-            Err(e) => {
-                let error_message = if *VERBOSE.get().unwrap() {
-                    format!("{e:?}")
-                } else {
-                    format!("{e}")
-                };
-                log::error!("Failed to extract table: {error_message}");
-            }
+        .for_each(|result| {
+            log_result(result);
         });
 
     Ok(())
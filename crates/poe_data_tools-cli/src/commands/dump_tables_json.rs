@@ -6,7 +6,7 @@ use std::{
 };
 
 use anyhow::{Context, Result, ensure};
-use glob::{MatchOptions, Pattern};
+use glob::Pattern;
 use poe_data_tools::{
     Patch,
     dat::{
@@ -99,6 +99,7 @@ fn resolve_keys(
     let DatFile {
         rows,
         variable_data,
+        ..
     } = contents;
 
     // FIXME: Figure out a way to give variable section to the parser without leaking it to a
@@ -186,6 +187,7 @@ fn resolve_table(
     let DatFile {
         rows,
         variable_data,
+        ..
     } = contents;
 
     // FIXME: Figure out a way to give variable section to the parser without leaking it to a
@@ -266,19 +268,7 @@ pub fn dump_tables(
 
     // Filter list of files we're going to extract
     let filenames = fs
-        .list()
-        // Filter on glob
-        .filter(|filename| {
-            patterns.iter().any(|pattern| {
-                pattern.matches_with(
-                    filename,
-                    MatchOptions {
-                        require_literal_separator: true,
-                        ..Default::default()
-                    },
-                )
-            })
-        })
+        .glob(patterns)
         // Skip files we can't process
         .filter(|filename| {
             let path = Path::new(filename);
@@ -0,0 +1,339 @@
+use std::{
+    fs::{create_dir_all, remove_file},
+    path::Path,
+};
+
+use anyhow::{Context, Result, bail, ensure};
+use arrow_array::{
+    Array, ArrayRef, RecordBatch,
+    cast::AsArray,
+    types::{Float32Type, Int16Type, Int32Type, UInt16Type, UInt32Type, UInt64Type},
+};
+use arrow_schema::DataType;
+use glob::Pattern;
+use poe_data_tools::{
+    Patch,
+    dat::{
+        localized::is_localized_path,
+        schema::{ColumnSchema, DatTableSchema, SchemaCollection, fetch_schema, load_schema},
+        table::{find_table, parse_table, table_name},
+    },
+    file_parsers::{
+        FileParser,
+        dat::{DatParser, types::DatLayout},
+    },
+    fs::{FS, FileSystem},
+};
+use rusqlite::{Connection, Transaction, params_from_iter, types::Value};
+
+use crate::VERBOSE;
+
+/// Name of the row index column added to every table. References point at this column.
+const INDEX_COLUMN: &str = "_index";
+
+/// Quote an identifier for use in SQL
+fn quote(ident: &str) -> String {
+    format!("\"{}\"", ident.replace('"', "\"\""))
+}
+
+/// SQLite column type for a dat column type
+fn sql_type(column: &ColumnSchema) -> &'static str {
+    match column.column_type.as_str() {
+        "string" => "TEXT",
+        "f32" => "REAL",
+        _ => "INTEGER",
+    }
+}
+
+/// Foreign key constraint for a reference column, if the target table is known
+fn foreign_key(
+    column: &ColumnSchema,
+    table: &DatTableSchema,
+    schemas: &SchemaCollection,
+) -> Option<String> {
+    if !column.is_ref() {
+        return None;
+    }
+
+    let target = match column.get_ref() {
+        Some(target) => target,
+        // Plain rows without a target refer to their own table
+        None if column.column_type == "row" => table.name.as_str(),
+        None => return None,
+    };
+
+    // References don't always match the case of the table name
+    let target = schemas
+        .tables
+        .iter()
+        .map(|t| &t.name)
+        .chain(schemas.enumerations.iter().map(|e| &e.name))
+        .find(|name| name.eq_ignore_ascii_case(target))?;
+
+    Some(format!(
+        " REFERENCES {}({})",
+        quote(target),
+        quote(INDEX_COLUMN)
+    ))
+}
+
+/// Convert a single Arrow value to a SQLite one
+fn sql_value(array: &ArrayRef, row: usize) -> Value {
+    if array.is_null(row) {
+        return Value::Null;
+    }
+
+    match array.data_type() {
+        DataType::Utf8 => Value::Text(array.as_string::<i32>().value(row).to_owned()),
+        DataType::Boolean => Value::Integer(array.as_boolean().value(row) as i64),
+        DataType::Float32 => Value::Real(array.as_primitive::<Float32Type>().value(row) as f64),
+        DataType::UInt64 => Value::Integer(array.as_primitive::<UInt64Type>().value(row) as i64),
+        DataType::UInt32 => Value::Integer(array.as_primitive::<UInt32Type>().value(row) as i64),
+        DataType::Int32 => Value::Integer(array.as_primitive::<Int32Type>().value(row) as i64),
+        DataType::UInt16 => Value::Integer(array.as_primitive::<UInt16Type>().value(row) as i64),
+        DataType::Int16 => Value::Integer(array.as_primitive::<Int16Type>().value(row) as i64),
+        data_type => unreachable!("Unexpected column type from parse_table: {data_type}"),
+    }
+}
+
+/// Write a parsed table into the database. Scalar columns are stored in a table named after the
+/// dat table, intervals are split into Min/Max columns, and each array column gets a join table
+/// named `<Table>_<Column>`.
+fn write_table(
+    tx: &Transaction,
+    df: &RecordBatch,
+    schema: &DatTableSchema,
+    schemas: &SchemaCollection,
+) -> Result<()> {
+    let table_name = quote(&schema.name);
+
+    // Only columns that were successfully parsed are written
+    let columns = schema
+        .enumerate()
+        .filter_map(|(name, column)| df.column_by_name(&name).map(|a| (name, column, a)))
+        .collect::<Vec<_>>();
+
+    // Main table
+    let mut definitions = vec![format!("{} INTEGER PRIMARY KEY", quote(INDEX_COLUMN))];
+    for (name, column, _) in &columns {
+        match (column.array, column.interval) {
+            (false, false) => {
+                let fk = foreign_key(column, schema, schemas).unwrap_or_default();
+                definitions.push(format!("{} {}{fk}", quote(name), sql_type(column)));
+            }
+            (false, true) => {
+                definitions.push(format!(
+                    "{} {}",
+                    quote(&format!("{name}Min")),
+                    sql_type(column)
+                ));
+                definitions.push(format!(
+                    "{} {}",
+                    quote(&format!("{name}Max")),
+                    sql_type(column)
+                ));
+            }
+            _ => {}
+        }
+    }
+    tx.execute_batch(&format!(
+        "CREATE TABLE {table_name} ({});",
+        definitions.join(", ")
+    ))?;
+
+    let placeholders = vec!["?"; definitions.len()].join(", ");
+    let mut insert = tx.prepare(&format!("INSERT INTO {table_name} VALUES ({placeholders})"))?;
+    for row in 0..df.num_rows() {
+        let mut values = vec![Value::Integer(row as i64)];
+        for (_, column, array) in &columns {
+            match (column.array, column.interval) {
+                (false, false) => values.push(sql_value(array, row)),
+                (false, true) if array.is_null(row) => values.extend([Value::Null, Value::Null]),
+                (false, true) => {
+                    let interval = array.as_list::<i32>().value(row);
+                    values.push(sql_value(&interval, 0));
+                    values.push(sql_value(&interval, 1));
+                }
+                _ => {}
+            }
+        }
+        insert.execute(params_from_iter(values))?;
+    }
+
+    // Indices for columns used as keys
+    for (name, column, _) in &columns {
+        if column.unique && !column.is_multi() {
+            tx.execute_batch(&format!(
+                "CREATE INDEX {} ON {table_name} ({});",
+                quote(&format!("idx_{}_{name}", schema.name)),
+                quote(name)
+            ))?;
+        }
+    }
+
+    // Join tables for arrays
+    for (name, column, array) in columns.iter().filter(|(_, c, _)| c.array) {
+        let join_name = quote(&format!("{}_{name}", schema.name));
+        let fk = foreign_key(column, schema, schemas).unwrap_or_default();
+
+        tx.execute_batch(&format!(
+            "CREATE TABLE {join_name} (
+                \"_row\" INTEGER NOT NULL REFERENCES {table_name}({index}),
+                \"_position\" INTEGER NOT NULL,
+                \"value\" {value_type}{fk},
+                PRIMARY KEY (\"_row\", \"_position\")
+            );
+            CREATE INDEX {value_index} ON {join_name} (\"value\");",
+            index = quote(INDEX_COLUMN),
+            value_type = sql_type(column),
+            value_index = quote(&format!("idx_{}_{name}_value", schema.name)),
+        ))?;
+
+        let mut insert = tx.prepare(&format!("INSERT INTO {join_name} VALUES (?, ?, ?)"))?;
+        let list = array.as_list::<i32>();
+        for row in (0..list.len()).filter(|r| list.is_valid(*r)) {
+            let items = list.value(row);
+            for position in 0..items.len() {
+                insert.execute(params_from_iter([
+                    Value::Integer(row as i64),
+                    Value::Integer(position as i64),
+                    sql_value(&items, position),
+                ]))?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Create a table for each enum, holding the value used to refer to each variant and its name
+fn write_enums(tx: &Transaction, schemas: &SchemaCollection) -> Result<()> {
+    for enumeration in &schemas.enumerations {
+        if schemas.tables.iter().any(|t| t.name == enumeration.name) {
+            log::warn!(
+                "Skipping enum {:?}, a table has the same name",
+                enumeration.name
+            );
+            continue;
+        }
+
+        let table_name = quote(&enumeration.name);
+        tx.execute_batch(&format!(
+            "CREATE TABLE {table_name} ({} INTEGER PRIMARY KEY, \"Name\" TEXT);",
+            quote(INDEX_COLUMN)
+        ))?;
+
+        let mut insert = tx.prepare(&format!("INSERT INTO {table_name} VALUES (?, ?)"))?;
+        for (i, name) in enumeration.enumerators.iter().enumerate() {
+            insert.execute((i + enumeration.indexing, name))?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Convert tables into a single SQLite database, with foreign keys between them
+pub fn dump_tables(
+    fs: &mut FS,
+    patterns: &[Pattern],
+    cache_dir: &Path,
+    output_folder: &Path,
+    version: &Patch,
+    schema: Option<impl AsRef<Path>>,
+) -> Result<()> {
+    for pattern in patterns {
+        ensure!(
+            DatLayout::from_path(pattern.as_str()).is_some(),
+            "Only .dat, .dat64, .datl, .datl64 and .datc64 table export is supported."
+        );
+    }
+
+    if !matches!(version, Patch::One | Patch::Two) {
+        bail!("Only patch versions 1/2 supported for table extraction.");
+    }
+
+    let schemas = if let Some(path) = schema {
+        load_schema(path.as_ref()).context("Failed to load schema file")?
+    } else {
+        fetch_schema(cache_dir).context("Failed to fetch schema file")?
+    }
+    .filter_version(version);
+
+    create_dir_all(output_folder).context("Failed to create output folder")?;
+    let db_path = output_folder.join("tables.sqlite");
+    if db_path.exists() {
+        remove_file(&db_path).context("Failed to remove existing database")?;
+    }
+    let mut conn = Connection::open(&db_path).context("Failed to create database")?;
+
+    let tx = conn.transaction()?;
+    write_enums(&tx, &schemas).context("Failed to write enums")?;
+    tx.commit()?;
+
+    // Localized copies share the English table's name, so only the English one is written
+    let mut filenames = fs
+        .glob(patterns)
+        .filter(|filename| !is_localized_path(filename))
+        .collect::<Vec<_>>();
+
+    // Variants of a table, eg. `.datc64` and `.datl64`, would also share a name, so only the
+    // newest is written
+    filenames.sort_by_cached_key(|filename| {
+        let extension = Path::new(filename).extension().unwrap_or_default();
+        let newest = DatLayout::EXTENSIONS
+            .iter()
+            .position(|ext| extension.eq_ignore_ascii_case(ext));
+        (table_name(filename), newest)
+    });
+    filenames.dedup_by(|skipped, kept| {
+        let duplicate = table_name(skipped) == table_name(kept);
+        if duplicate {
+            log::info!("Skipping {skipped:?}, already writing its table from {kept:?}");
+        }
+        duplicate
+    });
+
+    fs.batch_read(&filenames)
+        // Print and filter out errors
+        .filter_map(|(path, res)| match res {
+            Ok(b) => Some((path, b)),
+            Err(e) => {
+                log::error!("Failed to extract file: {:?}: {:?}", path, e);
+                None
+            }
+        })
+        .map(|(filename, contents)| -> Result<_, anyhow::Error> {
+            let schema = find_table(&schemas, &table_name(&filename), version.major())
+                .with_context(|| format!("Couldn't find schema for {:?}", filename))?;
+
+            let table = DatParser
+                .parse(&contents)
+                .context("Failed to parse table data")?
+                .with_layout(DatLayout::from_path(filename.as_ref()).unwrap_or_default());
+            ensure!(!table.rows.is_empty(), "Empty table");
+
+            let df = parse_table(&table, schema).context("Failed to apply schema to table")?;
+
+            // One transaction per table so a failure doesn't leave half a table behind
+            let tx = conn.transaction()?;
+            write_table(&tx, &df, schema, &schemas)
+                .with_context(|| format!("Failed to write table: {:?}", filename))?;
+            tx.commit()?;
+
+            Ok(filename)
+        })
+        // Report results
+        .for_each(|result| match result {
+            Ok(filename) => log::info!("Extracted table: {}", filename),
+            Err(e) => {
+                let error_message = if *VERBOSE.get().unwrap() {
+                    format!("{e:?}")
+                } else {
+                    format!("{e}")
+                };
+                log::error!("Failed to extract table: {error_message}");
+            }
+        });
+
+    Ok(())
+}
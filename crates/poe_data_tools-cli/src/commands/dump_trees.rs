@@ -6,7 +6,7 @@ use std::{
 };
 
 use anyhow::{Context, Result, ensure};
-use glob::{MatchOptions, Pattern};
+use glob::Pattern;
 use poe_data_tools::{
     Patch,
     file_parsers::{
@@ -90,20 +90,7 @@ pub fn dump_trees(
         );
     }
 
-    let filenames = fs
-        .list()
-        .filter(|filename| {
-            patterns.iter().any(|pattern| {
-                pattern.matches_with(
-                    filename,
-                    MatchOptions {
-                        require_literal_separator: true,
-                        ..Default::default()
-                    },
-                )
-            })
-        })
-        .collect::<Vec<_>>();
+    let filenames = fs.glob(patterns).collect::<Vec<_>>();
 
     let passive_info = load_passive_info(fs, version, cache_dir)?
         .into_iter()
@@ -4,25 +4,12 @@ use std::{
 };
 
 use anyhow::{Context, Result};
-use glob::{MatchOptions, Pattern};
+use glob::Pattern;
 use poe_data_tools::fs::{FS, FileSystem};
 
 /// Extract files to disk matching a glob pattern
 pub fn extract_files(fs: &mut FS, patterns: &[Pattern], output_folder: &Path) -> Result<()> {
-    let filenames = fs
-        .list()
-        .filter(|filename| {
-            patterns.iter().any(|pattern| {
-                pattern.matches_with(
-                    filename,
-                    MatchOptions {
-                        require_literal_separator: true,
-                        ..Default::default()
-                    },
-                )
-            })
-        })
-        .collect::<Vec<_>>();
+    let filenames = fs.glob(patterns).collect::<Vec<_>>();
 
     fs.batch_read(&filenames)
         // Print and filter out errors
@@ -0,0 +1,111 @@
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use poe_data_tools::{
+    Patch,
+    dat::{
+        schema::{DatTableSchema, fetch_schema, load_schema},
+        table::{find_table, table_name},
+        validate::{SchemaIssue, validate_schema},
+    },
+    file_parsers::{
+        FileParser,
+        dat::{DatParser, types::DatLayout},
+    },
+    fs::{FS, FileSystem},
+};
+
+/// Check a table against its schema, printing any problems and guesses for uncovered columns
+pub fn inspect_table(
+    fs: &mut FS,
+    path: &str,
+    cache_dir: &Path,
+    version: &Patch,
+    schema: Option<impl AsRef<Path>>,
+) -> Result<()> {
+    let schemas = if let Some(path) = schema {
+        load_schema(path.as_ref()).context("Failed to load schema file")?
+    } else {
+        fetch_schema(cache_dir).context("Failed to fetch schema file")?
+    };
+
+    let bytes = fs.read(path).context("Failed to read file")?;
+    let table = DatParser
+        .parse(&bytes)
+        .context("Failed to parse table data")?
+        .with_layout(DatLayout::from_path(path).unwrap_or_default());
+
+    let table_name = table_name(path);
+    let schema = match find_table(&schemas, &table_name, version.major()) {
+        Some(schema) => schema.clone(),
+        None => {
+            // Everything is unclaimed, so every column gets a guess
+            println!("No schema found for {table_name:?}");
+            DatTableSchema {
+                valid_for: version.major(),
+                name: table_name,
+                columns: vec![],
+            }
+        }
+    };
+
+    let report = validate_schema(&table, &schema);
+
+    println!("Table: {}", schema.name);
+    println!("Rows: {}", report.rows);
+    println!("Row width: {} bytes", report.table_width);
+    match report.schema_width {
+        Some(width) => println!("Schema width: {width} bytes"),
+        None => println!("Schema width: unknown"),
+    }
+
+    if report.is_valid() {
+        println!("Schema matches table");
+    } else {
+        println!("Issues:");
+        for issue in &report.issues {
+            let message = match issue {
+                SchemaIssue::TooNarrow {
+                    schema_width,
+                    table_width,
+                } => format!(
+                    "schema covers {schema_width} of {table_width} bytes, {} unclaimed",
+                    table_width - schema_width
+                ),
+                SchemaIssue::TooWide {
+                    schema_width,
+                    table_width,
+                } => format!(
+                    "schema is {} bytes wider than the table",
+                    schema_width - table_width
+                ),
+                SchemaIssue::UnknownColumnType {
+                    column,
+                    column_type,
+                } => format!("column {column:?} has unknown type {column_type:?}"),
+                SchemaIssue::BadColumn {
+                    column,
+                    offset,
+                    error,
+                } => format!("column {column:?} at byte {offset}: {error}"),
+            };
+            println!("  - {message}");
+        }
+    }
+
+    if let Some(range) = &report.unclaimed {
+        println!("Suggested columns for bytes {range:?}:");
+        for s in &report.suggestions {
+            println!(
+                "  {:>5}..{:<5} {:<10}{} ({:.0}% of rows non-zero)",
+                s.offset,
+                s.offset + s.width,
+                s.column_type,
+                if s.array { " array" } else { "" },
+                s.evidence * 100.
+            );
+        }
+    }
+
+    Ok(())
+}
@@ -1,28 +1,57 @@
 use std::io::{self, BufWriter, Write};
 
 use anyhow::{Context, Result};
-use glob::{MatchOptions, Pattern};
-use poe_data_tools::fs::{FS, FileSystem};
+use glob::Pattern;
+use poe_data_tools::fs::{FS, FileSystem, stat::FileStat};
+
+/// How each listed file is printed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ListFormat {
+    /// Just the path
+    Paths,
+    /// Columns of size, bundle and hash before the path
+    Long,
+    /// A JSON object per line with all of the file's metadata
+    Json,
+}
+
+/// Print a file's metadata as columns: size, compressed bundle size, hash, bundle, path
+fn write_long(out: &mut impl Write, stat: &FileStat) -> io::Result<()> {
+    let or_dash = |v: Option<String>| v.unwrap_or_else(|| "-".to_owned());
+
+    writeln!(
+        out,
+        "{:>10} {:>10} {:016x} {} {}",
+        stat.size,
+        or_dash(stat.bundle_size.map(|s| s.to_string())),
+        stat.hash,
+        or_dash(stat.bundle.clone()),
+        stat.path
+    )
+}
 
 /// List filenames matching a glob pattern
-pub fn list_files(file_system: &FS, patterns: &[Pattern]) -> Result<()> {
+pub fn list_files(file_system: &FS, patterns: &[Pattern], format: ListFormat) -> Result<()> {
     // Use a buffered writer since we're dumping a lot of data
     let mut stdout = BufWriter::new(io::stdout().lock());
 
-    file_system
-        .list()
-        .filter(|path| {
-            patterns.iter().any(|pattern| {
-                pattern.matches_with(
-                    path,
-                    MatchOptions {
-                        require_literal_separator: true,
-                        ..Default::default()
-                    },
-                )
-            })
-        })
-        .try_for_each(|p| writeln!(stdout, "{}", p).context("Failed to write to stdout"))?;
+    file_system.glob(patterns).try_for_each(|p| -> Result<()> {
+        if format == ListFormat::Paths {
+            return writeln!(stdout, "{}", p).context("Failed to write to stdout");
+        }
+
+        let stat = file_system
+            .stat(&p)
+            .with_context(|| format!("Failed to stat file: {p:?}"))?;
+
+        match format {
+            ListFormat::Long => write_long(&mut stdout, &stat),
+            _ => serde_json::to_writer(&mut stdout, &stat)
+                .map_err(io::Error::from)
+                .and_then(|_| writeln!(stdout)),
+        }
+        .context("Failed to write to stdout")
+    })?;
 
     stdout.flush().context("Failed to flush stdout")
 }
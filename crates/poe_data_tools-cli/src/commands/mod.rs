@@ -1,8 +1,17 @@
+pub mod cache;
 pub mod cat;
+pub mod diff;
+pub mod diff_tables;
 pub mod dump_art;
+pub mod dump_meshes;
+pub mod dump_tables_arrow;
 pub mod dump_tables_csv;
 pub mod dump_tables_json;
+pub mod dump_tables_sqlite;
 pub mod dump_trees;
 pub mod extract;
+pub mod inspect_table;
 pub mod list;
+pub mod tables;
 pub mod translate;
+pub mod verify;
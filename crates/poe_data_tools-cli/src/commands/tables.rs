@@ -0,0 +1,65 @@
+//! Helpers shared by the table dump commands
+use anyhow::{Context, Result};
+use arrow_array::RecordBatch;
+use poe_data_tools::{
+    Patch,
+    dat::{
+        localized::{Language, is_localized_path},
+        schema::{DatTableSchema, SchemaCollection},
+        table::{FSDatEx, find_table, table_name},
+    },
+    fs::FS,
+};
+
+use crate::VERBOSE;
+
+/// Report the outcome of extracting a table from the given path, keeping what it produced if it
+/// succeeded
+pub fn log_result<T>(result: Result<(String, T)>) -> Option<T> {
+    match result {
+        Ok((filename, output)) => {
+            log::info!("Extracted table: {}", filename);
+            Some(output)
+        }
+        Err(e) => {
+            let error_message = if *VERBOSE.get().unwrap() {
+                format!("{e:?}")
+            } else {
+                format!("{e}")
+            };
+            log::error!("Failed to extract table: {error_message}");
+            None
+        }
+    }
+}
+
+/// Load tables with a column per language for each localized column, see
+/// [FSDatEx::load_localized_table], and write each out with `save`. Each table is read in every
+/// language, so they're loaded one at a time rather than batched.
+pub fn dump_localized_tables<T>(
+    fs: &mut FS,
+    filenames: &[String],
+    schemas: &SchemaCollection,
+    patch: &Patch,
+    languages: &[Language],
+    mut save: impl FnMut(&str, &DatTableSchema, &RecordBatch) -> Result<T>,
+) -> Vec<T> {
+    filenames
+        .iter()
+        // Localized copies are merged into the English table rather than exported on their own
+        .filter(|filename| !is_localized_path(filename))
+        .map(|filename| -> Result<_> {
+            let schema = find_table(schemas, &table_name(filename), patch.major())
+                .with_context(|| format!("Couldn't find schema for {:?}", filename))?;
+            let df = fs
+                .load_localized_table(schemas, filename, languages, patch)
+                .with_context(|| format!("Failed to load table: {:?}", filename))?;
+
+            let output = save(filename, schema, &df)
+                .with_context(|| format!("Failed to write table: {:?}", filename))?;
+
+            Ok((filename.clone(), output))
+        })
+        .filter_map(log_result)
+        .collect()
+}
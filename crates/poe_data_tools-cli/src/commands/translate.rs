@@ -1,7 +1,7 @@
 use std::path::Path;
 
 use anyhow::{Context, Result};
-use glob::{MatchOptions, Pattern};
+use glob::Pattern;
 use poe_data_tools::{
     Patch,
     file_parsers::Parser,
@@ -19,19 +19,7 @@ pub fn translate(
     poe_version: &Patch,
 ) -> Result<()> {
     let filenames = fs
-        .list()
-        // Filter on globs
-        .filter(|filename| {
-            patterns.iter().any(|pattern| {
-                pattern.matches_with(
-                    filename,
-                    MatchOptions {
-                        require_literal_separator: true,
-                        ..Default::default()
-                    },
-                )
-            })
-        })
+        .glob(patterns)
         // Filter out files that we can't parse
         // TODO: This might be expensive, also we might want to log skips?
         .filter(|filename| {
@@ -0,0 +1,43 @@
+use anyhow::{Result, bail};
+use poe_data_tools::fs::{
+    FS,
+    ggpk::{Corruption, VerifyReport},
+};
+
+/// Check a GGPK's contents against its stored digests, failing if anything is corrupt
+pub fn verify(fs: &FS) -> Result<()> {
+    let report = match fs {
+        FS::GGPK(fs) => fs.verify()?,
+        FS::LegacyGGPK(fs) => fs.verify()?,
+        _ => bail!("Verification is only supported for GGPK files"),
+    };
+
+    let VerifyReport {
+        files,
+        directories,
+        bytes,
+        corrupt,
+    } = &report;
+
+    for entry in corrupt {
+        let reason = match &entry.corruption {
+            Corruption::Mismatch => "contents don't match digest".to_owned(),
+            Corruption::Truncated => "truncated".to_owned(),
+            Corruption::Directory => "directory digest doesn't match children".to_owned(),
+            Corruption::Read(e) => format!("failed to read: {e}"),
+        };
+        log::error!("{}: {reason}", entry.path);
+    }
+
+    log::info!(
+        "Checked {files} files ({:.2} GB) in {directories} directories",
+        *bytes as f64 / 1e9
+    );
+
+    if !report.is_ok() {
+        bail!("{} corrupt entries found", corrupt.len());
+    }
+
+    log::info!("No corruption found");
+    Ok(())
+}
@@ -1,17 +1,39 @@
-use std::path::PathBuf;
+use std::{
+    path::PathBuf,
+    str::FromStr,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
 
-use anyhow::{Context, Result, ensure};
+use anyhow::{Context, Result, bail, ensure};
 use clap::{ArgGroup, Parser, Subcommand, ValueEnum};
 use glob::Pattern;
 use poe_data_tools::{
     Patch,
-    fs::{FS, cdn::cdn_base_url},
+    dat::localized::Language,
+    fs::{
+        FS,
+        cdn::{BatchProgress, CDNFS, cached_cdn_base_url, cdn_base_url},
+    },
 };
 use poe_data_tools_cli::{
     VERBOSE,
     commands::{
-        cat::cat_file, dump_art::extract_art, dump_tables_csv::dump_tables, dump_tables_json,
-        dump_trees::dump_trees, extract::extract_files, list::list_files, translate::translate,
+        cache::{list_cache, prune_cache},
+        cat::cat_file,
+        diff::{DiffFormat, diff_files},
+        diff_tables::{TableDiffFormat, diff_tables},
+        dump_art::extract_art,
+        dump_meshes::dump_meshes,
+        dump_tables_arrow::{self, TableFormat},
+        dump_tables_csv::dump_tables,
+        dump_tables_json, dump_tables_sqlite,
+        dump_trees::dump_trees,
+        extract::extract_files,
+        inspect_table::inspect_table,
+        list::{ListFormat, list_files},
+        translate::translate,
+        verify::verify,
     },
 };
 
@@ -19,12 +41,56 @@ use poe_data_tools_cli::{
 enum DumpDatsMode {
     Csv,
     Json,
+    /// Parquet files with a manifest.json
+    Parquet,
+    /// Arrow IPC (Feather v2) files with a manifest.json
+    Arrow,
+    /// A single SQLite database with foreign keys between tables
+    Sqlite,
+}
+
+/// One side of a diff: a CDN patch (eg. `2` or `3.25.1.2`), or `steam:<folder>`,
+/// `ggpk:<file>` or `dir:<folder>` for the other sources
+#[derive(Debug, Clone)]
+enum DiffSource {
+    Patch(Patch),
+    Steam(PathBuf),
+    Ggpk(PathBuf),
+    Dir(PathBuf),
+}
+
+impl FromStr for DiffSource {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once(':') {
+            Some(("steam", path)) => Ok(Self::Steam(path.into())),
+            Some(("ggpk", path)) => Ok(Self::Ggpk(path.into())),
+            Some(("dir", path)) => Ok(Self::Dir(path.into())),
+            _ => Patch::from_str(s).map(Self::Patch),
+        }
+    }
+}
+
+#[derive(Debug, Clone, ValueEnum)]
+enum DiffTablesMode {
+    Json,
+    /// A changelog with a section per changed table
+    Markdown,
 }
 
 #[derive(Debug, Subcommand)]
 enum Command {
     /// List files
     List {
+        /// Also print each file's size, bundle and path hash
+        #[arg(long)]
+        long: bool,
+
+        /// Print each file's metadata as a line of JSON
+        #[arg(long, conflicts_with = "long")]
+        json: bool,
+
         /// Glob patterns to filter the list of files
         #[clap(default_value = "**")]
         #[arg(num_args = 1..)]
@@ -44,7 +110,8 @@ enum Command {
         /// Path to the file to extract
         path: String,
     },
-    /// Converts datc64 files by applying community-curated schemas
+    /// Converts data tables (.datc64, .dat, .dat64, .datl, .datl64) by applying community-curated
+    /// schemas
     DumpTables {
         /// Path to write out the parsed tables to
         output_folder: PathBuf,
@@ -57,11 +124,25 @@ enum Command {
         #[arg(long)]
         schema: Option<PathBuf>,
 
+        /// Merge the localized copies of each table into it, with a column per language for
+        /// translated columns, eg. `--languages en,fr`. Takes language codes or names
+        #[arg(long, value_delimiter = ',')]
+        languages: Vec<Language>,
+
         /// Glob patterns to filter the list of files
         #[clap(default_value = "**/*.datc64")]
         #[arg(num_args = 1..)]
         globs: Vec<Pattern>,
     },
+    /// Checks a data table against its schema, suggesting types for columns the schema is missing
+    InspectTable {
+        /// Path to the table
+        path: String,
+
+        /// Specifify a local schema rather than fetching from github
+        #[arg(long)]
+        schema: Option<PathBuf>,
+    },
     DumpArt {
         /// Path to the folder to output the extracted files
         output_folder: PathBuf,
@@ -70,6 +151,24 @@ enum Command {
         #[arg(num_args = 1..)]
         globs: Vec<Pattern>,
     },
+    /// Converts meshes (.sm, .smd, .fmt, .tgm) and skeletons (.ast) to binary glTF (.glb)
+    DumpMeshes {
+        /// Path to the folder to output the converted meshes
+        output_folder: PathBuf,
+
+        /// Level of detail to export, 0 being the highest quality
+        #[arg(long, default_value_t = 0)]
+        lod: usize,
+
+        /// Include the skeleton's animations when exporting skinned meshes (.sm)
+        #[arg(long)]
+        animations: bool,
+
+        /// Glob patterns to filter the list of files
+        #[clap(default_values = ["**/*.sm", "**/*.fmt", "**/*.tgm"])]
+        #[arg(num_args = 1..)]
+        globs: Vec<Pattern>,
+    },
     /// Extracts the passive trees as JSON
     DumpTrees {
         output_folder: PathBuf,
@@ -79,6 +178,57 @@ enum Command {
         #[arg(num_args = 1..)]
         globs: Vec<Pattern>,
     },
+    /// Lists files added, removed or modified between two patches or sources
+    Diff {
+        /// Older source: a CDN patch (eg. 1, 2 or 3.25.1.2), or steam:<folder>, ggpk:<file> or
+        /// dir:<folder>
+        #[arg(long)]
+        from: DiffSource,
+
+        /// Newer source, in the same form as --from
+        #[arg(long)]
+        to: DiffSource,
+
+        /// Print the changes as JSON
+        #[arg(long)]
+        json: bool,
+
+        /// Glob patterns to filter the list of files
+        #[clap(default_value = "**")]
+        #[arg(num_args = 1..)]
+        globs: Vec<Pattern>,
+    },
+    /// Compares the rows of data tables between two patches or sources, matching rows by key
+    DiffTables {
+        /// Older source, see the diff command. Sources other than CDN patches use --patch to pick
+        /// the schemas.
+        #[arg(long)]
+        from: DiffSource,
+
+        /// Newer source, in the same form as --from
+        #[arg(long)]
+        to: DiffSource,
+
+        /// The format to output changes as
+        #[arg(long, value_enum, default_value_t = DiffTablesMode::Markdown)]
+        mode: DiffTablesMode,
+
+        /// Specify a local schema rather than fetching from github
+        #[arg(long)]
+        schema: Option<PathBuf>,
+
+        /// Glob patterns to filter the list of tables
+        #[clap(default_value = "**/*.datc64")]
+        #[arg(num_args = 1..)]
+        globs: Vec<Pattern>,
+    },
+    /// Checks the integrity of a GGPK file against its stored SHA-256 digests
+    Verify,
+    /// Inspects or cleans up the CDN download cache
+    Cache {
+        #[command(subcommand)]
+        action: CacheAction,
+    },
     /// Extracts files into more accessible formats
     Translate {
         output_folder: PathBuf,
@@ -90,6 +240,23 @@ enum Command {
     },
 }
 
+#[derive(Debug, Subcommand)]
+enum CacheAction {
+    /// List cached patches, their sizes, and how much of each is shared with other patches
+    List,
+    /// Remove old patches, keeping the newest ones of each CDN
+    Prune {
+        /// Number of patches to keep for each CDN. At least one is always kept, so newer patches
+        /// can reuse its unchanged bundles.
+        #[arg(long, default_value_t = 1)]
+        keep: usize,
+
+        /// Only report what would be removed
+        #[arg(long)]
+        dry_run: bool,
+    },
+}
+
 /// A simple CLI tool that extracts the virtual filenames from PoE data files.
 /// File paths are printed to stdout.
 #[derive(Parser, Debug)]
@@ -97,29 +264,45 @@ enum Command {
     name = "poe_data_tools",
     group(
         ArgGroup::new("source")
-        .args(&["steam", "cache_dir", "ggpk"])
+        .args(&["steam", "cache_dir", "ggpk", "dir"])
         .required(false) // At least one is not required, but they are mutually exclusive
         .multiple(false) // Only one can be used at a time
     )
 )]
 #[clap(version)]
 struct Cli {
-    /// Specify the patch version (1, 2, or specific_patch)
-    #[arg(short, long, required = true)]
-    patch: Patch,
+    /// Specify the patch version (1, 2, or specific_patch). Not needed for the cache and diff
+    /// commands, or for diff-tables between two CDN patches.
+    #[arg(short, long)]
+    patch: Option<Patch>,
 
     /// Specify the Steam folder path (optional)
     #[arg(long)]
     steam: Option<PathBuf>,
 
-    /// Specify the standalone .ggpk path (optional)
+    /// Specify the standalone .ggpk path (optional). Archives from before bundles were introduced
+    /// (pre-3.11) are also supported.
     #[arg(long)]
     ggpk: Option<PathBuf>,
 
+    /// Specify a folder of previously extracted files (optional)
+    #[arg(long)]
+    dir: Option<PathBuf>,
+
+    /// Layer a folder of loose files over the source, replacing files with the same path. Can be
+    /// repeated, with later folders taking precedence (optional)
+    #[arg(long)]
+    overlay: Vec<PathBuf>,
+
     /// Specify the cache directory (optional)
     #[arg(long)]
     cache_dir: Option<PathBuf>,
 
+    /// Only use bundles already in the cache, never contacting the CDN. Bundles missing from the
+    /// patch's cache are taken from other cached patches.
+    #[arg(long)]
+    offline: bool,
+
     /// Verbose printing of non-fatal error messages
     #[arg(short, long, default_value_t = false)]
     verbose: bool,
@@ -133,14 +316,17 @@ enum Source {
     Cdn { cache_dir: PathBuf },
     Steam { steam_folder: PathBuf },
     Ggpk { ggpk_path: PathBuf },
+    Dir { dir: PathBuf },
 }
 
 #[derive(Debug)]
 struct Args {
     patch: Patch,
     source: Source,
+    overlays: Vec<PathBuf>,
     command: Command,
     cache_dir: PathBuf,
+    offline: bool,
     verbose: bool,
 }
 
@@ -152,29 +338,77 @@ fn parse_args() -> Result<Args> {
         .cache_dir
         .unwrap_or_else(|| dirs::cache_dir().unwrap().join("poe_data_tools"));
 
+    let patch = match (cli.patch, &cli.command) {
+        (Some(patch), _) => patch,
+        // Managing the cache covers all patches, and file diffs name their own
+        (None, Command::Cache { .. } | Command::Diff { .. }) => Patch::One,
+        // Table diffs need the patch to pick schemas for sources other than the CDN
+        (
+            None,
+            Command::DiffTables {
+                from: DiffSource::Patch(_),
+                to: DiffSource::Patch(_),
+                ..
+            },
+        ) => Patch::One,
+        (None, Command::DiffTables { .. }) => {
+            bail!("--patch is required for diff-tables unless both sides are CDN patches")
+        }
+        (None, _) => bail!("--patch is required"),
+    };
+
     let source = if let Some(steam_folder) = cli.steam {
         ensure!(steam_folder.exists(), "Steam folder doesn't exist");
         Source::Steam { steam_folder }
     } else if let Some(ggpk_path) = cli.ggpk {
         Source::Ggpk { ggpk_path }
+    } else if let Some(dir) = cli.dir {
+        ensure!(dir.is_dir(), "Folder doesn't exist");
+        Source::Dir { dir }
     } else {
         Source::Cdn {
             cache_dir: cache_dir.clone(),
         }
     };
 
-    if matches!(source, Source::Steam { .. } | Source::Ggpk { .. }) {
+    if matches!(
+        source,
+        Source::Steam { .. } | Source::Ggpk { .. } | Source::Dir { .. }
+    ) {
         ensure!(
-            !matches!(cli.patch, Patch::Specific { .. }),
-            "When using steam or ggpk, specific patch versions are not supported."
+            !matches!(patch, Patch::Specific { .. }),
+            "When using steam, ggpk or dir, specific patch versions are not supported."
+        );
+    }
+
+    ensure!(
+        !cli.offline || matches!(source, Source::Cdn { .. }),
+        "Offline mode only applies to the CDN"
+    );
+
+    ensure!(
+        cli.overlay.is_empty()
+            || !matches!(
+                cli.command,
+                Command::Diff { .. } | Command::DiffTables { .. }
+            ),
+        "--overlay isn't supported when diffing, use dir:<folder> to compare against loose files"
+    );
+
+    for overlay in &cli.overlay {
+        ensure!(
+            overlay.is_dir(),
+            "Overlay folder doesn't exist: {overlay:?}"
         );
     }
 
     Ok(Args {
-        patch: cli.patch,
+        patch,
         source,
+        overlays: cli.overlay,
         command: cli.command,
         cache_dir,
+        offline: cli.offline,
         verbose: cli.verbose,
     })
 }
@@ -186,28 +420,149 @@ fn init_logger() {
     .init();
 }
 
-fn main() -> Result<()> {
-    init_logger();
+/// Periodically log batch download progress, so long first-time extracts aren't silent
+fn progress_logger() -> impl Fn(&BatchProgress) + Send + Sync {
+    const INTERVAL: Duration = Duration::from_secs(5);
+    let last_report = Mutex::new(Instant::now());
 
-    let args = parse_args()?;
-    VERBOSE.set(args.verbose).unwrap();
+    move |progress| {
+        let mut last_report = last_report.lock().unwrap();
+        let finished = progress.is_finished() && progress.elapsed >= INTERVAL;
+        if last_report.elapsed() < INTERVAL && !finished {
+            return;
+        }
+        *last_report = Instant::now();
+
+        let eta = progress
+            .eta()
+            .filter(|_| !progress.is_finished())
+            .map(|eta| format!(", ~{}s remaining", eta.as_secs()))
+            .unwrap_or_default();
+        log::info!(
+            "Processed {}/{} bundles ({:.1}%){eta}",
+            progress.bundles_done,
+            progress.bundles_total,
+            progress.fraction() * 100.
+        );
+    }
+}
 
-    let mut fs = match args.source {
+/// Initialise the file system over a source, without any overlays
+fn open_source(source: Source, patch: &Patch, offline: bool) -> Result<FS> {
+    let fs = match source {
         Source::Cdn { cache_dir } => {
-            let version_string = match &args.patch {
-                Patch::One => "1",
-                Patch::Two => "2",
-                Patch::Specific(v) => v,
+            let fs = if offline {
+                let base_url = cached_cdn_base_url(&cache_dir, patch.as_str())?;
+                CDNFS::new_offline(&base_url, &cache_dir)
+            } else {
+                CDNFS::new(&cdn_base_url(&cache_dir, patch.as_str())?, &cache_dir)
             };
-            FS::from_cdn(&cdn_base_url(&cache_dir, version_string)?, &cache_dir)
+            fs.map(|fs| FS::CDN(fs.with_progress_callback(progress_logger())))
         }
         Source::Steam { steam_folder } => FS::from_steam(steam_folder),
         Source::Ggpk { ggpk_path } => FS::from_ggpk(&ggpk_path),
+        Source::Dir { dir } => FS::from_dir(&dir),
+    }?;
+
+    Ok(fs)
+}
+
+/// Initialise the file system for one side of a diff, along with the patch it's for
+fn open_diff_source(source: DiffSource, args: &Args) -> Result<(FS, Patch)> {
+    let (source, patch) = match source {
+        DiffSource::Patch(patch) => {
+            let cache_dir = args.cache_dir.clone();
+            (Source::Cdn { cache_dir }, patch)
+        }
+        DiffSource::Steam(steam_folder) => (Source::Steam { steam_folder }, args.patch.clone()),
+        DiffSource::Ggpk(ggpk_path) => (Source::Ggpk { ggpk_path }, args.patch.clone()),
+        DiffSource::Dir(dir) => (Source::Dir { dir }, args.patch.clone()),
+    };
+
+    let fs = open_source(source, &patch, args.offline)?;
+    Ok((fs, patch))
+}
+
+fn main() -> Result<()> {
+    init_logger();
+
+    let args = parse_args()?;
+    VERBOSE.set(args.verbose).unwrap();
+
+    // Doesn't need a file system
+    if let Command::Cache { action } = &args.command {
+        match action {
+            CacheAction::List => list_cache(&args.cache_dir).context("Cache command failed")?,
+            CacheAction::Prune { keep, dry_run } => {
+                prune_cache(&args.cache_dir, *keep, *dry_run).context("Cache command failed")?
+            }
+        }
+        return Ok(());
+    }
+
+    // Opens its own pair of file systems
+    if let Command::Diff {
+        from,
+        to,
+        json,
+        globs,
+    } = &args.command
+    {
+        let (from, _) = open_diff_source(from.clone(), &args)
+            .context("Failed to initialise --from file system")?;
+        let (to, _) =
+            open_diff_source(to.clone(), &args).context("Failed to initialise --to file system")?;
+        let format = if *json {
+            DiffFormat::Json
+        } else {
+            DiffFormat::Text
+        };
+        diff_files(&from, &to, globs, format).context("Diff command failed")?;
+        return Ok(());
+    }
+    if let Command::DiffTables {
+        from,
+        to,
+        mode,
+        schema,
+        globs,
+    } = &args.command
+    {
+        let (mut from, from_version) = open_diff_source(from.clone(), &args)
+            .context("Failed to initialise --from file system")?;
+        let (mut to, to_version) =
+            open_diff_source(to.clone(), &args).context("Failed to initialise --to file system")?;
+        let format = match mode {
+            DiffTablesMode::Json => TableDiffFormat::Json,
+            DiffTablesMode::Markdown => TableDiffFormat::Markdown,
+        };
+        diff_tables(
+            &mut from,
+            &mut to,
+            &from_version,
+            &to_version,
+            globs,
+            &args.cache_dir,
+            schema.as_ref(),
+            format,
+        )
+        .context("Diff Tables command failed")?;
+        return Ok(());
     }
-    .context("Failed to initialise file system")?;
+
+    let mut fs = open_source(args.source, &args.patch, args.offline)
+        .and_then(|fs| Ok(fs.with_overlays(&args.overlays)?))
+        .context("Failed to initialise file system")?;
 
     match args.command {
-        Command::List { globs } => list_files(&fs, &globs).context("List command failed")?,
+        Command::List { globs, long, json } => {
+            let format = match (long, json) {
+                (true, _) => ListFormat::Long,
+                (_, true) => ListFormat::Json,
+                _ => ListFormat::Paths,
+            };
+            list_files(&fs, &globs, format).context("List command failed")?
+        }
         Command::Cat { path } => cat_file(&mut fs, &path).context("Cat command failed")?,
         Command::Extract {
             globs,
@@ -218,31 +573,80 @@ fn main() -> Result<()> {
             globs,
             mode,
             schema,
-        } => match mode {
-            DumpDatsMode::Csv => dump_tables(
-                &mut fs,
-                &globs,
-                &args.cache_dir,
-                &output_folder,
-                &args.patch,
-                schema.as_ref(),
-            )
-            .context("Dump Tables command failed")?,
+            languages,
+        } => {
+            ensure!(
+                languages.is_empty() || !matches!(mode, DumpDatsMode::Json | DumpDatsMode::Sqlite),
+                "--languages is only supported by the csv, parquet and arrow modes."
+            );
 
-            DumpDatsMode::Json => dump_tables_json::dump_tables(
-                &mut fs,
-                &globs,
-                &args.cache_dir,
-                &output_folder,
-                &args.patch,
-                schema.as_ref(),
-            )
-            .context("Dump Tables command failed")?,
-        },
+            match mode {
+                DumpDatsMode::Csv => dump_tables(
+                    &mut fs,
+                    &globs,
+                    &args.cache_dir,
+                    &output_folder,
+                    &args.patch,
+                    schema.as_ref(),
+                    &languages,
+                )
+                .context("Dump Tables command failed")?,
+
+                DumpDatsMode::Json => dump_tables_json::dump_tables(
+                    &mut fs,
+                    &globs,
+                    &args.cache_dir,
+                    &output_folder,
+                    &args.patch,
+                    schema.as_ref(),
+                )
+                .context("Dump Tables command failed")?,
+
+                DumpDatsMode::Parquet | DumpDatsMode::Arrow => dump_tables_arrow::dump_tables(
+                    &mut fs,
+                    &globs,
+                    &args.cache_dir,
+                    &output_folder,
+                    &args.patch,
+                    schema.as_ref(),
+                    match mode {
+                        DumpDatsMode::Parquet => TableFormat::Parquet,
+                        _ => TableFormat::Arrow,
+                    },
+                    &languages,
+                )
+                .context("Dump Tables command failed")?,
+
+                DumpDatsMode::Sqlite => dump_tables_sqlite::dump_tables(
+                    &mut fs,
+                    &globs,
+                    &args.cache_dir,
+                    &output_folder,
+                    &args.patch,
+                    schema.as_ref(),
+                )
+                .context("Dump Tables command failed")?,
+            }
+        }
+        Command::InspectTable { path, schema } => inspect_table(
+            &mut fs,
+            &path,
+            &args.cache_dir,
+            &args.patch,
+            schema.as_ref(),
+        )
+        .context("Inspect Table command failed")?,
         Command::DumpArt {
             output_folder,
             globs,
         } => extract_art(&mut fs, &globs, &output_folder).context("Dump Art command failed")?,
+        Command::DumpMeshes {
+            output_folder,
+            lod,
+            animations,
+            globs,
+        } => dump_meshes(&mut fs, &globs, &output_folder, lod, animations)
+            .context("Dump Meshes command failed")?,
         Command::DumpTrees {
             output_folder,
             globs,
@@ -269,6 +673,10 @@ fn main() -> Result<()> {
             )
             .context("Translate command failed")?;
         }
+        Command::Verify => verify(&fs).context("Verify command failed")?,
+        Command::Cache { .. } | Command::Diff { .. } | Command::DiffTables { .. } => {
+            unreachable!("handled before the file system is initialised")
+        }
     }
 
     Ok(())
@@ -1,12 +1,31 @@
+use std::{
+    collections::VecDeque,
+    io::{self, Read, Seek, SeekFrom},
+};
+
 use bytes::Bytes;
 use oozextract::Extractor;
 use rayon::iter::{IndexedParallelIterator, IntoParallelIterator, ParallelIterator};
 
 use crate::{
-    file_parsers::{bundle::types::BundleFile, error::ParseError},
+    file_parsers::{
+        bundle::{
+            parser::{parse_bundle_head, parse_bundle_header},
+            types::{BundleFile, HeadPayload},
+            writer::HEAD_PAYLOAD_SIZE,
+        },
+        bundle_index::types::FileInfo,
+        error::ParseError,
+    },
     fs::error::FSError,
 };
 
+/// Number of decoded blocks kept by a [BundleReader], enough for reads straddling a block boundary
+const CACHED_BLOCKS: usize = 2;
+
+/// Size of a bundle's header, up to the block sizes
+const HEADER_SIZE: usize = 12 + HEAD_PAYLOAD_SIZE as usize;
+
 impl BundleFile {
     /// Return the entire content of the bundle
     pub fn read_all(&self) -> Result<Bytes, FSError> {
@@ -41,4 +60,193 @@ impl BundleFile {
         let slice = Bytes::from(buf).slice(offset % block_size..offset % block_size + len);
         Ok(slice)
     }
+
+    /// Decode a single block
+    pub(crate) fn read_block(&self, index: usize) -> Result<Vec<u8>, FSError> {
+        let block = self.blocks.get(index).ok_or(FSError::BlockOutOfRange {
+            index,
+            count: self.blocks.len(),
+        })?;
+
+        decode_block(&self.head, index, block)
+    }
+}
+
+/// Decode a block of a bundle into its uncompressed contents
+fn decode_block(head: &HeadPayload, index: usize, block: &[u8]) -> Result<Vec<u8>, FSError> {
+    let block_size = head.uncompressed_block_granularity as usize;
+    let start = index * block_size;
+    let len = block_size.min((head.uncompressed_size as usize).saturating_sub(start));
+
+    let mut buf = vec![0; len];
+    Extractor::new()
+        .read_from_slice(block, &mut buf)
+        .map_err(ParseError::other)?;
+
+    Ok(buf)
+}
+
+/// A bundle's header and where each of its compressed blocks is stored, so blocks can be read on
+/// demand rather than loading the whole bundle
+struct BlockTable {
+    head: HeadPayload,
+    /// Offset from the start of the bundle and compressed size of each block
+    blocks: Vec<(u64, usize)>,
+}
+
+impl BlockTable {
+    /// Read the header from the start of a bundle
+    fn read(source: &mut impl Read) -> Result<Self, FSError> {
+        let mut bytes = vec![0; HEADER_SIZE];
+        source.read_exact(&mut bytes)?;
+        let head = parse_bundle_header(&bytes)?;
+
+        bytes.resize(HEADER_SIZE + 4 * head.block_count as usize, 0);
+        source.read_exact(&mut bytes[HEADER_SIZE..])?;
+        let (head, block_sizes) = parse_bundle_head(&bytes)?;
+
+        // Blocks are stored back to back after the header
+        let blocks = block_sizes
+            .iter()
+            .scan(bytes.len() as u64, |offset, &size| {
+                let block = (*offset, size as usize);
+                *offset += size as u64;
+                Some(block)
+            })
+            .collect();
+
+        Ok(Self { head, blocks })
+    }
+}
+
+/// Streams a single file out of a bundle, only reading and decoding blocks once the cursor reaches
+/// them
+pub(crate) struct BundleReader<R> {
+    /// The bundle's on-disk contents
+    source: R,
+    table: BlockTable,
+    /// Start of the file within the bundle's decoded contents
+    offset: u64,
+    len: u64,
+    /// Cursor position, relative to the start of the file
+    pos: u64,
+    /// Recently decoded blocks and their indices, most recently used first
+    cache: VecDeque<(usize, Vec<u8>)>,
+}
+
+impl<R: Read + Seek> BundleReader<R> {
+    /// Read a file out of a bundle, only the bundle's header is read up front
+    pub(crate) fn new(mut source: R, file: &FileInfo) -> Result<Self, FSError> {
+        source.rewind()?;
+        let table = BlockTable::read(&mut source)?;
+
+        Ok(Self {
+            source,
+            table,
+            offset: file.offset as u64,
+            len: file.size as u64,
+            pos: 0,
+            cache: VecDeque::with_capacity(CACHED_BLOCKS),
+        })
+    }
+
+    /// Read and decode a single block
+    fn read_block(&mut self, index: usize) -> Result<Vec<u8>, FSError> {
+        let &(offset, size) = self
+            .table
+            .blocks
+            .get(index)
+            .ok_or(FSError::BlockOutOfRange {
+                index,
+                count: self.table.blocks.len(),
+            })?;
+
+        let mut block = vec![0; size];
+        self.source.seek(SeekFrom::Start(offset))?;
+        self.source.read_exact(&mut block)?;
+
+        decode_block(&self.table.head, index, &block)
+    }
+
+    /// Get a decoded block, from the cache if possible
+    fn block(&mut self, index: usize) -> io::Result<&[u8]> {
+        if let Some(i) = self.cache.iter().position(|(cached, _)| *cached == index) {
+            let block = self.cache.remove(i).unwrap();
+            self.cache.push_front(block);
+        } else {
+            let block = self.read_block(index).map_err(io::Error::other)?;
+            self.cache.truncate(CACHED_BLOCKS - 1);
+            self.cache.push_front((index, block));
+        }
+
+        Ok(&self.cache[0].1)
+    }
+}
+
+impl<R: Read + Seek> Read for BundleReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.pos >= self.len || buf.is_empty() {
+            return Ok(0);
+        }
+
+        let block_size = self.table.head.uncompressed_block_granularity as u64;
+        let position = self.offset + self.pos;
+        let within = (position % block_size) as usize;
+        let remaining = self.len - self.pos;
+
+        let block = self.block((position / block_size) as usize)?;
+        let n = buf.len().min(block.len() - within).min(remaining as usize);
+        buf[..n].copy_from_slice(&block[within..within + n]);
+
+        self.pos += n as u64;
+        Ok(n)
+    }
+}
+
+impl<R> Seek for BundleReader<R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let pos = match pos {
+            SeekFrom::Start(pos) => Some(pos),
+            SeekFrom::End(delta) => self.len.checked_add_signed(delta),
+            SeekFrom::Current(delta) => self.pos.checked_add_signed(delta),
+        }
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "invalid seek to a negative or overflowing position",
+            )
+        })?;
+
+        self.pos = pos;
+        Ok(pos)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::file_parsers::bundle::writer::BLOCK_SIZE;
+
+    #[test]
+    fn reader() {
+        let data = (0..3 * BLOCK_SIZE).map(|i| i as u8).collect::<Vec<_>>();
+        let bundle = BundleFile::from_uncompressed(&data);
+        let file = FileInfo::new("", 0, BLOCK_SIZE as u32 - 8, BLOCK_SIZE as u32);
+
+        let mut reader = BundleReader::new(Cursor::new(bundle.to_bytes()), &file).unwrap();
+        let mut contents = vec![];
+        reader.read_to_end(&mut contents).unwrap();
+        assert_eq!(contents, data[BLOCK_SIZE - 8..2 * BLOCK_SIZE - 8]);
+
+        assert!(matches!(
+            reader.read_block(3),
+            Err(FSError::BlockOutOfRange { index: 3, count: 3 })
+        ));
+        assert!(matches!(
+            bundle.read_block(3),
+            Err(FSError::BlockOutOfRange { index: 3, count: 3 })
+        ));
+    }
 }
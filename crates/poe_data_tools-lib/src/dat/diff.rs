@@ -0,0 +1,267 @@
+//! Row-level comparison of a table between two versions of the game files
+use arrow_array::{Array, ArrayRef};
+use arrow_cast::display::{ArrayFormatter, FormatOptions};
+use serde::Serialize;
+
+use super::{
+    graph::{KeyIndex, Resolve, TableGraph},
+    table_view::DatResult,
+};
+use crate::fs::FileSystem;
+
+/// A value that differs between the two versions of a row. Values are formatted as text, with
+/// references replaced by the key of the row they point to.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ColumnChange {
+    pub column: String,
+    pub from: Option<String>,
+    pub to: Option<String>,
+}
+
+/// A row present in both versions, with the columns that changed
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct RowChange {
+    pub key: String,
+    pub changes: Vec<ColumnChange>,
+}
+
+/// Signs that the schema fits one version of the table better than the other, so some of the
+/// changes reported may just be misaligned columns
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum SchemaDrift {
+    /// Rows are a different width in each version
+    RowWidth {
+        from: usize,
+        to: usize,
+        /// Row width the schema describes, if all column types are known
+        schema: Option<usize>,
+    },
+    /// A column could only be parsed in one of the versions
+    MissingColumn { column: String },
+}
+
+/// Differences between two versions of a table, with rows matched by their key. See
+/// [KeyIndex] for how keys are built.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct TableDiff {
+    pub table: String,
+    /// Keys of rows only in the newer version
+    pub added: Vec<String>,
+    /// Keys of rows only in the older version
+    pub removed: Vec<String>,
+    pub modified: Vec<RowChange>,
+    pub unchanged: usize,
+    /// Rows that couldn't be matched up as they have no key, or share it with an earlier row
+    pub unmatched: usize,
+    pub drift: Vec<SchemaDrift>,
+}
+
+impl TableDiff {
+    /// Whether any rows were added, removed or modified
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.modified.is_empty()
+    }
+}
+
+/// Rows that can be matched by key, ie. the first row with each key
+fn keyed_rows(keys: &KeyIndex) -> impl Iterator<Item = (&str, usize)> {
+    (0..keys.len()).filter_map(|row| {
+        let key = keys.key(row)?;
+        (keys.row(key) == Some(row)).then_some((key, row))
+    })
+}
+
+/// Value of a cell as text, see [ColumnChange]
+fn cell(array: &ArrayRef, formatter: &ArrayFormatter, row: usize) -> Option<String> {
+    (!array.is_null(row)).then(|| formatter.value(row).to_string())
+}
+
+/// Compare a table between two versions, matching rows by key. Both versions are loaded with
+/// their references resolved to keys, so rows that point to the same thing compare equal even if
+/// the target table was reordered.
+pub fn diff_table<F: FileSystem, G: FileSystem>(
+    from: &mut TableGraph<F>,
+    to: &mut TableGraph<G>,
+    table: &str,
+) -> DatResult<TableDiff> {
+    let mut diff = TableDiff {
+        table: table.to_owned(),
+        ..Default::default()
+    };
+
+    if let (Some(from_width), Some(to_width)) = (from.row_width(table)?, to.row_width(table)?)
+        && from_width != to_width
+    {
+        let layout = to.layout(table);
+        let schema = to
+            .schema(table)
+            .and_then(|s| s.columns.iter().map(|c| c.width_in(layout)).sum());
+        diff.drift.push(SchemaDrift::RowWidth {
+            from: from_width,
+            to: to_width,
+            schema,
+        });
+    }
+
+    let from_df = from.resolve(table, Resolve::Key)?;
+    let to_df = to.resolve(table, Resolve::Key)?;
+    let from_keys = from.keys(table)?;
+    let to_keys = to.keys(table)?;
+
+    // Only columns parsed in both versions can be compared
+    let mut columns = vec![];
+    for (field, from_column) in from_df.schema_ref().fields().iter().zip(from_df.columns()) {
+        match to_df.column_by_name(field.name()) {
+            Some(to_column) => columns.push((field.name(), from_column, to_column)),
+            None => diff.drift.push(SchemaDrift::MissingColumn {
+                column: field.name().clone(),
+            }),
+        }
+    }
+    for field in to_df.schema_ref().fields() {
+        if from_df.column_by_name(field.name()).is_none() {
+            diff.drift.push(SchemaDrift::MissingColumn {
+                column: field.name().clone(),
+            });
+        }
+    }
+
+    let options = FormatOptions::default();
+    let formatters = columns
+        .iter()
+        .map(|(_, from_column, to_column)| -> DatResult<_> {
+            Ok((
+                ArrayFormatter::try_new(from_column.as_ref(), &options)?,
+                ArrayFormatter::try_new(to_column.as_ref(), &options)?,
+            ))
+        })
+        .collect::<DatResult<Vec<_>>>()?;
+
+    let mut matched = 0;
+    for (key, from_row) in keyed_rows(&from_keys) {
+        let Some(to_row) = to_keys.row(key) else {
+            diff.removed.push(key.to_owned());
+            continue;
+        };
+        matched += 1;
+
+        let changes = columns
+            .iter()
+            .zip(&formatters)
+            .filter_map(|((name, from_column, to_column), (from_fmt, to_fmt))| {
+                let from = cell(from_column, from_fmt, from_row);
+                let to = cell(to_column, to_fmt, to_row);
+                (from != to).then(|| ColumnChange {
+                    column: (*name).clone(),
+                    from,
+                    to,
+                })
+            })
+            .collect::<Vec<_>>();
+
+        if changes.is_empty() {
+            diff.unchanged += 1;
+        } else {
+            diff.modified.push(RowChange {
+                key: key.to_owned(),
+                changes,
+            });
+        }
+    }
+
+    for (key, _) in keyed_rows(&to_keys) {
+        if from_keys.row(key).is_none() {
+            diff.added.push(key.to_owned());
+        }
+    }
+
+    diff.unmatched = (from_keys.len() - matched - diff.removed.len())
+        + (to_keys.len() - matched - diff.added.len());
+
+    Ok(diff)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{Value, json};
+
+    use super::*;
+    use crate::{
+        Patch,
+        dat::{
+            schema::{ColumnSchema, DatTableSchema, SchemaCollection},
+            writer::write_json_rows,
+        },
+        fs::memory::MemoryFS,
+    };
+
+    #[test]
+    fn rows() {
+        let schemas = SchemaCollection::new(
+            vec![
+                DatTableSchema::new("Tags", [ColumnSchema::new("Id", "string").with_unique()]),
+                DatTableSchema::new(
+                    "Mods",
+                    [
+                        ColumnSchema::new("Id", "string").with_unique(),
+                        ColumnSchema::new("Level", "i32"),
+                        ColumnSchema::new("Tag", "foreignrow").with_references("Tags"),
+                    ],
+                ),
+            ],
+            vec![],
+        );
+        let mods = &schemas.tables[1];
+        let tags = &schemas.tables[0];
+
+        let fs = |tag_rows: &[Value], mod_rows: &[Value]| {
+            MemoryFS::new()
+                .with_file(
+                    "data/tags.datc64",
+                    write_json_rows(tag_rows, tags).unwrap().to_bytes(),
+                )
+                .with_file(
+                    "data/mods.datc64",
+                    write_json_rows(mod_rows, mods).unwrap().to_bytes(),
+                )
+        };
+        let mut from = fs(
+            &[json!({"Id": "fire"}), json!({"Id": "cold"})],
+            &[
+                json!({"Id": "Kept", "Level": 1, "Tag": 0}),
+                json!({"Id": "Levelled", "Level": 1, "Tag": 1}),
+                json!({"Id": "Gone", "Level": 1, "Tag": null}),
+            ],
+        );
+        // Tags reordered, so the raw references change but the tags they point to don't
+        let mut to = fs(
+            &[json!({"Id": "cold"}), json!({"Id": "fire"})],
+            &[
+                json!({"Id": "New", "Level": 5, "Tag": 0}),
+                json!({"Id": "Levelled", "Level": 3, "Tag": 0}),
+                json!({"Id": "Kept", "Level": 1, "Tag": 1}),
+            ],
+        );
+
+        let mut from = TableGraph::new(&mut from, &schemas, &Patch::One);
+        let mut to = TableGraph::new(&mut to, &schemas, &Patch::One);
+        let diff = diff_table(&mut from, &mut to, "Mods").unwrap();
+
+        assert_eq!(diff.added, ["New"]);
+        assert_eq!(diff.removed, ["Gone"]);
+        assert_eq!(
+            diff.modified,
+            [RowChange {
+                key: "Levelled".to_owned(),
+                changes: vec![ColumnChange {
+                    column: "Level".to_owned(),
+                    from: Some("1".to_owned()),
+                    to: Some("3".to_owned()),
+                }],
+            }]
+        );
+        assert_eq!((diff.unchanged, diff.unmatched), (1, 0));
+        assert!(diff.drift.is_empty());
+    }
+}
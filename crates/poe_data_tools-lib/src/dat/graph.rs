@@ -0,0 +1,540 @@
+//! Lazily loaded tables with references between them resolved
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
+
+use arrow_array::{
+    Array, ArrayRef, ListArray, RecordBatch, StringArray, StructArray, UInt32Array,
+    cast::AsArray,
+    types::{UInt32Type, UInt64Type},
+};
+use arrow_cast::display::{ArrayFormatter, FormatOptions};
+use arrow_schema::{ArrowError, DataType, Field, SchemaBuilder};
+use arrow_select::take::take;
+
+use crate::{
+    Patch,
+    dat::{
+        schema::{DatTableSchema, Enumeration, SchemaCollection},
+        table::FSDatEx,
+        table_view::{DatError, DatResult},
+    },
+    file_parsers::{
+        FileParser,
+        dat::{DatParser, types::DatLayout},
+    },
+    fs::FileSystem,
+};
+
+/// How reference columns are resolved
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Resolve {
+    /// Replace references with the key of the row they point to, as a string
+    Key,
+    /// Replace references with the full row they point to, as a struct
+    Row,
+}
+
+/// Lookup between the rows of a table and their keys.
+///
+/// Keys are taken from the columns marked unique in the schema, falling back to the first column
+/// for tables without any. Key columns that are themselves references use the key of the row they
+/// point to. Composite keys are formatted as a list, eg. `[Key1, Key2]`.
+#[derive(Debug)]
+pub struct KeyIndex {
+    /// Reference value of the first row. Enums may be 1-indexed, tables are always 0-indexed
+    offset: usize,
+    keys: Vec<Option<String>>,
+    rows: HashMap<String, usize>,
+}
+
+impl KeyIndex {
+    fn new(offset: usize, keys: Vec<Option<String>>) -> Self {
+        let mut rows = HashMap::new();
+        keys.iter().enumerate().for_each(|(row, key)| {
+            if let Some(key) = key {
+                // Keep the first row if keys aren't actually unique
+                rows.entry(key.clone()).or_insert(row);
+            }
+        });
+
+        Self { offset, keys, rows }
+    }
+
+    /// Number of rows in the table
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    /// Key of a row, if it has one
+    pub fn key(&self, row: usize) -> Option<&str> {
+        self.keys.get(row)?.as_deref()
+    }
+
+    /// First row with the given key
+    pub fn row(&self, key: &str) -> Option<usize> {
+        self.rows.get(key).copied()
+    }
+
+    /// Row that a reference value points to, if it's in bounds
+    pub fn row_of_ref(&self, value: u64) -> Option<usize> {
+        let row = usize::try_from(value).ok()?.checked_sub(self.offset)?;
+        (row < self.keys.len()).then_some(row)
+    }
+}
+
+/// A set of tables that are loaded on demand, used to follow references between them.
+///
+/// Loaded tables and key indices are cached for the lifetime of the graph.
+pub struct TableGraph<'a, F: FileSystem> {
+    fs: &'a mut F,
+    schemas: &'a SchemaCollection,
+    version: Patch,
+    tables: HashMap<String, Arc<RecordBatch>>,
+    keys: HashMap<String, Arc<KeyIndex>>,
+    /// Tables whose keys are currently being built, used to break reference cycles
+    resolving: HashSet<String>,
+}
+
+impl<'a, F: FileSystem> TableGraph<'a, F> {
+    pub fn new(fs: &'a mut F, schemas: &'a SchemaCollection, version: &Patch) -> Self {
+        Self {
+            fs,
+            schemas,
+            version: version.clone(),
+            tables: HashMap::new(),
+            keys: HashMap::new(),
+            resolving: HashSet::new(),
+        }
+    }
+
+    /// Virtual path of a table in the game files, using the newest of the variants that exist
+    fn path(&self, name: &str) -> String {
+        let folder = match self.version.major() {
+            1 => "data",
+            2 => "data/balance",
+            v => unreachable!("unknown version: {v}"),
+        };
+
+        DatLayout::EXTENSIONS
+            .iter()
+            .map(|extension| format!("{folder}/{name}.{extension}"))
+            .find(|path| self.fs.exists(path))
+            .unwrap_or_else(|| format!("{folder}/{name}.datc64"))
+    }
+
+    /// Layout of the rows of a table, which depends on the variant it's stored as
+    pub fn layout(&self, name: &str) -> DatLayout {
+        DatLayout::from_path(self.path(&name.to_lowercase())).unwrap_or_default()
+    }
+
+    fn is_current(&self, valid_for: u32) -> bool {
+        valid_for == self.version.major() || valid_for == 3
+    }
+
+    /// Schema of a table, if it's a regular table rather than an enum
+    pub fn schema(&self, name: &str) -> Option<&'a DatTableSchema> {
+        self.schemas
+            .tables
+            .iter()
+            .filter(|t| self.is_current(t.valid_for))
+            .find(|t| t.name.eq_ignore_ascii_case(name))
+    }
+
+    fn enumeration(&self, name: &str) -> Option<&'a Enumeration> {
+        self.schemas
+            .enumerations
+            .iter()
+            .filter(|e| self.is_current(e.valid_for))
+            .find(|e| e.name.eq_ignore_ascii_case(name))
+    }
+
+    /// Load a table, or get it from the cache if it's already been loaded
+    pub fn table(&mut self, name: &str) -> DatResult<Arc<RecordBatch>> {
+        let name = name.to_lowercase();
+        if let Some(table) = self.tables.get(&name) {
+            return Ok(table.clone());
+        }
+
+        let path = self.path(&name);
+        let table = Arc::new(self.fs.load_dat_table(self.schemas, &path, &self.version)?);
+        log::debug!("Loaded table: {name}");
+
+        self.tables.insert(name, table.clone());
+        Ok(table)
+    }
+
+    /// Width in bytes of each row of a table, or [None] for enum tables which only exist in the
+    /// schema
+    pub fn row_width(&mut self, name: &str) -> DatResult<Option<usize>> {
+        if self.schema(name).is_none() {
+            return Ok(None);
+        }
+
+        let bytes = self.fs.read(&self.path(&name.to_lowercase()))?;
+        Ok(Some(DatParser.parse(&bytes)?.width()))
+    }
+
+    /// Get the key index of a table, loading it if needed
+    pub fn keys(&mut self, name: &str) -> DatResult<Arc<KeyIndex>> {
+        let name = name.to_lowercase();
+        if let Some(keys) = self.keys.get(&name) {
+            return Ok(keys.clone());
+        }
+
+        let keys = Arc::new(self.build_keys(&name)?);
+        self.keys.insert(name, keys.clone());
+        Ok(keys)
+    }
+
+    fn build_keys(&mut self, name: &str) -> DatResult<KeyIndex> {
+        let table = self.table(name)?;
+
+        // Enum tables are keyed by their variant names
+        let Some(schema) = self.schema(name) else {
+            let offset = self.enumeration(name).map(|e| e.indexing).unwrap_or(0);
+            let names = table.column(1).as_string::<i32>();
+            let keys = names.iter().map(|n| n.map(ToOwned::to_owned)).collect();
+
+            return Ok(KeyIndex::new(offset, keys));
+        };
+
+        let mut key_columns = schema.primary_keys().collect::<Vec<_>>();
+        if key_columns.is_empty()
+            && let Some(name) = schema.column_names().next()
+        {
+            // Fall back to first column as key for tables without any key
+            key_columns.push(name);
+        }
+
+        self.resolving.insert(name.to_owned());
+        let columns = key_columns
+            .iter()
+            .filter_map(|column| {
+                let Some(array) = table.column_by_name(column) else {
+                    log::warn!("Key column {column:?} is missing from table {name:?}");
+                    return None;
+                };
+
+                // Key columns that are references are keyed by their target's key
+                let target = schema
+                    .enumerate()
+                    .find(|(n, _)| n == column)
+                    .filter(|(_, c)| c.is_ref())
+                    .and_then(|(_, c)| c.get_ref());
+                let array = match target {
+                    Some(target) if !self.resolving.contains(&target.to_lowercase()) => self
+                        .resolve_array(array, target, Resolve::Key)
+                        .unwrap_or_else(|e| {
+                            log::debug!("Failed to resolve key column {column:?}: {e}");
+                            array.clone()
+                        }),
+                    _ => array.clone(),
+                };
+
+                Some(array)
+            })
+            .collect::<Vec<_>>();
+        self.resolving.remove(name);
+
+        let options = FormatOptions::default();
+        let formatters = columns
+            .iter()
+            .map(|c| ArrayFormatter::try_new(c.as_ref(), &options))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let keys = (0..table.num_rows())
+            .map(|row| {
+                if columns.iter().all(|c| c.is_null(row)) {
+                    return None;
+                }
+
+                let values = formatters
+                    .iter()
+                    .map(|f| f.value(row).to_string())
+                    .collect::<Vec<_>>();
+
+                Some(match values.as_slice() {
+                    [value] => value.clone(),
+                    values => format!("[{}]", values.join(", ")),
+                })
+            })
+            .collect();
+
+        Ok(KeyIndex::new(0, keys))
+    }
+
+    /// Resolve a single reference column of a table
+    pub fn resolve_column(
+        &mut self,
+        table: &str,
+        column: &str,
+        resolve: Resolve,
+    ) -> DatResult<ArrayRef> {
+        let schema = self
+            .schema(table)
+            .ok_or_else(|| DatError::SchemaNotFound(table.to_owned()))?;
+
+        let target = schema
+            .enumerate()
+            .find(|(name, _)| name == column)
+            .ok_or_else(|| DatError::MissingColumn(column.to_owned()))?
+            .1
+            .get_ref()
+            .ok_or_else(|| DatError::NotReference(column.to_owned()))?;
+
+        let df = self.table(table)?;
+        let array = df
+            .column_by_name(column)
+            .ok_or_else(|| DatError::MissingColumn(column.to_owned()))?;
+
+        self.resolve_array(array, target, resolve)
+    }
+
+    /// Load a table with all of its reference columns resolved. Columns that can't be resolved,
+    /// eg. when the target table is missing, are left as row indices.
+    pub fn resolve(&mut self, table: &str, resolve: Resolve) -> DatResult<RecordBatch> {
+        let df = self.table(table)?;
+        let Some(schema) = self.schema(table) else {
+            // Enum tables don't have any references
+            return Ok((*df).clone());
+        };
+
+        let (arrow_schema, mut columns, _) = (*df).clone().into_parts();
+        let mut schema_builder = SchemaBuilder::from(&*arrow_schema);
+
+        for (name, column) in schema.enumerate() {
+            let (Some(target), Ok(i)) = (column.get_ref(), arrow_schema.index_of(&name)) else {
+                continue;
+            };
+
+            match self.resolve_array(&columns[i], target, resolve) {
+                Ok(array) => {
+                    let field = (**schema_builder.field(i))
+                        .clone()
+                        .with_data_type(array.data_type().clone())
+                        .with_nullable(true);
+                    *schema_builder.field_mut(i) = Arc::new(field);
+                    columns[i] = array;
+                }
+                Err(e) => log::warn!("Failed to resolve column {name:?} -> {target:?}: {e}"),
+            }
+        }
+
+        let df = RecordBatch::try_new(Arc::new(schema_builder.finish()), columns)?;
+        Ok(df)
+    }
+
+    /// Resolve an array of reference values against a target table
+    fn resolve_array(
+        &mut self,
+        array: &ArrayRef,
+        target: &str,
+        resolve: Resolve,
+    ) -> DatResult<ArrayRef> {
+        if let DataType::List(_) = array.data_type() {
+            let list = array.as_list::<i32>();
+            let values = self.resolve_array(list.values(), target, resolve)?;
+
+            let field = Field::new_list_field(values.data_type().clone(), true);
+            let list = ListArray::try_new(
+                Arc::new(field),
+                list.offsets().clone(),
+                values,
+                list.nulls().cloned(),
+            )?;
+            return Ok(Arc::new(list));
+        }
+
+        let keys = self.keys(target)?;
+        let rows = match array.data_type() {
+            DataType::UInt64 => array
+                .as_primitive::<UInt64Type>()
+                .iter()
+                .map(|v| v.and_then(|v| keys.row_of_ref(v)).map(|r| r as u32))
+                .collect::<UInt32Array>(),
+            DataType::UInt32 => array
+                .as_primitive::<UInt32Type>()
+                .iter()
+                .map(|v| v.and_then(|v| keys.row_of_ref(v as u64)).map(|r| r as u32))
+                .collect::<UInt32Array>(),
+            data_type => {
+                return Err(ArrowError::InvalidArgumentError(format!(
+                    "unexpected data type for references: {data_type}"
+                ))
+                .into());
+            }
+        };
+
+        let bad_refs = rows.null_count() - array.null_count();
+        if bad_refs > 0 {
+            log::debug!("{bad_refs} references to {target:?} are out of bounds");
+        }
+
+        let resolved = match resolve {
+            Resolve::Key => Arc::new(
+                rows.iter()
+                    .map(|r| r.and_then(|r| keys.key(r as usize)))
+                    .collect::<StringArray>(),
+            ) as ArrayRef,
+            Resolve::Row => {
+                let table = self.table(target)?;
+                let rows_array = StructArray::from((*table).clone());
+                take(&rows_array, &rows, None)?
+            }
+        };
+
+        Ok(resolved)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::{
+        dat::{
+            schema::{ColumnSchema, Enumeration},
+            writer::write_json_rows,
+        },
+        fs::memory::MemoryFS,
+    };
+
+    #[test]
+    fn key_index() {
+        let keys = KeyIndex::new(0, vec![Some("Sword".to_owned()), None]);
+        assert_eq!(keys.key(0), Some("Sword"));
+        assert_eq!(keys.key(1), None);
+        assert_eq!(keys.row("Sword"), Some(0));
+        assert_eq!(keys.row_of_ref(1), Some(1));
+        assert_eq!(keys.row_of_ref(2), None);
+
+        // 1-indexed enum
+        let keys = KeyIndex::new(1, vec![Some("A".to_owned()), Some("B".to_owned())]);
+        assert_eq!(keys.row_of_ref(0), None);
+        assert_eq!(keys.row_of_ref(2), Some(1));
+    }
+
+    #[test]
+    fn variants() {
+        let schemas = SchemaCollection::new(vec![], vec![]);
+        let mut fs = MemoryFS::new()
+            .with_file("data/mods.dat", vec![])
+            .with_file("data/mods.datl64", vec![])
+            .with_file("data/balance/tags.datl", vec![]);
+
+        let graph = TableGraph::new(&mut fs, &schemas, &Patch::One);
+        assert_eq!(graph.layout("Mods"), DatLayout::DAT);
+        // Missing tables are assumed to be the current variant
+        assert_eq!(graph.layout("Tags"), DatLayout::DATC64);
+
+        let graph = TableGraph::new(&mut fs, &schemas, &Patch::Two);
+        assert_eq!(graph.layout("Tags"), DatLayout::DATL);
+    }
+
+    #[test]
+    fn resolve() {
+        let tags = DatTableSchema::new("Tags", [ColumnSchema::new("Id", "string").with_unique()]);
+        let mods = DatTableSchema::new(
+            "Mods",
+            [
+                ColumnSchema::new("Id", "string").with_unique(),
+                ColumnSchema::new("Tag", "foreignrow").with_references("Tags"),
+                ColumnSchema::new("Tags", "foreignrow")
+                    .with_references("Tags")
+                    .with_array(),
+                ColumnSchema::new("Colour", "enumrow").with_references("Colour"),
+                // Has a schema, but no file
+                ColumnSchema::new("Stat", "foreignrow").with_references("Stats"),
+            ],
+        );
+        let stats = DatTableSchema::new("Stats", [ColumnSchema::new("Id", "string")]);
+        let colour = Enumeration {
+            valid_for: 3,
+            name: "Colour".to_owned(),
+            indexing: 1,
+            enumerators: vec![Some("Red".to_owned()), Some("Blue".to_owned())],
+        };
+
+        let mut fs = MemoryFS::new()
+            .with_file(
+                "data/tags.datc64",
+                write_json_rows(&[json!({"Id": "fire"}), json!({"Id": "cold"})], &tags)
+                    .unwrap()
+                    .to_bytes(),
+            )
+            .with_file(
+                "data/mods.datc64",
+                write_json_rows(
+                    &[
+                        json!({"Id": "A", "Tag": 1, "Tags": [0, 1], "Colour": 2, "Stat": 0}),
+                        json!({"Id": "B", "Tag": null, "Tags": [], "Colour": 1, "Stat": null}),
+                    ],
+                    &mods,
+                )
+                .unwrap()
+                .to_bytes(),
+            );
+        let schemas = SchemaCollection::new(vec![tags, mods, stats], vec![colour]);
+        let mut graph = TableGraph::new(&mut fs, &schemas, &Patch::One);
+
+        let strings = |array: &dyn Array| {
+            array
+                .as_string::<i32>()
+                .iter()
+                .map(|s| s.map(ToOwned::to_owned))
+                .collect::<Vec<_>>()
+        };
+
+        let tag = graph.resolve_column("Mods", "Tag", Resolve::Key).unwrap();
+        assert_eq!(strings(&tag), [Some("cold".to_owned()), None]);
+
+        let tag_lists = graph.resolve_column("Mods", "Tags", Resolve::Key).unwrap();
+        let tag_lists = tag_lists.as_list::<i32>();
+        assert_eq!(
+            strings(&tag_lists.value(0)),
+            [Some("fire".to_owned()), Some("cold".to_owned())]
+        );
+        assert!(tag_lists.value(1).is_empty());
+
+        // 1-indexed enum
+        let colours = graph
+            .resolve_column("Mods", "Colour", Resolve::Key)
+            .unwrap();
+        assert_eq!(
+            strings(&colours),
+            [Some("Blue".to_owned()), Some("Red".to_owned())]
+        );
+
+        assert!(graph.resolve_column("Mods", "Stat", Resolve::Key).is_err());
+        assert!(matches!(
+            graph.resolve_column("Mods", "Id", Resolve::Key),
+            Err(DatError::NotReference(_))
+        ));
+
+        // Unresolvable columns are left as row indices
+        let df = graph.resolve("Mods", Resolve::Key).unwrap();
+        assert_eq!(df["Tag"].data_type(), &DataType::Utf8);
+        assert_eq!(df["Stat"].data_type(), &DataType::UInt64);
+
+        let df = graph.resolve("Mods", Resolve::Row).unwrap();
+        let tag = df["Tag"].as_struct();
+        assert_eq!(
+            strings(tag.column_by_name("Id").unwrap()),
+            [Some("cold".to_owned()), None]
+        );
+        assert!(tag.is_null(1));
+
+        let colour = df["Colour"].as_struct();
+        assert_eq!(
+            strings(colour.column_by_name("name").unwrap()),
+            [Some("Blue".to_owned()), Some("Red".to_owned())]
+        );
+    }
+}
@@ -0,0 +1,255 @@
+//! Merging the localized copies of a table, which live in a folder per language next to the
+//! English original, eg. `data/french/mods.datc64`
+use std::{collections::HashSet, fmt, str::FromStr, sync::Arc};
+
+use arrow_array::RecordBatch;
+use arrow_schema::{Schema, SchemaBuilder};
+
+use super::{
+    schema::DatTableSchema,
+    table_view::{DatError, DatResult},
+};
+
+/// A language the game is translated into
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Language {
+    English,
+    French,
+    German,
+    Japanese,
+    Korean,
+    Portuguese,
+    Russian,
+    SimplifiedChinese,
+    Spanish,
+    Thai,
+    TraditionalChinese,
+}
+
+impl Language {
+    pub const ALL: [Self; 11] = [
+        Self::English,
+        Self::French,
+        Self::German,
+        Self::Japanese,
+        Self::Korean,
+        Self::Portuguese,
+        Self::Russian,
+        Self::SimplifiedChinese,
+        Self::Spanish,
+        Self::Thai,
+        Self::TraditionalChinese,
+    ];
+
+    /// Short code used to suffix column names, eg. `Name_fr`
+    pub fn code(&self) -> &'static str {
+        use Language::*;
+        match self {
+            English => "en",
+            French => "fr",
+            German => "de",
+            Japanese => "ja",
+            Korean => "ko",
+            Portuguese => "pt",
+            Russian => "ru",
+            SimplifiedChinese => "zh_cn",
+            Spanish => "es",
+            Thai => "th",
+            TraditionalChinese => "zh_tw",
+        }
+    }
+
+    /// Folder holding the language's tables, or None for English which is stored in the base
+    /// folder
+    pub fn folder(&self) -> Option<&'static str> {
+        use Language::*;
+        let folder = match self {
+            English => return None,
+            French => "french",
+            German => "german",
+            Japanese => "japanese",
+            Korean => "korean",
+            Portuguese => "portuguese",
+            Russian => "russian",
+            SimplifiedChinese => "simplified chinese",
+            Spanish => "spanish",
+            Thai => "thai",
+            TraditionalChinese => "traditional chinese",
+        };
+
+        Some(folder)
+    }
+
+    /// Path of this language's copy of a table, given the path of the English one
+    pub fn table_path(&self, path: &str) -> String {
+        match (self.folder(), path.rsplit_once('/')) {
+            (None, _) => path.to_owned(),
+            (Some(folder), Some((parent, file))) => format!("{parent}/{folder}/{file}"),
+            (Some(folder), None) => format!("{folder}/{path}"),
+        }
+    }
+}
+
+impl fmt::Display for Language {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.code())
+    }
+}
+
+impl FromStr for Language {
+    type Err = String;
+
+    /// Parse either the language's code or its folder name, eg. `fr` or `French`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.to_lowercase().replace('-', "_");
+        Self::ALL
+            .into_iter()
+            .find(|l| l.code() == s || l.folder().unwrap_or("english") == s)
+            .ok_or_else(|| format!("Unknown language {s:?}"))
+    }
+}
+
+/// Whether a table is a localized copy, ie. it's in one of the language folders
+pub fn is_localized_path(path: &str) -> bool {
+    let Some((parent, _)) = path.rsplit_once('/') else {
+        return false;
+    };
+    let parent = parent.rsplit('/').next().unwrap_or(parent).to_lowercase();
+
+    Language::ALL
+        .iter()
+        .any(|l| l.folder() == Some(parent.as_str()))
+}
+
+/// Merge the copies of a table in each language into a single table. Columns the schema marks as
+/// localized get a column per language suffixed with its code, eg. `Name_en` and `Name_fr`, while
+/// the rest are taken from the first copy.
+///
+/// Localized copies are expected to hold the same rows in the same order, so the row counts must
+/// match.
+pub fn merge_localized(
+    schema: &DatTableSchema,
+    tables: &[(Language, RecordBatch)],
+) -> DatResult<RecordBatch> {
+    let Some(((_, base), _)) = tables.split_first() else {
+        return Ok(RecordBatch::new_empty(Arc::new(Schema::empty())));
+    };
+
+    for (language, table) in tables {
+        if table.num_rows() != base.num_rows() {
+            return Err(DatError::RowCountMismatch {
+                language: *language,
+                expected: base.num_rows(),
+                found: table.num_rows(),
+            });
+        }
+    }
+
+    let localized = schema
+        .enumerate()
+        .filter(|(_, c)| c.localized)
+        .map(|(name, _)| name)
+        .collect::<HashSet<_>>();
+
+    let mut schema_builder = SchemaBuilder::new();
+    let mut columns = vec![];
+    for (field, column) in base.schema_ref().fields().iter().zip(base.columns()) {
+        if !localized.contains(field.name()) {
+            schema_builder.push(field.clone());
+            columns.push(column.clone());
+            continue;
+        }
+
+        for (language, table) in tables {
+            let column = table
+                .column_by_name(field.name())
+                .ok_or_else(|| DatError::MissingColumn(field.name().clone()))?;
+            let name = format!("{}_{}", field.name(), language.code());
+            schema_builder.push(field.as_ref().clone().with_name(name));
+            columns.push(column.clone());
+        }
+    }
+
+    let df = RecordBatch::try_new(Arc::new(schema_builder.finish()), columns)?;
+    Ok(df)
+}
+
+#[cfg(test)]
+mod tests {
+    use arrow_array::{StringArray, cast::AsArray};
+    use serde_json::json;
+
+    use super::*;
+    use crate::dat::{schema::ColumnSchema, table::parse_table, writer::write_json_rows};
+
+    #[test]
+    fn languages() {
+        assert_eq!("FR".parse(), Ok(Language::French));
+        assert_eq!(
+            "simplified chinese".parse(),
+            Ok(Language::SimplifiedChinese)
+        );
+        assert_eq!("zh-tw".parse(), Ok(Language::TraditionalChinese));
+        assert!("klingon".parse::<Language>().is_err());
+
+        assert_eq!(
+            Language::French.table_path("data/balance/mods.datc64"),
+            "data/balance/french/mods.datc64"
+        );
+        assert_eq!(
+            Language::English.table_path("data/mods.datc64"),
+            "data/mods.datc64"
+        );
+        assert!(is_localized_path("data/French/mods.datc64"));
+        assert!(!is_localized_path("data/mods.datc64"));
+    }
+
+    #[test]
+    fn merge() {
+        let schema = DatTableSchema::new(
+            "Words",
+            [
+                ColumnSchema::new("Level", "i32"),
+                ColumnSchema::new("Name", "string").with_localized(),
+            ],
+        );
+        let table = |rows: &[serde_json::Value]| {
+            parse_table(&write_json_rows(rows, &schema).unwrap(), &schema).unwrap()
+        };
+
+        let english = table(&[json!({"Level": 1, "Name": "Cat"})]);
+        let french = table(&[json!({"Level": 1, "Name": "Chat"})]);
+        let df = merge_localized(
+            &schema,
+            &[
+                (Language::English, english.clone()),
+                (Language::French, french),
+            ],
+        )
+        .unwrap();
+
+        let names = df
+            .schema()
+            .fields()
+            .iter()
+            .map(|f| f.name().clone())
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["Level", "Name_en", "Name_fr"]);
+        assert_eq!(
+            df["Name_fr"].as_string::<i32>(),
+            &StringArray::from(vec!["Chat"])
+        );
+
+        let longer = table(&[
+            json!({"Level": 1, "Name": "Katze"}),
+            json!({"Level": 2, "Name": "Hund"}),
+        ]);
+        assert!(matches!(
+            merge_localized(
+                &schema,
+                &[(Language::English, english), (Language::German, longer)]
+            ),
+            Err(DatError::RowCountMismatch { found: 2, .. })
+        ));
+    }
+}
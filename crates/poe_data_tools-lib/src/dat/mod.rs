@@ -1,4 +1,9 @@
+pub mod diff;
+pub mod graph;
+pub mod localized;
 pub mod parser;
 pub mod schema;
 pub mod table;
 pub mod table_view;
+pub mod validate;
+pub mod writer;
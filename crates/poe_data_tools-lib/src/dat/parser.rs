@@ -25,6 +25,16 @@ pub(super) fn utf16le_cstring() -> impl U8Parser<Output = String> {
         .try_map(|bytes| String::from_utf16(&bytes))
 }
 
+// Take a null-terminated UTF-32 string
+pub(super) fn utf32le_cstring() -> impl U8Parser<Output = String> {
+    u32::LE.repeat_till_exc(b"\0\0\0\0").try_map(|chars| {
+        chars
+            .into_iter()
+            .map(char::try_from)
+            .collect::<Result<String, _>>()
+    })
+}
+
 /// Read an index and read a string out of the variable section starting at the index
 /// Returns null instead of erroring if a bad offset is found
 fn string<'a>(variable_section: &[u8]) -> impl WinnowParser<&'a [u8], Option<String>> {
@@ -2,7 +2,7 @@ use std::{fs, path::Path};
 
 use serde::Deserialize;
 
-use crate::Patch;
+use crate::{Patch, file_parsers::dat::types::DatLayout};
 
 #[derive(Debug, thiserror::Error)]
 pub enum SchemaError {
@@ -25,6 +25,12 @@ type Result<T, E = SchemaError> = std::result::Result<T, E>;
 /// https://github.com/poe-tool-dev/dat-schema
 #[derive(Deserialize, Debug, Clone)]
 pub struct SchemaCollection {
+    /// Schema format version, missing from hand-written schema files
+    #[serde(default)]
+    pub version: Option<u32>,
+    /// Unix timestamp of when the schema was generated
+    #[serde(rename = "createdAt", default)]
+    pub created_at: Option<u64>,
     /// Per-table schemas
     pub tables: Vec<DatTableSchema>,
     /// Enum variants
@@ -37,6 +43,8 @@ impl SchemaCollection {
         let version = version.major();
 
         Self {
+            version: self.version,
+            created_at: self.created_at,
             tables: self
                 .tables
                 .iter()
@@ -166,6 +174,41 @@ impl ColumnSchema {
     pub fn is_multi(&self) -> bool {
         self.array || self.interval
     }
+
+    /// Size in bytes of a single value of this column's type in a .datc64 table, if known
+    pub fn item_width(&self) -> Option<usize> {
+        self.item_width_in(DatLayout::DATC64)
+    }
+
+    /// Size in bytes of a single value of this column's type, if known
+    pub fn item_width_in(&self, layout: DatLayout) -> Option<usize> {
+        let width = match self.column_type.as_str() {
+            "foreignrow" => layout.foreignrow_width(),
+            "string" | "row" => layout.pointer_width,
+            "enumrow" | "u32" | "i32" | "f32" => 4,
+            "u16" | "i16" => 2,
+            "bool" => 1,
+            _ => return None,
+        };
+
+        Some(width)
+    }
+
+    /// Number of bytes this column takes up in each row of a .datc64 table, if known
+    pub fn width(&self) -> Option<usize> {
+        self.width_in(DatLayout::DATC64)
+    }
+
+    /// Number of bytes this column takes up in each row, if known
+    pub fn width_in(&self, layout: DatLayout) -> Option<usize> {
+        match (self.array, self.interval) {
+            // Length + pointer into the variable data section
+            (true, false) => Some(layout.array_width()),
+            (false, true) => self.item_width_in(layout).map(|w| w * 2),
+            (false, false) => self.item_width_in(layout),
+            (true, true) => None,
+        }
+    }
 }
 
 /// Foreign table name
@@ -244,6 +287,77 @@ pub fn fetch_schema(cache_dir: &Path) -> Result<SchemaCollection> {
     )?)
 }
 
+/// Builders for schemas in tests, leaving out the fields that don't affect parsing
+#[cfg(test)]
+impl ColumnSchema {
+    pub(crate) fn new(name: &str, column_type: &str) -> Self {
+        Self {
+            name: Some(name.to_owned()),
+            description: None,
+            array: false,
+            interval: false,
+            column_type: column_type.to_owned(),
+            unique: false,
+            localized: false,
+            references: None,
+            until: None,
+            file: None,
+            files: None,
+        }
+    }
+
+    pub(crate) fn with_array(mut self) -> Self {
+        self.array = true;
+        self
+    }
+
+    pub(crate) fn with_interval(mut self) -> Self {
+        self.interval = true;
+        self
+    }
+
+    pub(crate) fn with_unique(mut self) -> Self {
+        self.unique = true;
+        self
+    }
+
+    pub(crate) fn with_localized(mut self) -> Self {
+        self.localized = true;
+        self
+    }
+
+    pub(crate) fn with_references(mut self, table: &str) -> Self {
+        self.references = Some(References {
+            table: table.to_owned(),
+        });
+        self
+    }
+}
+
+#[cfg(test)]
+impl DatTableSchema {
+    /// Table common to both games
+    pub(crate) fn new(name: &str, columns: impl IntoIterator<Item = ColumnSchema>) -> Self {
+        Self {
+            valid_for: 3,
+            name: name.to_owned(),
+            columns: columns.into_iter().collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+impl SchemaCollection {
+    pub(crate) fn new(tables: Vec<DatTableSchema>, enumerations: Vec<Enumeration>) -> Self {
+        Self {
+            version: None,
+            created_at: None,
+            tables,
+            enumerations,
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use dirs::cache_dir;
@@ -1,36 +1,55 @@
 use std::{path::PathBuf, sync::Arc};
 
 use arrow_array::{
-    ArrayRef, BooleanArray, Float32Array, Int16Array, Int32Array, RecordBatch, StringArray,
-    UInt16Array, UInt32Array, UInt64Array,
+    Array, ArrayRef, BooleanArray, DictionaryArray, Float32Array, Int16Array, Int32Array,
+    ListArray, RecordBatch, StringArray, UInt16Array, UInt32Array, UInt64Array,
     builder::{
         Float32Builder, Int16Builder, Int32Builder, ListBuilder, StringBuilder, UInt16Builder,
         UInt32Builder, UInt64Builder,
     },
+    cast::AsArray,
+    types::UInt32Type,
 };
+use arrow_schema::{DataType, Field, SchemaBuilder};
 
 use super::table_view::ColResult;
 use crate::{
     Patch,
     dat::{
-        schema::{ColumnSchema, DatTableSchema, SchemaCollection},
+        localized::{Language, merge_localized},
+        schema::{ColumnSchema, DatTableSchema, Enumeration, SchemaCollection},
         table_view::{DatColumnError, DatError, DatResult},
     },
     file_parsers::{
         FileParser,
-        dat::{DatParser, types::DatFile},
+        dat::{
+            DatParser,
+            types::{DatFile, DatLayout},
+        },
     },
     fs::FileSystem,
 };
 
+/// Read a row index, which is 4 or 8 bytes depending on the table's layout
+fn parse_index(bytes: &[u8]) -> u64 {
+    match bytes.len() {
+        4 => parse_u32(bytes) as u64,
+        _ => parse_u64(bytes),
+    }
+}
+
 fn parse_foreignrow(bytes: &[u8]) -> u64 {
-    // TODO: polars doesn't support u128, so figure something out later. For now
-    // just downcast
-    u128::from_le_bytes(bytes.try_into().unwrap()) as u64
+    // Only the first half holds the row index
+    parse_index(&bytes[..bytes.len() / 2])
+}
+
+/// Whether a reference is null, ie. all 0xfe
+fn is_null_ref(bytes: &[u8]) -> bool {
+    bytes.iter().all(|b| *b == 0xfe)
 }
 
 fn parse_maybe_foreignrow(bytes: &[u8]) -> Option<u64> {
-    if bytes == [0xfe; 16] {
+    if is_null_ref(bytes) {
         None
     } else {
         Some(parse_foreignrow(bytes))
@@ -38,10 +57,10 @@ fn parse_maybe_foreignrow(bytes: &[u8]) -> Option<u64> {
 }
 
 fn parse_maybe_row(bytes: &[u8]) -> Option<u64> {
-    if bytes == [0xfe; 8] {
+    if is_null_ref(bytes) {
         None
     } else {
-        Some(parse_u64(bytes))
+        Some(parse_index(bytes))
     }
 }
 
@@ -76,11 +95,12 @@ fn parse_bool(bytes: &[u8]) -> ColResult<bool> {
 }
 
 /// Apply a schema to a single column
-fn parse_column(
+pub(super) fn parse_column(
     table: &DatFile,
     column: &ColumnSchema,
     cur_offset: usize,
 ) -> ColResult<(usize, ColResult<ArrayRef>)> {
+    let layout = table.layout;
     let (bytes_taken, series) = match (column.array, column.interval) {
         // Array
         (true, false) => {
@@ -104,7 +124,7 @@ fn parse_column(
                     }),
 
                 "foreignrow" => table
-                    .view_col_as_array_of(cur_offset, 16, parse_foreignrow)?
+                    .view_col_as_array_of(cur_offset, layout.foreignrow_width(), parse_foreignrow)?
                     .collect::<ColResult<Vec<_>>>()
                     .map(|s| {
                         let mut builder = ListBuilder::new(UInt64Builder::new());
@@ -119,7 +139,7 @@ fn parse_column(
                     }),
 
                 "row" => table
-                    .view_col_as_array_of(cur_offset, 8, parse_maybe_row)?
+                    .view_col_as_array_of(cur_offset, layout.pointer_width, parse_maybe_row)?
                     .collect::<ColResult<Vec<_>>>()
                     .map(|s| {
                         let mut builder = ListBuilder::new(UInt64Builder::new());
@@ -231,7 +251,7 @@ fn parse_column(
             }
             .map(|s| Arc::new(s) as _);
 
-            (16, series)
+            (layout.array_width(), series)
         }
 
         // Interval
@@ -269,25 +289,25 @@ fn parse_column(
                     .and_then(|strings| strings.collect::<ColResult<Vec<_>>>())
                     // .map(|s| Series::new(col_name.into(), s));
                     .map(|s| Arc::new(StringArray::from(s)) as _);
-                (8, series)
+                (layout.pointer_width, series)
             }
 
             "foreignrow" => {
                 let series = table
-                    .view_col(cur_offset, 16)
+                    .view_col(cur_offset, layout.foreignrow_width())
                     .map(|items| items.map(parse_maybe_foreignrow).collect::<Vec<_>>())
                     // .map(|s| Series::new(col_name.into(), s));
                     .map(|s| Arc::new(UInt64Array::from(s)) as _);
-                (16, series)
+                (layout.foreignrow_width(), series)
             }
 
             "row" => {
                 let series = table
-                    .view_col(cur_offset, 8)
+                    .view_col(cur_offset, layout.pointer_width)
                     .map(|items| items.map(parse_maybe_row).collect::<Vec<_>>())
                     // .map(|s| Series::new(col_name.into(), s));
                     .map(|s| Arc::new(UInt64Array::from(s)) as _);
-                (8, series)
+                (layout.pointer_width, series)
             }
 
             "enumrow" => {
@@ -405,10 +425,146 @@ pub fn parse_table(table: &DatFile, schema: &DatTableSchema) -> DatResult<Record
     Ok(df)
 }
 
+/// Build a table of an enum's variants, with the value used to refer to each one alongside its
+/// name. Unnamed variants have a null name.
+pub fn enum_table(enumeration: &Enumeration) -> DatResult<RecordBatch> {
+    let indices = (0..enumeration.enumerators.len())
+        .map(|i| (i + enumeration.indexing) as u32)
+        .collect::<Vec<_>>();
+
+    let df = RecordBatch::try_from_iter([
+        ("index", Arc::new(UInt32Array::from(indices)) as ArrayRef),
+        (
+            "name",
+            Arc::new(StringArray::from(enumeration.enumerators.clone())) as _,
+        ),
+    ])?;
+    Ok(df)
+}
+
+/// Convert enum values into dictionary keys. Values without a known variant become null.
+fn enum_keys(column: &str, values: &UInt32Array, enumeration: &Enumeration) -> UInt32Array {
+    let keys = values
+        .iter()
+        .map(|v| {
+            v.and_then(|v| v.checked_sub(enumeration.indexing as u32))
+                .filter(|k| (*k as usize) < enumeration.enumerators.len())
+        })
+        .collect::<UInt32Array>();
+
+    let unknown = keys.null_count() - values.null_count();
+    if unknown > 0 {
+        log::warn!(
+            "{unknown} values in column {column:?} are outside of enum {:?}",
+            enumeration.name
+        );
+    }
+
+    keys
+}
+
+/// Dictionary-encode a column of enum values using the variant names
+fn materialise_enum(
+    column: &str,
+    array: &ArrayRef,
+    enumeration: &Enumeration,
+) -> DatResult<Option<ArrayRef>> {
+    let names = Arc::new(StringArray::from(enumeration.enumerators.clone())) as ArrayRef;
+
+    let array = match array.data_type() {
+        DataType::UInt32 => {
+            let keys = enum_keys(column, array.as_primitive::<UInt32Type>(), enumeration);
+            Arc::new(DictionaryArray::try_new(keys, names)?) as ArrayRef
+        }
+        DataType::List(item) if item.data_type() == &DataType::UInt32 => {
+            let list = array.as_list::<i32>();
+            let keys = enum_keys(column, list.values().as_primitive(), enumeration);
+            let values = DictionaryArray::try_new(keys, names)?;
+
+            let field = Field::new_list_field(values.data_type().clone(), true);
+            Arc::new(ListArray::try_new(
+                Arc::new(field),
+                list.offsets().clone(),
+                Arc::new(values),
+                list.nulls().cloned(),
+            )?) as _
+        }
+        _ => return Ok(None),
+    };
+
+    Ok(Some(array))
+}
+
+/// Replace the enumrow columns of a parsed table with dictionary-encoded variant names. Columns
+/// that reference an enum missing from `enumerations` keep their raw values.
+pub fn materialise_enums(
+    df: RecordBatch,
+    schema: &DatTableSchema,
+    enumerations: &[Enumeration],
+) -> DatResult<RecordBatch> {
+    let (arrow_schema, mut columns, _) = df.into_parts();
+    let mut schema_builder = SchemaBuilder::from(&*arrow_schema);
+
+    for (name, column) in schema.enumerate() {
+        if column.column_type != "enumrow" {
+            continue;
+        }
+
+        let Some(enumeration) = column
+            .get_ref()
+            .and_then(|table| enumerations.iter().find(|e| e.name == table))
+        else {
+            continue;
+        };
+
+        // Columns that failed to parse are missing from the table
+        let Ok(i) = arrow_schema.index_of(&name) else {
+            continue;
+        };
+
+        if let Some(array) = materialise_enum(&name, &columns[i], enumeration)? {
+            let field = (**schema_builder.field(i))
+                .clone()
+                .with_data_type(array.data_type().clone())
+                // Unknown variants are nulls
+                .with_nullable(true);
+            *schema_builder.field_mut(i) = Arc::new(field);
+            columns[i] = array;
+        }
+    }
+
+    let df = RecordBatch::try_new(Arc::new(schema_builder.finish()), columns)?;
+    Ok(df)
+}
+
+/// Lowercase table name from a dat file path
+pub fn table_name(path: &str) -> String {
+    PathBuf::from(path)
+        .file_stem()
+        .unwrap()
+        .to_string_lossy()
+        .to_lowercase()
+}
+
+/// Find the schema of a table for a major version, given its lowercase name
+// TODO: HashMap rather than vector
+pub fn find_table<'a>(
+    schemas: &'a SchemaCollection,
+    name: &str,
+    version: u32,
+) -> Option<&'a DatTableSchema> {
+    schemas
+        .tables
+        .iter()
+        // valid_for == 3 is common between both games
+        .filter(|t| t.valid_for == version || t.valid_for == 3)
+        .find(|t| t.name.to_lowercase() == name)
+}
+
 /// Extension trait providing easier loading of dat tables
 pub trait FSDatEx: FileSystem {
-    /// Loads a table into an Arrow RecordBatch
-    // TODO: Support for enum tables
+    /// Loads a table into an Arrow RecordBatch. Enum tables are built from their schema, see
+    /// [enum_table].
     fn load_dat_table(
         &mut self,
         schemas: &SchemaCollection,
@@ -416,20 +572,26 @@ pub trait FSDatEx: FileSystem {
         version: &Patch,
     ) -> DatResult<RecordBatch> {
         let version = version.major();
+        let name = table_name(path);
 
         // Load table schema
-        // TODO: HashMap rather than vector
-        let schema = schemas
-            .tables
-            .iter()
-            // valid_for == 3 is common between both games
-            .filter(|t| t.valid_for == version || t.valid_for == 3)
-            .find(|t| *t.name.to_lowercase() == *PathBuf::from(&path).file_stem().unwrap())
-            .ok_or_else(|| DatError::SchemaNotFound(path.to_owned()))?;
-
-        // Load dat file & parse generic structure
+        let Some(schema) = find_table(schemas, &name, version) else {
+            // Enum tables have no columns, their variants only exist in the schema
+            let enumeration = schemas
+                .enumerations
+                .iter()
+                .filter(|e| e.valid_for == version || e.valid_for == 3)
+                .find(|e| e.name.to_lowercase() == name)
+                .ok_or_else(|| DatError::SchemaNotFound(path.to_owned()))?;
+
+            return enum_table(enumeration);
+        };
+
+        // Load dat file & parse generic structure, in whichever variant the extension says
         let bytes = self.read(path)?;
-        let table = DatParser.parse(&bytes)?;
+        let table = DatParser
+            .parse(&bytes)?
+            .with_layout(DatLayout::from_path(path).unwrap_or_default());
 
         if table.rows.is_empty() {
             return Err(DatError::EmptyTable);
@@ -440,6 +602,161 @@ pub trait FSDatEx: FileSystem {
 
         Ok(df)
     }
+
+    /// Loads a table like [load_dat_table](FSDatEx::load_dat_table), with enumrow columns
+    /// materialised as variant names. See [materialise_enums].
+    fn load_dat_table_with_enums(
+        &mut self,
+        schemas: &SchemaCollection,
+        path: &str,
+        version: &Patch,
+    ) -> DatResult<RecordBatch> {
+        let df = self.load_dat_table(schemas, path, version)?;
+
+        let major = version.major();
+        match find_table(schemas, &table_name(path), major) {
+            Some(schema) => {
+                let enumerations = schemas
+                    .enumerations
+                    .iter()
+                    .filter(|e| e.valid_for == major || e.valid_for == 3)
+                    .cloned()
+                    .collect::<Vec<_>>();
+
+                materialise_enums(df, schema, &enumerations)
+            }
+            // Enum tables are returned as-is
+            None => Ok(df),
+        }
+    }
+
+    /// Loads a table's copy in each of the languages and merges them into a single table, with
+    /// a column per language for each localized column. `path` is the English copy's path, see
+    /// [merge_localized] for how the copies are combined.
+    fn load_localized_table(
+        &mut self,
+        schemas: &SchemaCollection,
+        path: &str,
+        languages: &[Language],
+        version: &Patch,
+    ) -> DatResult<RecordBatch> {
+        let schema = match find_table(schemas, &table_name(path), version.major()) {
+            Some(schema) if !languages.is_empty() => schema,
+            // Enum tables aren't translated
+            _ => return self.load_dat_table(schemas, path, version),
+        };
+
+        let tables = languages
+            .iter()
+            .map(|language| {
+                let df = self.load_dat_table(schemas, &language.table_path(path), version)?;
+                Ok((*language, df))
+            })
+            .collect::<DatResult<Vec<_>>>()?;
+
+        merge_localized(schema, &tables)
+    }
 }
 
 impl<T> FSDatEx for T where T: FileSystem {}
+
+#[cfg(test)]
+mod tests {
+    use arrow_array::ArrayAccessor;
+    use serde_json::json;
+
+    use super::*;
+    use crate::dat::writer::write_json_rows;
+
+    #[test]
+    fn enums() {
+        let enumeration: Enumeration = serde_json::from_value(json!({
+            "validFor": 3,
+            "name": "Colour",
+            "indexing": 1,
+            "enumerators": ["Red", null, "Blue"],
+        }))
+        .unwrap();
+
+        let df = enum_table(&enumeration).unwrap();
+        let indices = df["index"].as_primitive::<UInt32Type>();
+        assert_eq!(indices.values().to_vec(), [1, 2, 3]);
+        assert_eq!(df["name"].null_count(), 1);
+
+        let schema = DatTableSchema::new(
+            "Test",
+            [
+                ColumnSchema::new("Colour", "enumrow").with_references("Colour"),
+                ColumnSchema::new("Colours", "enumrow")
+                    .with_references("Colour")
+                    .with_array(),
+            ],
+        );
+
+        let rows = [
+            json!({"Colour": 3, "Colours": [1, 3]}),
+            json!({"Colour": 9, "Colours": []}),
+        ];
+        let table = write_json_rows(&rows, &schema).unwrap();
+        let df = parse_table(&table, &schema).unwrap();
+        let df = materialise_enums(df, &schema, &[enumeration]).unwrap();
+
+        let colour = df["Colour"].as_dictionary::<UInt32Type>();
+        let names = colour.downcast_dict::<StringArray>().unwrap();
+        assert_eq!(names.value(0), "Blue");
+        // Unknown variant
+        assert!(colour.is_null(1));
+
+        let colours = df["Colours"].as_list::<i32>();
+        let first = colours.value(0);
+        let names = first
+            .as_dictionary::<UInt32Type>()
+            .downcast_dict::<StringArray>()
+            .unwrap();
+        assert_eq!(
+            names.into_iter().collect::<Vec<_>>(),
+            [Some("Red"), Some("Blue")]
+        );
+    }
+
+    #[test]
+    fn legacy_layout() {
+        let schema = DatTableSchema::new(
+            "Test",
+            [
+                ColumnSchema::new("Id", "string"),
+                ColumnSchema::new("Tag", "foreignrow"),
+                ColumnSchema::new("Levels", "i32").with_array(),
+            ],
+        );
+
+        let rows = [
+            json!({"Id": "ab", "Tag": 1, "Levels": [1, 2]}),
+            json!({"Id": "ab", "Tag": null, "Levels": []}),
+        ];
+        let expected = parse_table(&write_json_rows(&rows, &schema).unwrap(), &schema).unwrap();
+
+        // The same rows as a .datl, with 32-bit pointers and UTF-32 strings. Pointers count from
+        // the start of the marker, so the variable data starts at 8.
+        let u32s =
+            |values: &[u32]| -> Vec<u8> { values.iter().flat_map(|v| v.to_le_bytes()).collect() };
+        let variable_data = [u32s(&['a' as u32, 'b' as u32, 0]), u32s(&[1, 2])].concat();
+        let table = DatFile {
+            rows: vec![
+                u32s(&[8, 1, 0, 2, 20]),
+                [u32s(&[8]), vec![0xfe; 8], u32s(&[0, 20])].concat(),
+            ],
+            variable_data,
+            layout: DatLayout::DATL,
+        };
+        let width: Option<usize> = schema
+            .columns
+            .iter()
+            .map(|c| c.width_in(table.layout))
+            .sum();
+        assert_eq!(width, Some(20));
+
+        let df = parse_table(&table, &schema).unwrap();
+        assert_eq!(df, expected);
+    }
+}
@@ -4,9 +4,13 @@ use annotated_parser::Parser;
 use arrow_schema::ArrowError;
 
 use crate::{
-    dat::{parser::utf16le_cstring, schema::ColumnSchema},
+    dat::{
+        localized::Language,
+        parser::{utf16le_cstring, utf32le_cstring},
+        schema::ColumnSchema,
+    },
     file_parsers::{
-        dat::types::DatFile,
+        dat::types::{DatFile, StringEncoding},
         error::{AnnotatedError, ParseError},
     },
 };
@@ -43,6 +47,9 @@ pub enum DatColumnError {
 
     #[error("invalid boolean value: {0}")]
     InvalidBool(u8),
+
+    #[error("value can't be written as {column_type:?}: {value}")]
+    UnexpectedValue { column_type: String, value: String },
 }
 
 pub(super) type ColResult<T, E = DatColumnError> = std::result::Result<T, E>;
@@ -73,11 +80,26 @@ pub enum DatError {
 
     #[error("table has no data")]
     EmptyTable,
+
+    #[error("no data provided for column {0:?}")]
+    MissingColumn(String),
+
+    #[error("column {0:?} is not a reference")]
+    NotReference(String),
+
+    /// A localized copy of a table doesn't line up with the others
+    #[error("{language} table has {found} rows, expected {expected}")]
+    RowCountMismatch {
+        language: Language,
+        expected: usize,
+        found: usize,
+    },
 }
 
 pub(super) type DatResult<T, E = DatError> = std::result::Result<T, E>;
 
-/// Methods for reading Dat tables column-wise
+/// Methods for reading Dat tables column-wise. Widths of pointers and the encoding of strings are
+/// taken from the table's [layout](DatFile::layout).
 impl DatFile {
     /// Number of bytes in a row
     pub fn width(&self) -> usize {
@@ -107,66 +129,77 @@ impl DatFile {
         offset: usize,
         dtype_width: usize,
     ) -> ColResult<impl Iterator<Item = ColResult<Vec<&[u8]>>>> {
-        let iter = self.view_col(offset, 16)?.map(move |bytes| {
-            let length = u64::from_le_bytes(bytes[..8].try_into().unwrap()) as usize;
-            let pointer = u64::from_le_bytes(bytes[8..].try_into().unwrap()) as usize;
-
-            // Check bounds
-            let start = pointer
-                .checked_sub(8)
-                .ok_or(DatColumnError::PointerUnderflow)?;
-            let end = start
-                .checked_add(
-                    length
-                        .checked_mul(dtype_width)
-                        .ok_or(DatColumnError::PointerOverflow)?,
-                )
-                .ok_or(DatColumnError::PointerOverflow)?;
-            if end > self.variable_data.len() {
-                return Err(DatColumnError::ArrayOutOfBounds {
-                    range: start..end,
-                    length,
-                });
-            }
-
-            let bytes = self.variable_data[start..end]
-                .chunks_exact(dtype_width)
-                .collect();
-            Ok(bytes)
-        });
+        let layout = self.layout;
+        let iter = self
+            .view_col(offset, layout.array_width())?
+            .map(move |bytes| {
+                let length = layout.read_pointer(bytes) as usize;
+                let pointer = layout.read_pointer(&bytes[layout.pointer_width..]) as usize;
+
+                // Check bounds
+                let start = pointer
+                    .checked_sub(8)
+                    .ok_or(DatColumnError::PointerUnderflow)?;
+                let end = start
+                    .checked_add(
+                        length
+                            .checked_mul(dtype_width)
+                            .ok_or(DatColumnError::PointerOverflow)?,
+                    )
+                    .ok_or(DatColumnError::PointerOverflow)?;
+                if end > self.variable_data.len() {
+                    return Err(DatColumnError::ArrayOutOfBounds {
+                        range: start..end,
+                        length,
+                    });
+                }
+
+                let bytes = self.variable_data[start..end]
+                    .chunks_exact(dtype_width)
+                    .collect();
+                Ok(bytes)
+            });
 
         Ok(iter)
     }
 
+    /// Read a null-terminated string from the variable data section. Empty strings are null.
+    fn read_string(&self, pointer: &[u8]) -> ColResult<Option<String>> {
+        let pointer = self.layout.read_pointer(pointer) as usize;
+
+        let start = pointer
+            .checked_sub(8)
+            .ok_or(DatColumnError::PointerUnderflow)?;
+        if start > self.variable_data.len() {
+            return Err(DatColumnError::StringOutOfBounds {
+                start,
+                length: self.variable_data.len(),
+            });
+        }
+
+        let input = &mut &self.variable_data[start..];
+        let (string, _) = match self.layout.encoding {
+            StringEncoding::Utf16 => utf16le_cstring().parse(input),
+            StringEncoding::Utf32 => utf32le_cstring().parse(input),
+        }
+        .map_err(|e| DatColumnError::StringReadError(e.into()))?;
+        let string = if string.is_empty() {
+            None
+        } else {
+            Some(string)
+        };
+
+        Ok(string)
+    }
+
     /// Interpret a column as strings, dereferencing them from the variable data section
     pub fn view_col_as_string(
         &self,
         offset: usize,
     ) -> ColResult<impl Iterator<Item = ColResult<Option<String>>> + '_> {
-        let iter = self.view_col(offset, 8)?.map(move |bytes| {
-            let pointer = u64::from_le_bytes(bytes.try_into().unwrap()) as usize;
-
-            let start = pointer
-                .checked_sub(8)
-                .ok_or(DatColumnError::PointerUnderflow)?;
-            if start > self.variable_data.len() {
-                return Err(DatColumnError::StringOutOfBounds {
-                    start,
-                    length: self.variable_data.len(),
-                });
-            }
-
-            let (string, _) = utf16le_cstring()
-                .parse(&mut &self.variable_data[start..])
-                .map_err(|e| DatColumnError::StringReadError(e.into()))?;
-            let string = if string.is_empty() {
-                None
-            } else {
-                Some(string)
-            };
-
-            Ok(string)
-        });
+        let iter = self
+            .view_col(offset, self.layout.pointer_width)?
+            .map(|bytes| self.read_string(bytes));
 
         Ok(iter)
     }
@@ -192,30 +225,8 @@ impl DatFile {
         offset: usize,
     ) -> ColResult<impl Iterator<Item = ColResult<Vec<Option<String>>>> + '_> {
         let iter = self
-            .view_col_as_array_of(offset, 8, |bytes| {
-                let pointer = u64::from_le_bytes(bytes.try_into().unwrap()) as usize;
-
-                let start = pointer
-                    .checked_sub(8)
-                    .ok_or(DatColumnError::PointerUnderflow)?;
-                if start > self.variable_data.len() {
-                    return Err(DatColumnError::StringOutOfBounds {
-                        start,
-                        length: self.variable_data.len(),
-                    });
-                }
-
-                let (string, _) = utf16le_cstring()
-                    .parse(&mut &self.variable_data[start..])
-                    .map_err(|e| DatColumnError::StringReadError(e.into()))?;
-
-                let string = if string.is_empty() {
-                    None
-                } else {
-                    Some(string)
-                };
-
-                Ok(string)
+            .view_col_as_array_of(offset, self.layout.pointer_width, |bytes| {
+                self.read_string(bytes)
             })?
             // Pull the Result up to the item level
             .map(|x| x?.into_iter().collect::<ColResult<Vec<_>>>());
@@ -0,0 +1,321 @@
+//! Checks a schema against the table it describes, and guesses at columns the schema is missing
+use std::ops::Range;
+
+use super::{schema::DatTableSchema, table::parse_column};
+use crate::file_parsers::dat::types::{DatFile, StringEncoding};
+
+/// A problem found when applying a schema to a table
+#[derive(Debug, Clone, PartialEq)]
+pub enum SchemaIssue {
+    /// The schema doesn't cover the full width of the rows
+    TooNarrow {
+        schema_width: usize,
+        table_width: usize,
+    },
+    /// The schema describes more bytes than the rows contain
+    TooWide {
+        schema_width: usize,
+        table_width: usize,
+    },
+    /// A column's width can't be determined, so the columns following it can't be checked
+    UnknownColumnType { column: String, column_type: String },
+    /// A column's data couldn't be interpreted as its type
+    BadColumn {
+        column: String,
+        offset: usize,
+        error: String,
+    },
+}
+
+/// A guess at the type of a column that isn't covered by the schema
+#[derive(Debug, Clone, PartialEq)]
+pub struct ColumnSuggestion {
+    /// Byte offset within the row
+    pub offset: usize,
+    pub width: usize,
+    /// Column type, using the same names as the schema
+    pub column_type: &'static str,
+    pub array: bool,
+    /// Fraction of rows that hold a non-zero, non-null value. Guesses backed by few rows are
+    /// less reliable.
+    pub evidence: f32,
+}
+
+/// Result of checking a schema against a table
+#[derive(Debug, Clone)]
+pub struct ValidationReport {
+    pub rows: usize,
+    /// Width of each row in the table
+    pub table_width: usize,
+    /// Width of each row according to the schema, if all column types are known
+    pub schema_width: Option<usize>,
+    pub issues: Vec<SchemaIssue>,
+    /// Byte range at the end of each row not covered by the schema
+    pub unclaimed: Option<Range<usize>>,
+    /// Guesses for columns within the unclaimed bytes
+    pub suggestions: Vec<ColumnSuggestion>,
+}
+
+impl ValidationReport {
+    pub fn is_valid(&self) -> bool {
+        self.issues.is_empty()
+    }
+}
+
+/// Apply a schema to a table column by column, reporting any problems and suggesting types for
+/// the bytes it doesn't cover
+pub fn validate_schema(table: &DatFile, schema: &DatTableSchema) -> ValidationReport {
+    let table_width = table.width();
+    let mut issues = vec![];
+
+    // Walk the columns, stopping once offsets can no longer be trusted
+    let mut offset = 0;
+    let mut schema_width = Some(0);
+    for (name, column) in schema.enumerate() {
+        let Some(width) = column.width_in(table.layout) else {
+            issues.push(SchemaIssue::UnknownColumnType {
+                column: name,
+                column_type: column.column_type.clone(),
+            });
+            schema_width = None;
+            break;
+        };
+
+        // Columns past the end of the row are covered by the width check below
+        if offset + width <= table_width && !table.rows.is_empty() {
+            let error = match parse_column(table, column, offset) {
+                Ok((_, Ok(_))) => None,
+                Ok((_, Err(e))) | Err(e) => Some(e.to_string()),
+            };
+
+            if let Some(error) = error {
+                issues.push(SchemaIssue::BadColumn {
+                    column: name,
+                    offset,
+                    error,
+                });
+            }
+        }
+
+        offset += width;
+        schema_width = Some(offset);
+    }
+
+    let unclaimed = match schema_width {
+        Some(schema_width) if schema_width < table_width => {
+            issues.push(SchemaIssue::TooNarrow {
+                schema_width,
+                table_width,
+            });
+            Some(schema_width..table_width)
+        }
+        Some(schema_width) if schema_width > table_width => {
+            issues.push(SchemaIssue::TooWide {
+                schema_width,
+                table_width,
+            });
+            None
+        }
+        _ => None,
+    };
+
+    let suggestions = unclaimed
+        .as_ref()
+        .map(|range| suggest_columns(table, range.clone()))
+        .unwrap_or_default();
+
+    ValidationReport {
+        rows: table.rows.len(),
+        table_width,
+        schema_width,
+        issues,
+        unclaimed,
+        suggestions,
+    }
+}
+
+/// Values of a byte range across all rows
+fn cells(table: &DatFile, offset: usize, width: usize) -> impl Iterator<Item = &[u8]> {
+    table
+        .rows
+        .iter()
+        .map(move |row| &row[offset..offset + width])
+}
+
+/// Whether a pointer lands within the variable data section. Pointers are relative to the start
+/// of the section's 8 byte marker.
+fn in_variable_data(table: &DatFile, pointer: u64) -> bool {
+    (8..table.variable_data.len() as u64 + 8).contains(&pointer)
+}
+
+fn is_string(table: &DatFile, bytes: &[u8]) -> bool {
+    let pointer = table.layout.read_pointer(bytes);
+    if !in_variable_data(table, pointer) {
+        return false;
+    }
+
+    // Must be a null-terminated string in the table's encoding
+    let data = &table.variable_data[pointer as usize - 8..];
+    let char_width = table.layout.char_width();
+    let units = data
+        .chunks_exact(char_width)
+        .take_while(|c| c.iter().any(|b| *b != 0))
+        .collect::<Vec<_>>();
+
+    let valid = match table.layout.encoding {
+        StringEncoding::Utf16 => {
+            let units = units.iter().map(|c| u16::from_le_bytes([c[0], c[1]]));
+            char::decode_utf16(units).all(|c| c.is_ok())
+        }
+        StringEncoding::Utf32 => units
+            .iter()
+            .all(|c| char::from_u32(u32::from_le_bytes([c[0], c[1], c[2], c[3]])).is_some()),
+    };
+
+    (units.len() + 1) * char_width <= data.len() && valid
+}
+
+fn is_array(table: &DatFile, bytes: &[u8]) -> bool {
+    let layout = table.layout;
+    let (length, pointer) = (
+        layout.read_pointer(bytes),
+        layout.read_pointer(&bytes[layout.pointer_width..]),
+    );
+    let end = table.variable_data.len() as u64 + 8;
+
+    // Empty arrays point to the end of the variable data section
+    if length == 0 {
+        return pointer <= end;
+    }
+
+    // Items are at least one byte each
+    in_variable_data(table, pointer) && length <= end - pointer
+}
+
+/// Rows are indices into tables, which are rarely this large
+const MAX_ROW_INDEX: u64 = 1 << 20;
+
+fn is_foreignrow(table: &DatFile, bytes: &[u8]) -> bool {
+    let (row, unused) = bytes.split_at(table.layout.pointer_width);
+    bytes.iter().all(|b| *b == 0xfe) || (unused.iter().all(|b| *b == 0) && is_row(table, row))
+}
+
+fn is_row(table: &DatFile, bytes: &[u8]) -> bool {
+    bytes.iter().all(|b| *b == 0xfe) || table.layout.read_pointer(bytes) < MAX_ROW_INDEX
+}
+
+fn is_float(bytes: &[u8]) -> bool {
+    let value = f32::from_le_bytes(bytes.try_into().unwrap());
+
+    // Small integers show up as subnormal floats
+    value == 0. || (value.is_normal() && (1e-5..1e9).contains(&value.abs()))
+}
+
+/// Guess column types for a byte range by greedily matching the most distinctive types first
+fn suggest_columns(table: &DatFile, range: Range<usize>) -> Vec<ColumnSuggestion> {
+    let mut suggestions = vec![];
+    if table.rows.is_empty() {
+        return suggestions;
+    }
+
+    // Fraction of rows with a value other than zero / null
+    let evidence = |offset: usize, width: usize| {
+        let supporting = cells(table, offset, width)
+            .filter(|b| b.iter().any(|b| *b != 0) && b.iter().any(|b| *b != 0xfe))
+            .count();
+        supporting as f32 / table.rows.len() as f32
+    };
+
+    let (pointer_width, array_width, foreignrow_width) = (
+        table.layout.pointer_width,
+        table.layout.array_width(),
+        table.layout.foreignrow_width(),
+    );
+    let mut offset = range.start;
+    while offset < range.end {
+        let remaining = range.end - offset;
+        let all = |width: usize, check: &dyn Fn(&[u8]) -> bool| {
+            width <= remaining && cells(table, offset, width).all(check)
+        };
+
+        let (width, column_type, array) = if all(pointer_width, &|b| is_string(table, b)) {
+            (pointer_width, "string", false)
+        } else if all(array_width, &|b| is_array(table, b)) && evidence(offset, pointer_width) > 0.
+        {
+            // Can't tell what the array holds
+            (array_width, "array", true)
+        } else if all(foreignrow_width, &|b| is_foreignrow(table, b))
+            && evidence(offset, foreignrow_width) > 0.
+        {
+            (foreignrow_width, "foreignrow", false)
+        } else if all(pointer_width, &|b| is_row(table, b)) && evidence(offset, pointer_width) > 0.
+        {
+            (pointer_width, "row", false)
+        } else if all(4, &is_float) && evidence(offset, 4) > 0. {
+            (4, "f32", false)
+        } else if all(1, &|b| b[0] <= 1) && !all(4, &|b| b[1..] == [0; 3]) {
+            // A 0/1 byte followed by zeros is more likely a small integer
+            (1, "bool", false)
+        } else if remaining >= 4 {
+            (4, "i32", false)
+        } else if remaining >= 2 {
+            (2, "i16", false)
+        } else {
+            // Nothing sensible fits, likely padding or a misaligned schema
+            (1, "unknown", false)
+        };
+
+        suggestions.push(ColumnSuggestion {
+            offset,
+            width,
+            column_type,
+            array,
+            evidence: evidence(offset, width),
+        });
+        offset += width;
+    }
+
+    suggestions
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::dat::{schema::ColumnSchema, writer::write_json_rows};
+
+    #[test]
+    fn trailing_columns() {
+        let full = DatTableSchema::new(
+            "Test",
+            [
+                ColumnSchema::new("Id", "string"),
+                ColumnSchema::new("Name", "string"),
+                ColumnSchema::new("Weight", "f32"),
+            ],
+        );
+
+        let rows = [
+            json!({"Id": "A", "Name": "First", "Weight": 1.5}),
+            json!({"Id": "B", "Name": "Second", "Weight": 100.0}),
+        ];
+        let table = write_json_rows(&rows, &full).unwrap();
+
+        let report = validate_schema(&table, &full);
+        assert!(report.is_valid());
+
+        // Only the first column is known
+        let mut partial = full.clone();
+        partial.columns.truncate(1);
+
+        let report = validate_schema(&table, &partial);
+        assert_eq!(report.unclaimed, Some(8..20));
+        let types = report
+            .suggestions
+            .iter()
+            .map(|s| (s.offset, s.column_type))
+            .collect::<Vec<_>>();
+        assert_eq!(types, [(8, "string"), (16, "f32")]);
+    }
+}
@@ -0,0 +1,435 @@
+use std::collections::HashMap;
+
+use arrow_array::{
+    Array, BooleanArray, Float32Array, Int16Array, Int32Array, ListArray, RecordBatch, StringArray,
+    UInt16Array, UInt32Array, UInt64Array,
+};
+use arrow_schema::DataType;
+use serde_json::{Number, Value};
+
+use crate::{
+    dat::{
+        schema::{ColumnSchema, DatTableSchema},
+        table_view::{ColResult, DatColumnError, DatError, DatResult},
+    },
+    file_parsers::dat::types::{DatFile, DatLayout},
+};
+
+/// Separates the fixed-width rows from the variable data section. Pointers into the variable data
+/// section are relative to the start of the marker.
+const MARKER: [u8; 8] = [0xBB; 8];
+
+/// Methods for writing Dat tables
+impl DatFile {
+    /// Serialise into the on-disk format, which is the same for every variant
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(
+            4 + self.rows.len() * self.width() + MARKER.len() + self.variable_data.len(),
+        );
+
+        bytes.extend_from_slice(&(self.rows.len() as u32).to_le_bytes());
+        self.rows
+            .iter()
+            .for_each(|row| bytes.extend_from_slice(row));
+        bytes.extend_from_slice(&MARKER);
+        bytes.extend_from_slice(&self.variable_data);
+
+        bytes
+    }
+}
+
+/// Builds up the variable data section, de-duplicating strings
+#[derive(Default)]
+struct VariableData {
+    bytes: Vec<u8>,
+    /// String -> pointer
+    strings: HashMap<String, u64>,
+}
+
+impl VariableData {
+    /// Pointer to the current end of the section
+    fn pointer(&self) -> u64 {
+        (self.bytes.len() + MARKER.len()) as u64
+    }
+
+    /// Write a null-terminated UTF-16 string, returning its pointer
+    fn push_string(&mut self, string: &str) -> u64 {
+        if let Some(&pointer) = self.strings.get(string) {
+            return pointer;
+        }
+
+        let pointer = self.pointer();
+        string
+            .encode_utf16()
+            .for_each(|c| self.bytes.extend_from_slice(&c.to_le_bytes()));
+        // Strings are terminated by a 32-bit null
+        self.bytes.extend_from_slice(&[0; 4]);
+
+        self.strings.insert(string.to_owned(), pointer);
+        pointer
+    }
+
+    /// Write the encoded items of an array, returning its pointer
+    fn push_array(&mut self, items: &[u8]) -> u64 {
+        let pointer = self.pointer();
+        self.bytes.extend_from_slice(items);
+
+        pointer
+    }
+}
+
+fn unexpected(column: &ColumnSchema, value: &Value) -> DatColumnError {
+    DatColumnError::UnexpectedValue {
+        column_type: column.column_type.clone(),
+        value: value.to_string(),
+    }
+}
+
+/// Encode a single (non-array) value, interning any strings into the variable data section
+fn encode_item(
+    column: &ColumnSchema,
+    value: &Value,
+    variable_data: &mut VariableData,
+    out: &mut Vec<u8>,
+) -> ColResult<()> {
+    let err = || unexpected(column, value);
+
+    let as_u64 = || value.as_u64().ok_or_else(err);
+    let as_i64 = || value.as_i64().ok_or_else(err);
+
+    match column.column_type.as_str() {
+        "string" => {
+            let string = match value {
+                Value::String(s) => s.as_str(),
+                // Null strings are stored as empty strings
+                Value::Null => "",
+                _ => return Err(err()),
+            };
+            let pointer = variable_data.push_string(string);
+            out.extend_from_slice(&pointer.to_le_bytes());
+        }
+
+        "foreignrow" => match value {
+            Value::Null => out.extend_from_slice(&[0xfe; 16]),
+            _ => out.extend_from_slice(&(as_u64()? as u128).to_le_bytes()),
+        },
+        "row" => match value {
+            Value::Null => out.extend_from_slice(&[0xfe; 8]),
+            _ => out.extend_from_slice(&as_u64()?.to_le_bytes()),
+        },
+
+        "enumrow" | "u32" => {
+            let v = u32::try_from(as_u64()?).map_err(|_| err())?;
+            out.extend_from_slice(&v.to_le_bytes());
+        }
+        "i32" => {
+            let v = i32::try_from(as_i64()?).map_err(|_| err())?;
+            out.extend_from_slice(&v.to_le_bytes());
+        }
+        "u16" => {
+            let v = u16::try_from(as_u64()?).map_err(|_| err())?;
+            out.extend_from_slice(&v.to_le_bytes());
+        }
+        "i16" => {
+            let v = i16::try_from(as_i64()?).map_err(|_| err())?;
+            out.extend_from_slice(&v.to_le_bytes());
+        }
+        "f32" => {
+            let v = match value {
+                // JSON has no NaN, so they're nulls, see arrow_to_json
+                Value::Null => f32::NAN,
+                _ => value.as_f64().ok_or_else(err)? as f32,
+            };
+            out.extend_from_slice(&v.to_le_bytes());
+        }
+        "bool" => {
+            let v = value.as_bool().ok_or_else(err)?;
+            out.push(v as u8);
+        }
+
+        _ => {
+            return Err(DatColumnError::UnknownColumnType(Box::new(
+                column.to_owned(),
+            )));
+        }
+    }
+
+    Ok(())
+}
+
+/// Encode a single cell into the row. Returns the offset within the row of an empty array's
+/// pointer, which can only be filled in once the variable data section is complete.
+fn encode_cell(
+    column: &ColumnSchema,
+    value: &Value,
+    variable_data: &mut VariableData,
+    row: &mut Vec<u8>,
+) -> ColResult<Option<usize>> {
+    match (column.array, column.interval) {
+        // Array
+        (true, false) => {
+            let items = match value {
+                Value::Array(items) => items.as_slice(),
+                Value::Null => &[],
+                _ => return Err(unexpected(column, value)),
+            };
+
+            // Array of "array" is an unknown data type, which can only be written empty
+            if column.column_type == "array" && !items.is_empty() {
+                return Err(DatColumnError::UnknownArrayType);
+            }
+
+            row.extend_from_slice(&(items.len() as u64).to_le_bytes());
+            if items.is_empty() {
+                let offset = row.len();
+                row.extend_from_slice(&[0; 8]);
+                return Ok(Some(offset));
+            }
+
+            // Strings need to be written before the array so the items stay contiguous
+            let mut encoded = vec![];
+            for item in items {
+                encode_item(column, item, variable_data, &mut encoded)?;
+            }
+            let pointer = variable_data.push_array(&encoded);
+            row.extend_from_slice(&pointer.to_le_bytes());
+        }
+
+        // Interval
+        (false, true) => match value {
+            Value::Array(items) if items.len() == 2 => {
+                for item in items {
+                    encode_item(column, item, variable_data, row)?;
+                }
+            }
+            _ => return Err(unexpected(column, value)),
+        },
+
+        // Scalar
+        (false, false) => encode_item(column, value, variable_data, row)?,
+
+        (true, true) => return Err(DatColumnError::ArrayInterval(Box::new(column.to_owned()))),
+    }
+
+    Ok(None)
+}
+
+/// Encode rows of JSON objects (column name -> value) into a .datc64 table using the given schema.
+///
+/// Values use the same representation as [parse_table](super::table::parse_table): references
+/// are raw row indices, arrays and intervals are JSON arrays, null strings are written as empty
+/// strings and null floats as NaN.
+pub fn write_json_rows(rows: &[Value], schema: &DatTableSchema) -> DatResult<DatFile> {
+    let columns = schema.enumerate().collect::<Vec<_>>();
+
+    let mut variable_data = VariableData::default();
+    // Row index, offset in row
+    let mut empty_arrays = vec![];
+
+    let mut encoded_rows = rows
+        .iter()
+        .enumerate()
+        .map(|(i, row)| {
+            let mut encoded = vec![];
+
+            for (name, column) in &columns {
+                let value = row
+                    .get(name)
+                    .ok_or_else(|| DatError::MissingColumn(name.clone()))?;
+
+                let empty_array = encode_cell(column, value, &mut variable_data, &mut encoded)
+                    .map_err(|e| DatError::Column {
+                        column: Box::new((*column).to_owned()),
+                        source: e,
+                    })?;
+                empty_arrays.extend(empty_array.map(|offset| (i, offset)));
+            }
+
+            Ok(encoded)
+        })
+        .collect::<DatResult<Vec<_>>>()?;
+
+    // Empty arrays point to the end of the variable data section
+    let end = variable_data.pointer().to_le_bytes();
+    for (row, offset) in empty_arrays {
+        encoded_rows[row][offset..offset + 8].copy_from_slice(&end);
+    }
+
+    Ok(DatFile {
+        rows: encoded_rows,
+        variable_data: variable_data.bytes,
+        layout: DatLayout::DATC64,
+    })
+}
+
+/// Read a single cell from an Arrow array as JSON
+fn arrow_to_json(array: &dyn Array, index: usize) -> Value {
+    if array.is_null(index) {
+        return Value::Null;
+    }
+
+    let any = array.as_any();
+    match array.data_type() {
+        DataType::Utf8 => {
+            let array = any.downcast_ref::<StringArray>().unwrap();
+            Value::String(array.value(index).to_owned())
+        }
+        DataType::Boolean => Value::Bool(any.downcast_ref::<BooleanArray>().unwrap().value(index)),
+        DataType::UInt64 => any
+            .downcast_ref::<UInt64Array>()
+            .unwrap()
+            .value(index)
+            .into(),
+        DataType::UInt32 => any
+            .downcast_ref::<UInt32Array>()
+            .unwrap()
+            .value(index)
+            .into(),
+        DataType::UInt16 => any
+            .downcast_ref::<UInt16Array>()
+            .unwrap()
+            .value(index)
+            .into(),
+        DataType::Int32 => any
+            .downcast_ref::<Int32Array>()
+            .unwrap()
+            .value(index)
+            .into(),
+        DataType::Int16 => any
+            .downcast_ref::<Int16Array>()
+            .unwrap()
+            .value(index)
+            .into(),
+        DataType::Float32 => {
+            let value = any.downcast_ref::<Float32Array>().unwrap().value(index);
+            Number::from_f64(value as f64).map_or(Value::Null, Value::Number)
+        }
+        DataType::List(_) => {
+            let values = any.downcast_ref::<ListArray>().unwrap().value(index);
+            Value::Array(
+                (0..values.len())
+                    .map(|i| arrow_to_json(values.as_ref(), i))
+                    .collect(),
+            )
+        }
+        // Anything else can't have come from a dat table
+        _ => Value::Null,
+    }
+}
+
+/// Encode a RecordBatch into a table using the given schema. This is the inverse of
+/// [parse_table](super::table::parse_table), with columns matched up by name.
+pub fn write_table(batch: &RecordBatch, schema: &DatTableSchema) -> DatResult<DatFile> {
+    let columns = schema
+        .column_names()
+        .map(|name| {
+            batch
+                .column_by_name(&name)
+                .map(|array| (name.clone(), array))
+                .ok_or(DatError::MissingColumn(name))
+        })
+        .collect::<DatResult<Vec<_>>>()?;
+
+    let rows = (0..batch.num_rows())
+        .map(|i| {
+            let row = columns
+                .iter()
+                .map(|(name, array)| (name.clone(), arrow_to_json(array.as_ref(), i)))
+                .collect();
+            Value::Object(row)
+        })
+        .collect::<Vec<_>>();
+
+    write_json_rows(&rows, schema)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::{
+        dat::table::parse_table,
+        file_parsers::{FileParser, dat::DatParser},
+    };
+
+    #[test]
+    fn round_trip() {
+        let schema = DatTableSchema::new(
+            "Test",
+            [
+                ColumnSchema::new("Id", "string"),
+                ColumnSchema::new("Tags", "string").with_array(),
+                ColumnSchema::new("Foreign", "foreignrow"),
+                ColumnSchema::new("Rows", "row").with_array(),
+                ColumnSchema::new("Enum", "enumrow"),
+                ColumnSchema::new("Range", "i32").with_interval(),
+                ColumnSchema::new("Weights", "f32").with_array(),
+                ColumnSchema::new("Flag", "bool"),
+                ColumnSchema::new("Small", "i16"),
+                ColumnSchema::new("Count", "u16"),
+            ],
+        );
+
+        let rows = [
+            json!({
+                "Id": "First", "Tags": ["a", "b"], "Foreign": 3, "Rows": [0, 1], "Enum": 2,
+                "Range": [-5, 5], "Weights": [0.5, 1.5], "Flag": true, "Small": -2, "Count": 7,
+            }),
+            json!({
+                "Id": "Second", "Tags": ["b"], "Foreign": null, "Rows": [], "Enum": 0,
+                "Range": [0, 0], "Weights": [], "Flag": false, "Small": 0, "Count": 0,
+            }),
+        ];
+
+        let bytes = write_json_rows(&rows, &schema).unwrap().to_bytes();
+
+        // Parse -> write should reproduce the same bytes
+        let table = DatParser.parse(&bytes).unwrap();
+        assert_eq!(table.to_bytes(), bytes);
+
+        let batch = parse_table(&table, &schema).unwrap();
+        let rewritten = write_table(&batch, &schema).unwrap().to_bytes();
+        assert_eq!(rewritten, bytes);
+    }
+
+    #[test]
+    fn hand_built() {
+        let schema = DatTableSchema::new(
+            "Test",
+            [
+                ColumnSchema::new("Id", "string"),
+                ColumnSchema::new("Alias", "string"),
+                ColumnSchema::new("Weights", "f32").with_array(),
+                ColumnSchema::new("Value", "f32"),
+            ],
+        );
+
+        // "ab" at 8, shared by both string columns of both rows, then [1.0, NaN] at 16
+        let mut variable_data = vec![b'a', 0, b'b', 0, 0, 0, 0, 0];
+        variable_data.extend_from_slice(&1.0_f32.to_le_bytes());
+        variable_data.extend_from_slice(&f32::NAN.to_le_bytes());
+
+        let row = |weights: (u64, u64), value: f32| {
+            [8_u64, 8, weights.0, weights.1]
+                .iter()
+                .flat_map(|v| v.to_le_bytes())
+                .chain(value.to_le_bytes())
+                .collect::<Vec<_>>()
+        };
+        let bytes = DatFile {
+            // Empty arrays point at the end of the variable data
+            rows: vec![row((2, 16), f32::NAN), row((0, 24), 2.0)],
+            variable_data,
+            layout: DatLayout::DATC64,
+        }
+        .to_bytes();
+
+        let table = DatParser.parse(&bytes).unwrap();
+        assert_eq!(table.to_bytes(), bytes);
+
+        let batch = parse_table(&table, &schema).unwrap();
+        let rewritten = write_table(&batch, &schema).unwrap().to_bytes();
+        assert_eq!(rewritten, bytes);
+    }
+}
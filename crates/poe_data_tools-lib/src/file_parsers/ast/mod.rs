@@ -1,9 +1,20 @@
 pub mod parser;
 pub mod types;
-use parser::parse_ast_bytes;
-use types::ASTFile;
+use std::borrow::Cow;
 
-use crate::file_parsers::{FileParser, VersionedFile, error::Result};
+use parser::{parse_ast_bytes, parse_tracks_bytes};
+use types::{ASTFile, Animation, Track};
+
+use crate::file_parsers::{
+    FileParser, VersionedFile,
+    error::{ParseError, Result},
+};
+
+#[derive(Debug, thiserror::Error)]
+enum ASTError {
+    #[error("no track data found for animation {0:?}")]
+    MissingAnimationData(String),
+}
 
 pub struct ASTParser;
 
@@ -20,3 +31,24 @@ impl VersionedFile for ASTFile {
         Some(self.header.version as u32)
     }
 }
+
+impl ASTFile {
+    /// Track data for an animation. Newer versions store this in the embedded bundle, which is
+    /// decoded on demand.
+    pub fn tracks<'a>(&'a self, animation: &'a Animation) -> Result<Cow<'a, [Track]>> {
+        if let Some(tracks) = &animation.data {
+            return Ok(Cow::Borrowed(tracks));
+        }
+
+        let (Some(location), Some(bundle)) = (&animation.data_location, &self.bundle) else {
+            return Err(ParseError::other(ASTError::MissingAnimationData(
+                animation.name.clone(),
+            )));
+        };
+
+        let bytes = bundle
+            .read_range(location.offset as usize, location.length as usize)
+            .map_err(ParseError::other)?;
+        parse_tracks_bytes(&bytes, self.header.version, animation.num_tracks).map(Cow::Owned)
+    }
+}
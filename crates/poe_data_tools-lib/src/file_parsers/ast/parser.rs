@@ -274,3 +274,17 @@ pub fn parse_ast_bytes(mut input: &[u8]) -> Result<ASTFile> {
 
     Ok(ast_file)
 }
+
+/// Parse the track data for a single animation, as stored in the bundle sub-file of v8+ files
+pub fn parse_tracks_bytes(mut input: &[u8], version: u8, num_tracks: u8) -> Result<Vec<Track>> {
+    let mut parser = track(ForwardRef::with_value(version))
+        .repeat_vec(ForwardRef::with_value(num_tracks))
+        .trace("tracks");
+
+    let (tracks, _) = parser
+        .parse(&mut input)
+        .to_parse_error()
+        .with_maybe_version(Some(version as u32))?;
+
+    Ok(tracks)
+}
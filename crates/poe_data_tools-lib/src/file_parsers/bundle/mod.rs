@@ -2,6 +2,7 @@ use crate::file_parsers::{FileParser, VersionedFile, error::Result};
 
 pub mod parser;
 pub mod types;
+pub mod writer;
 use parser::parse_bundle_bytes;
 use types::BundleFile;
 
@@ -90,6 +90,32 @@ pub fn bundle() -> impl U8Parser<Output = BundleFile> {
         .trace("bundle")
 }
 
+/// Header and the compressed size of each block, without the blocks themselves
+fn bundle_head() -> impl U8Parser<Output = (HeadPayload, Vec<u32>)> {
+    let head = header().store();
+    let block_sizes = u32::LE
+        .repeat_vec(head.output().map(|h| h.block_count))
+        .trace("block_sizes");
+
+    (head, block_sizes).trace("bundle_head")
+}
+
+pub fn parse_bundle_header(mut input: &[u8]) -> Result<HeadPayload> {
+    let mut parser = header();
+
+    let (head, _) = parser.parse(&mut input).to_parse_error()?;
+
+    Ok(head)
+}
+
+pub fn parse_bundle_head(mut input: &[u8]) -> Result<(HeadPayload, Vec<u32>)> {
+    let mut parser = bundle_head();
+
+    let (head, _) = parser.parse(&mut input).to_parse_error()?;
+
+    Ok(head)
+}
+
 pub fn parse_bundle_bytes(mut input: &[u8]) -> Result<BundleFile> {
     let mut parser = bundle();
 
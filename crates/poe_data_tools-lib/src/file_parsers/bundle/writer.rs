@@ -0,0 +1,85 @@
+use super::types::{BundleFile, FirstFileEncode, HeadPayload};
+
+/// Uncompressed size of each block, as used by the game's bundles
+pub const BLOCK_SIZE: usize = 0x40000;
+
+/// Oodle block header marking its contents as stored as-is, using the Kraken decoder. Lets bundles
+/// be written without the proprietary encoder.
+const UNCOMPRESSED_BLOCK_HEADER: [u8; 2] = [0xcc, 0x06];
+
+/// Size of the header following the preamble, excluding the block sizes
+pub(crate) const HEAD_PAYLOAD_SIZE: u32 = 48;
+
+impl FirstFileEncode {
+    /// Identifier stored in the header
+    fn id(&self) -> u32 {
+        use FirstFileEncode::*;
+        match self {
+            Kraken6 => 8,
+            MermaidA => 9,
+            Bitknit => 12,
+            LeviathanC => 13,
+        }
+    }
+}
+
+/// Methods for writing bundles
+impl BundleFile {
+    /// Wrap data in a bundle without compressing it. Each block holds up to [BLOCK_SIZE] bytes
+    /// behind a header telling the decoder to copy it straight through.
+    pub fn from_uncompressed(data: &[u8]) -> Self {
+        let blocks = data
+            .chunks(BLOCK_SIZE)
+            .map(|chunk| [UNCOMPRESSED_BLOCK_HEADER.as_slice(), chunk].concat())
+            .collect::<Vec<_>>();
+        let payload_size = blocks.iter().map(Vec::len).sum::<usize>();
+
+        // Preamble repeats the sizes, followed by the size of the rest of the header
+        let mut unk1 = [0; 12];
+        unk1[..4].copy_from_slice(&(data.len() as u32).to_le_bytes());
+        unk1[4..8].copy_from_slice(&(payload_size as u32).to_le_bytes());
+        unk1[8..].copy_from_slice(&(HEAD_PAYLOAD_SIZE + 4 * blocks.len() as u32).to_le_bytes());
+
+        Self {
+            head: HeadPayload {
+                unk1,
+                first_file_encode: FirstFileEncode::Kraken6,
+                unk2: 1_u32.to_le_bytes(),
+                uncompressed_size: data.len() as u64,
+                total_payload_size: payload_size as u64,
+                block_count: blocks.len() as u32,
+                uncompressed_block_granularity: BLOCK_SIZE as u32,
+                unk3: [0; 16],
+            },
+            blocks,
+        }
+    }
+
+    /// Serialise into the .bundle.bin on-disk layout
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let head = &self.head;
+        let mut bytes = Vec::with_capacity(
+            12 + HEAD_PAYLOAD_SIZE as usize
+                + 4 * self.blocks.len()
+                + head.total_payload_size as usize,
+        );
+
+        bytes.extend_from_slice(&head.unk1);
+        bytes.extend_from_slice(&head.first_file_encode.id().to_le_bytes());
+        bytes.extend_from_slice(&head.unk2);
+        bytes.extend_from_slice(&head.uncompressed_size.to_le_bytes());
+        bytes.extend_from_slice(&head.total_payload_size.to_le_bytes());
+        bytes.extend_from_slice(&(self.blocks.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(&head.uncompressed_block_granularity.to_le_bytes());
+        bytes.extend_from_slice(&head.unk3);
+
+        self.blocks
+            .iter()
+            .for_each(|block| bytes.extend_from_slice(&(block.len() as u32).to_le_bytes()));
+        self.blocks
+            .iter()
+            .for_each(|block| bytes.extend_from_slice(block));
+
+        bytes
+    }
+}
@@ -0,0 +1,79 @@
+use bytes::Bytes;
+
+use super::types::{BundleIndexFile, BundleInfo, FileInfo};
+use crate::{
+    file_parsers::bundle::types::BundleFile,
+    hasher::murmur64a::{BuildHasherEx, BuildMurmurHash64A},
+    path::encode_paths,
+};
+
+const HASHER: BuildMurmurHash64A = BuildMurmurHash64A { seed: 0x1337b33f };
+
+fn write_u32(bytes: &mut Vec<u8>, value: u32) {
+    bytes.extend_from_slice(&value.to_le_bytes());
+}
+
+impl FileInfo {
+    /// Locate a file within a bundle, hashing its path the same way lookups do
+    pub fn new(path: &str, bundle_index: u32, offset: u32, size: u32) -> Self {
+        Self {
+            hash: HASHER.hash_one_str(&path.to_lowercase()),
+            bundle_index,
+            offset,
+            size,
+        }
+    }
+}
+
+/// Methods for writing bundle indices
+impl BundleIndexFile {
+    /// Build an index over files stored in the given bundles, encoding their paths. Each file's
+    /// [FileInfo] refers to its bundle by position in `bundles`.
+    pub fn new<'a>(
+        bundles: Vec<BundleInfo>,
+        files: impl IntoIterator<Item = (&'a str, FileInfo)>,
+    ) -> Self {
+        let (paths, files): (Vec<_>, Vec<_>) = files.into_iter().unzip();
+        let (paths, path_rep_bundle) = encode_paths(paths);
+
+        Self {
+            bundles,
+            files,
+            paths,
+            path_rep_bundle: Bytes::from(path_rep_bundle),
+        }
+    }
+
+    /// Serialise into the contents of the _.index.bin bundle. The path blob is stored
+    /// uncompressed.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![];
+
+        write_u32(&mut bytes, self.bundles.len() as u32);
+        for bundle in &self.bundles {
+            write_u32(&mut bytes, bundle.name.len() as u32);
+            bytes.extend_from_slice(bundle.name.as_bytes());
+            write_u32(&mut bytes, bundle.uncompressed_size);
+        }
+
+        write_u32(&mut bytes, self.files.len() as u32);
+        for file in &self.files {
+            bytes.extend_from_slice(&file.hash.to_le_bytes());
+            write_u32(&mut bytes, file.bundle_index);
+            write_u32(&mut bytes, file.offset);
+            write_u32(&mut bytes, file.size);
+        }
+
+        write_u32(&mut bytes, self.paths.len() as u32);
+        for path in &self.paths {
+            bytes.extend_from_slice(&path.hash.to_le_bytes());
+            write_u32(&mut bytes, path.offset);
+            write_u32(&mut bytes, path.size);
+            write_u32(&mut bytes, path.recursive_size);
+        }
+
+        bytes.extend_from_slice(&BundleFile::from_uncompressed(&self.path_rep_bundle).to_bytes());
+
+        bytes
+    }
+}
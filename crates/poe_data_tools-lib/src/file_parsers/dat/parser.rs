@@ -36,6 +36,7 @@ pub fn dat<'a>() -> impl WinnowParser<&'a [u8], DatFile> {
             DatFile {
                 rows,
                 variable_data: variable_data.to_vec(),
+                layout: DatLayout::default(),
             }
         })
 }
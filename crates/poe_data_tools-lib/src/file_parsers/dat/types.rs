@@ -1,4 +1,115 @@
+use std::path::Path;
+
 pub struct DatFile {
     pub rows: Vec<Vec<u8>>,
     pub variable_data: Vec<u8>,
+    /// How values within the rows are laid out, which depends on the file's variant
+    pub layout: DatLayout,
+}
+
+impl DatFile {
+    /// Set the layout used to interpret the rows, builder style
+    pub fn with_layout(mut self, layout: DatLayout) -> Self {
+        self.layout = layout;
+        self
+    }
+}
+
+/// Encoding of strings within the variable data section
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StringEncoding {
+    /// Null-terminated UTF-16, used by .dat, .dat64 and .datc64
+    Utf16,
+    /// Null-terminated UTF-32, used by .datl and .datl64
+    Utf32,
+}
+
+/// Layout of the values within a table's rows. The variants of the format differ only in the
+/// width of pointers and how strings are encoded, so the same schema applies to all of them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DatLayout {
+    /// Width in bytes of pointers into the variable data section, array lengths and row indices
+    pub pointer_width: usize,
+    pub encoding: StringEncoding,
+}
+
+impl Default for DatLayout {
+    fn default() -> Self {
+        Self::DATC64
+    }
+}
+
+impl DatLayout {
+    /// 32-bit pointers, UTF-16 strings
+    pub const DAT: Self = Self {
+        pointer_width: 4,
+        encoding: StringEncoding::Utf16,
+    };
+    /// 64-bit pointers, UTF-16 strings
+    pub const DAT64: Self = Self {
+        pointer_width: 8,
+        encoding: StringEncoding::Utf16,
+    };
+    /// 32-bit pointers, UTF-32 strings
+    pub const DATL: Self = Self {
+        pointer_width: 4,
+        encoding: StringEncoding::Utf32,
+    };
+    /// 64-bit pointers, UTF-32 strings
+    pub const DATL64: Self = Self {
+        pointer_width: 8,
+        encoding: StringEncoding::Utf32,
+    };
+    /// The current format, laid out the same as .dat64
+    pub const DATC64: Self = Self::DAT64;
+
+    /// Extensions of the table variants, newest first. Older game versions store each table in
+    /// several of them.
+    pub const EXTENSIONS: [&str; 5] = ["datc64", "dat64", "dat", "datl64", "datl"];
+
+    /// Layout of a variant from its file extension, eg. `datl64`
+    pub fn from_extension(extension: &str) -> Option<Self> {
+        let layout = match extension.to_lowercase().as_str() {
+            "dat" => Self::DAT,
+            "dat64" => Self::DAT64,
+            "datl" => Self::DATL,
+            "datl64" => Self::DATL64,
+            "datc64" => Self::DATC64,
+            _ => return None,
+        };
+
+        Some(layout)
+    }
+
+    /// Layout of a table from its path, if it has one of the table extensions
+    pub fn from_path(path: impl AsRef<Path>) -> Option<Self> {
+        Self::from_extension(path.as_ref().extension()?.to_str()?)
+    }
+
+    /// Width of a reference to a row in another table: the row index followed by a second,
+    /// unused value of the same width
+    pub fn foreignrow_width(&self) -> usize {
+        self.pointer_width * 2
+    }
+
+    /// Width of an array: its length followed by a pointer to its items
+    pub fn array_width(&self) -> usize {
+        self.pointer_width * 2
+    }
+
+    /// Width of each code unit of a string, and so of its null-terminator
+    pub fn char_width(&self) -> usize {
+        match self.encoding {
+            StringEncoding::Utf16 => 2,
+            StringEncoding::Utf32 => 4,
+        }
+    }
+
+    /// Read a pointer, array length or row index
+    pub fn read_pointer(&self, bytes: &[u8]) -> u64 {
+        match self.pointer_width {
+            4 => u32::from_le_bytes(bytes[..4].try_into().unwrap()) as u64,
+            _ => u64::from_le_bytes(bytes[..8].try_into().unwrap()),
+        }
+    }
 }
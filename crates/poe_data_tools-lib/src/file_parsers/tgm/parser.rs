@@ -300,7 +300,11 @@ pub fn tgm_parser() -> (impl U8Parser<Output = TGMFile>, ForwardRef<u32>) {
         section,
         EoF,
     )
-        .map_silent(|(version, bbox, _section, _)| TGMFile { version, bbox })
+        .map_silent(|(version, bbox, section, _)| TGMFile {
+            version,
+            bbox,
+            section,
+        })
         .trace("tgm_file");
 
     (parser, version_u32)
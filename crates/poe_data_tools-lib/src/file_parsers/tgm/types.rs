@@ -6,6 +6,7 @@ use crate::file_parsers::dolm::types::{Dolm, DolmVertex, IndexBuffer};
 pub struct TGMFile {
     pub version: u8,
     pub bbox: [f32; 6],
+    pub section: Section,
 }
 
 #[derive(Debug, Clone, Serialize)]
@@ -0,0 +1,258 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    ops::Range,
+    sync::Mutex,
+};
+
+use bytes::{Bytes, BytesMut};
+use rayon::iter::{IndexedParallelIterator, IntoParallelIterator, ParallelIterator};
+
+use super::Result;
+use crate::file_parsers::{bundle::types::BundleFile, bundle_index::types::FileInfo};
+
+/// Default memory budget for decoded blocks
+pub const DEFAULT_CAPACITY: usize = 64 * 1024 * 1024;
+
+/// (bundle index, block index)
+type BlockKey = (u32, usize);
+
+/// Counters describing how well the cache is doing
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    /// Blocks served from the cache
+    pub hits: u64,
+    /// Blocks that had to be decoded
+    pub misses: u64,
+    /// Blocks dropped to stay within capacity
+    pub evictions: u64,
+    /// Bytes of decoded blocks currently held
+    pub size: usize,
+    /// Max bytes of decoded blocks held
+    pub capacity: usize,
+}
+
+impl CacheStats {
+    /// Fraction of block lookups served from the cache
+    pub fn hit_rate(&self) -> f64 {
+        let total = self.hits + self.misses;
+        if total == 0 {
+            0.
+        } else {
+            self.hits as f64 / total as f64
+        }
+    }
+}
+
+#[derive(Default)]
+struct CacheState {
+    /// Decoded block and when it was last used
+    blocks: HashMap<BlockKey, (Bytes, u64)>,
+    /// Last use -> block, oldest first
+    recency: BTreeMap<u64, BlockKey>,
+    tick: u64,
+    /// Block size of each bundle seen so far, so fully cached files don't need their bundle loaded
+    block_sizes: HashMap<u32, usize>,
+    stats: CacheStats,
+}
+
+impl CacheState {
+    /// Look up a block, marking it as recently used
+    fn get(&mut self, key: BlockKey) -> Option<Bytes> {
+        let (data, last_used) = self.blocks.get_mut(&key)?;
+
+        self.recency.remove(last_used);
+        self.tick += 1;
+        *last_used = self.tick;
+        self.recency.insert(self.tick, key);
+
+        Some(data.clone())
+    }
+
+    fn insert(&mut self, key: BlockKey, data: Bytes) {
+        // Too big to ever fit, or already added by a concurrent read
+        if data.len() > self.stats.capacity || self.blocks.contains_key(&key) {
+            return;
+        }
+
+        while self.stats.size + data.len() > self.stats.capacity {
+            let Some((_, oldest)) = self.recency.pop_first() else {
+                break;
+            };
+            if let Some((evicted, _)) = self.blocks.remove(&oldest) {
+                self.stats.size -= evicted.len();
+                self.stats.evictions += 1;
+            }
+        }
+
+        self.tick += 1;
+        self.stats.size += data.len();
+        self.recency.insert(self.tick, key);
+        self.blocks.insert(key, (data, self.tick));
+    }
+}
+
+/// Memory-bounded LRU cache of decoded bundle blocks, shared by reads of individual files so
+/// reading several files from one bundle doesn't repeat the decompression. Batch reads decode
+/// whole bundles at once so bypass it.
+pub(crate) struct BlockCache {
+    state: Mutex<CacheState>,
+}
+
+impl Default for BlockCache {
+    fn default() -> Self {
+        Self::new(DEFAULT_CAPACITY)
+    }
+}
+
+/// Blocks covering a file
+fn block_range(file: &FileInfo, block_size: usize) -> Range<usize> {
+    let start = file.offset as usize;
+    start / block_size..(start + file.size as usize).div_ceil(block_size)
+}
+
+/// Stitch a file together from the blocks covering it
+fn assemble(blocks: &[Bytes], file: &FileInfo, block_size: usize) -> Bytes {
+    let start = file.offset as usize % block_size;
+    let end = start + file.size as usize;
+
+    match blocks {
+        [block] => block.slice(start..end),
+        blocks => {
+            let mut buf = BytesMut::with_capacity(blocks.len() * block_size);
+            blocks.iter().for_each(|b| buf.extend_from_slice(b));
+            buf.freeze().slice(start..end)
+        }
+    }
+}
+
+impl BlockCache {
+    /// Cache holding up to `capacity` bytes of decoded blocks. A capacity of 0 disables caching.
+    pub(crate) fn new(capacity: usize) -> Self {
+        let state = CacheState {
+            stats: CacheStats {
+                capacity,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        Self {
+            state: Mutex::new(state),
+        }
+    }
+
+    pub(crate) fn stats(&self) -> CacheStats {
+        self.lock().stats
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, CacheState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Read a file entirely from the cache, if all of its blocks are present
+    pub(crate) fn get(&self, file: &FileInfo) -> Option<Bytes> {
+        if file.size == 0 {
+            return Some(Bytes::new());
+        }
+
+        let mut state = self.lock();
+        let block_size = *state.block_sizes.get(&file.bundle_index)?;
+
+        let range = block_range(file, block_size);
+        let all_cached = range
+            .clone()
+            .all(|b| state.blocks.contains_key(&(file.bundle_index, b)));
+        if !all_cached {
+            return None;
+        }
+
+        let blocks = range
+            .map(|b| state.get((file.bundle_index, b)))
+            .collect::<Option<Vec<_>>>()?;
+        state.stats.hits += blocks.len() as u64;
+
+        Some(assemble(&blocks, file, block_size))
+    }
+
+    /// Read a file from its loaded bundle, decoding any blocks that aren't cached and caching them
+    pub(crate) fn read(&self, bundle: &BundleFile, file: &FileInfo) -> Result<Bytes> {
+        if self.lock().stats.capacity == 0 {
+            return bundle.read_range(file.offset as usize, file.size as usize);
+        }
+        if file.size == 0 {
+            return Ok(Bytes::new());
+        }
+
+        let block_size = bundle.head.uncompressed_block_granularity as usize;
+        let range = block_range(file, block_size);
+
+        let cached = {
+            let mut state = self.lock();
+            state.block_sizes.insert(file.bundle_index, block_size);
+
+            let cached = range
+                .clone()
+                .map(|b| state.get((file.bundle_index, b)))
+                .collect::<Vec<_>>();
+            let hits = cached.iter().flatten().count() as u64;
+            state.stats.hits += hits;
+            state.stats.misses += cached.len() as u64 - hits;
+
+            cached
+        };
+
+        // Decode the rest without holding the lock
+        let blocks = cached
+            .into_par_iter()
+            .zip(range.clone())
+            .map(|(cached, b)| match cached {
+                Some(data) => Ok((data, true)),
+                None => Ok((Bytes::from(bundle.read_block(b)?), false)),
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let mut state = self.lock();
+        for ((data, _), b) in blocks.iter().zip(range).filter(|((_, cached), _)| !cached) {
+            state.insert((file.bundle_index, b), data.clone());
+        }
+        drop(state);
+
+        let blocks = blocks.into_iter().map(|(data, _)| data).collect::<Vec<_>>();
+        Ok(assemble(&blocks, file, block_size))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::file_parsers::bundle::writer::BLOCK_SIZE;
+
+    #[test]
+    fn lru() {
+        let data = (0..3 * BLOCK_SIZE).map(|i| i as u8).collect::<Vec<_>>();
+        let bundle = BundleFile::from_uncompressed(&data);
+        let file = |offset: usize, size: usize| FileInfo::new("", 0, offset as u32, size as u32);
+
+        // Room for two blocks
+        let cache = BlockCache::new(2 * BLOCK_SIZE);
+
+        // Straddles the first two blocks
+        let straddling = file(BLOCK_SIZE - 8, 16);
+        assert_eq!(cache.get(&straddling), None);
+        let bytes = cache.read(&bundle, &straddling).unwrap();
+        assert_eq!(bytes, data[BLOCK_SIZE - 8..BLOCK_SIZE + 8]);
+        assert_eq!(cache.get(&straddling), Some(bytes));
+
+        // Pushes out the least recently used first block
+        cache.read(&bundle, &file(2 * BLOCK_SIZE, 4)).unwrap();
+        assert_eq!(cache.get(&file(0, 4)), None);
+        assert_eq!(
+            cache.get(&file(BLOCK_SIZE, 4)).unwrap(),
+            data[BLOCK_SIZE..BLOCK_SIZE + 4]
+        );
+
+        let stats = cache.stats();
+        assert_eq!((stats.hits, stats.misses, stats.evictions), (3, 3, 1));
+        assert_eq!(stats.size, 2 * BLOCK_SIZE);
+    }
+}
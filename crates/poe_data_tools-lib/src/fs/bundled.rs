@@ -0,0 +1,164 @@
+//! Async reads shared by the file systems built on a bundle index
+use std::{borrow::Cow, collections::HashMap};
+
+use bytes::Bytes;
+use futures::{StreamExt, stream::BoxStream};
+use iterators_extended::bucket::Bucket;
+
+use super::{Result, block_cache::BlockCache, error::FSError, tree::dir_prefix};
+use crate::{
+    file_parsers::{
+        bundle::types::BundleFile,
+        bundle_index::types::{BundleIndexFile, FileInfo},
+    },
+    fs::stat::FileStat,
+    hasher::murmur64a::{BuildHasherEx, BuildMurmurHash64A},
+    path::paths_under,
+};
+
+const HASHER: BuildMurmurHash64A = BuildMurmurHash64A { seed: 0x1337b33f };
+
+/// Max number of bundles being loaded at once
+const CONCURRENCY: usize = 16;
+
+/// Look up the index entry for a virtual file path
+fn lookup<'a>(
+    index: &'a BundleIndexFile,
+    lut: &HashMap<u64, usize>,
+    path: &str,
+) -> Result<&'a FileInfo> {
+    let hash = HASHER.hash_one_str(&path.to_lowercase());
+
+    lut.get(&hash)
+        .map(|i| &index.files[*i])
+        .ok_or_else(|| FSError::FileNotFound(path.to_owned()))
+}
+
+/// Metadata of a file from its index entry, given the size of its bundle if known
+pub(crate) fn stat(
+    index: &BundleIndexFile,
+    path: &str,
+    file: &FileInfo,
+    bundle_size: Option<u64>,
+) -> FileStat {
+    FileStat {
+        path: path.to_owned(),
+        size: file.size as u64,
+        hash: file.hash,
+        bundle: Some(index.bundles[file.bundle_index as usize].name.clone()),
+        offset: Some(file.offset as u64),
+        bundle_size,
+        sha256: None,
+    }
+}
+
+/// Lists paths within a directory, only building the paths of the index sections that may hold
+/// them
+pub(crate) fn walk<'a>(
+    index: &'a BundleIndexFile,
+    dir: &str,
+) -> Box<dyn Iterator<Item = String> + 'a> {
+    paths_under(&index.path_rep_bundle, &index.paths, &dir_prefix(dir))
+}
+
+/// Group files by the bundle they're stored in. Bundles serving the most files come first, so
+/// the bulk of a batch becomes available as early as possible, with smaller bundles breaking ties.
+pub(crate) fn group_by_bundle<T>(
+    index: &BundleIndexFile,
+    files: impl IntoIterator<Item = T>,
+    bundle_index: impl Fn(&T) -> u32,
+) -> Vec<(u32, Vec<T>)> {
+    let mut groups = files
+        .into_iter()
+        .fold(HashMap::<_, Vec<_>>::new(), |mut acc, file| {
+            acc.entry(bundle_index(&file)).or_default().push(file);
+            acc
+        })
+        .into_iter()
+        .collect::<Vec<_>>();
+
+    groups.sort_by_key(|(bundle_index, files)| {
+        let size = index.bundles[*bundle_index as usize].uncompressed_size;
+        (std::cmp::Reverse(files.len()), size)
+    });
+
+    groups
+}
+
+/// Read a single file, loading its bundle with the provided function unless all of the file's
+/// blocks are cached. The bundle loader is given the bundle's name as stored in the index.
+pub(crate) async fn read<F, Fut>(
+    index: &BundleIndexFile,
+    lut: &HashMap<u64, usize>,
+    cache: &BlockCache,
+    path: &str,
+    load_bundle: F,
+) -> Result<Bytes>
+where
+    F: FnOnce(String) -> Fut,
+    Fut: Future<Output = Result<BundleFile>>,
+{
+    let file = lookup(index, lut, path)?;
+    if let Some(bytes) = cache.get(file) {
+        return Ok(bytes);
+    }
+
+    let bundle = load_bundle(index.bundles[file.bundle_index as usize].name.clone()).await?;
+
+    cache.read(&bundle, file)
+}
+
+/// Read many files, loading each required bundle once. Files are yielded as soon as their bundle
+/// has been loaded, so order is not preserved.
+pub(crate) fn batch_read<'a, F, Fut>(
+    index: &'a BundleIndexFile,
+    lut: &'a HashMap<u64, usize>,
+    paths: &'a [impl AsRef<str> + Sync],
+    load_bundle: F,
+) -> BoxStream<'a, (Cow<'a, str>, Result<Bytes>)>
+where
+    F: Fn(String) -> Fut + Send + Sync + 'a,
+    Fut: Future<Output = Result<BundleFile>> + Send + 'a,
+{
+    // Get FileInfo's
+    let (fileinfos, errors) = paths
+        .iter()
+        .map(|path| {
+            let path = path.as_ref();
+            match lookup(index, lut, path) {
+                Ok(f) => Ok((path, f)),
+                Err(e) => Err((Cow::Borrowed(path), Err(e))),
+            }
+        })
+        .bucket_result();
+
+    // Batch them into their bundles
+    let fileinfos = group_by_bundle(index, fileinfos, |(_, f)| f.bundle_index);
+
+    let file_contents = futures::stream::iter(fileinfos)
+        .map(move |(bundle_index, files)| {
+            let bundle = load_bundle(index.bundles[bundle_index as usize].name.clone());
+
+            async move {
+                // Read the file contents
+                match bundle.await {
+                    Ok(b) => files
+                        .into_iter()
+                        .map(|(path, file)| {
+                            let res = b.read_range(file.offset as usize, file.size as usize);
+                            (Cow::Borrowed(path), res)
+                        })
+                        .collect::<Vec<_>>(),
+                    Err(e) => files
+                        .into_iter()
+                        .map(|(path, _)| (Cow::Borrowed(path), Err(e.clone())))
+                        .collect(),
+                }
+            }
+        })
+        .buffer_unordered(CONCURRENCY)
+        .flat_map(futures::stream::iter);
+
+    // Add on previous errors
+    futures::stream::iter(errors).chain(file_contents).boxed()
+}
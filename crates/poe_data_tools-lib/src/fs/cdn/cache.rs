@@ -0,0 +1,283 @@
+//! Inspecting and pruning the folders bundles are downloaded to
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    path::{Path, PathBuf},
+};
+
+use super::downloader::CacheFolder;
+use crate::fs::Result;
+
+/// Max depth of patch folders below the cache directory, eg. `<cache>/<cdn host>/<patch>`
+const MAX_DEPTH: usize = 4;
+
+/// A file downloaded from the CDN
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CachedFile {
+    /// Path relative to the patch folder, eg. `Bundles2/_.index.bin`
+    pub path: PathBuf,
+    /// Etag the CDN served the file with. Files with the same path and etag are identical.
+    pub etag: String,
+    pub size: u64,
+}
+
+/// Downloads for a single patch version
+#[derive(Debug, Clone)]
+pub struct CachedPatch {
+    /// Folder holding every patch downloaded from the same CDN, which are fallbacks for each other
+    pub root: PathBuf,
+    /// Version the folder is named after, eg. `3.25.1.2`
+    pub version: String,
+    pub path: PathBuf,
+    /// Complete downloads, ie. those with an etag
+    pub files: Vec<CachedFile>,
+    /// Files with an etag recorded but no download, which offline mode reads from another
+    /// patch's copy with the same etag
+    pub fallbacks: Vec<(PathBuf, String)>,
+    /// Total size of the folder on disk, including etags and incomplete downloads
+    pub size: u64,
+    /// Version components used for ordering
+    patch_parts: Vec<u64>,
+}
+
+impl CachedPatch {
+    /// Delete the patch's folder
+    pub fn remove(&self) -> Result<()> {
+        Ok(fs::remove_dir_all(&self.path)?)
+    }
+}
+
+/// Every patch in a CDN cache directory
+#[derive(Debug, Clone, Default)]
+pub struct CDNCache {
+    /// Grouped by root, oldest version first
+    pub patches: Vec<CachedPatch>,
+}
+
+/// Recursively collect all files under a folder, along with their sizes
+fn walk_files(dir: &Path, out: &mut Vec<(PathBuf, u64)>) -> std::io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let file_type = entry.file_type()?;
+
+        if file_type.is_dir() {
+            walk_files(&entry.path(), out)?;
+        } else if file_type.is_file() {
+            out.push((entry.path(), entry.metadata()?.len()));
+        }
+    }
+
+    Ok(())
+}
+
+/// Find folders named after patch versions, without looking inside them
+fn find_patch_folders(dir: &Path, depth: usize, out: &mut Vec<CacheFolder>) -> Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        if !entry.file_type()?.is_dir() {
+            continue;
+        }
+
+        match CacheFolder::from_path(entry.path()) {
+            Ok(folder) => out.push(folder),
+            Err(_) if depth < MAX_DEPTH => find_patch_folders(&entry.path(), depth + 1, out)?,
+            Err(_) => {}
+        }
+    }
+
+    Ok(())
+}
+
+fn scan_patch(folder: CacheFolder) -> Result<CachedPatch> {
+    let mut files = vec![];
+    walk_files(&folder.path, &mut files)?;
+
+    let size = files.iter().map(|(_, size)| size).sum();
+    let (etags, files) = files
+        .into_iter()
+        .partition::<Vec<_>, _>(|(path, _)| path.extension().is_some_and(|ext| ext == "etag"));
+    let downloaded = files.iter().map(|(path, _)| path).collect::<HashSet<_>>();
+
+    let fallbacks = etags
+        .iter()
+        .map(|(path, _)| path.with_extension(""))
+        .filter(|path| !downloaded.contains(path))
+        .filter_map(|path| {
+            let etag = fs::read_to_string(path.with_added_extension("etag")).ok()?;
+            let path = path.strip_prefix(&folder.path).ok()?.to_owned();
+            Some((path, etag))
+        })
+        .collect();
+
+    let files = files
+        .into_iter()
+        .filter_map(|(path, size)| {
+            let etag = fs::read_to_string(path.with_added_extension("etag")).ok()?;
+            let path = path.strip_prefix(&folder.path).ok()?.to_owned();
+            Some(CachedFile { path, etag, size })
+        })
+        .collect();
+
+    Ok(CachedPatch {
+        root: folder.path.parent().unwrap_or(&folder.path).to_owned(),
+        version: folder
+            .path
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .into_owned(),
+        path: folder.path,
+        files,
+        fallbacks,
+        size,
+        patch_parts: folder.patch_parts,
+    })
+}
+
+impl CDNCache {
+    /// Find every cached patch within a cache directory, as given to [CDNFS](super::CDNFS)
+    pub fn scan(cache_dir: &Path) -> Result<Self> {
+        let mut folders = vec![];
+        if cache_dir.is_dir() {
+            find_patch_folders(cache_dir, 1, &mut folders)?;
+        }
+
+        let mut patches = folders
+            .into_iter()
+            .map(scan_patch)
+            .collect::<Result<Vec<_>>>()?;
+        patches.sort_by(|a, b| (&a.root, &a.patch_parts).cmp(&(&b.root, &b.patch_parts)));
+
+        Ok(Self { patches })
+    }
+
+    /// Files of a patch that are cached unchanged for another patch from the same CDN, so either
+    /// copy can serve as an etag fallback for the other
+    pub fn shared<'a>(&'a self, patch: &'a CachedPatch) -> impl Iterator<Item = &'a CachedFile> {
+        let others = self
+            .patches
+            .iter()
+            .filter(|p| p.root == patch.root && p.path != patch.path)
+            .flat_map(|p| &p.files)
+            .map(|f| (&f.path, &f.etag))
+            .collect::<HashSet<_>>();
+
+        patch
+            .files
+            .iter()
+            .filter(move |f| others.contains(&(&f.path, &f.etag)))
+    }
+
+    /// Patches to remove so only the newest `keep` of each CDN remain, oldest first. At least one
+    /// patch is always kept, so newer patches can still fall back to its files, along with any
+    /// patch holding the only copy of a file a kept patch falls back to.
+    pub fn prune_candidates(&self, keep: usize) -> Vec<&CachedPatch> {
+        let mut by_root = HashMap::<_, Vec<_>>::new();
+        self.patches
+            .iter()
+            .for_each(|p| by_root.entry(&p.root).or_default().push(p));
+
+        let mut candidates = by_root
+            .into_values()
+            .flat_map(|mut patches| {
+                let remove = patches.len().saturating_sub(keep.max(1));
+                let mut kept = patches.split_off(remove);
+
+                // Keeping a patch may make it depend on older ones in turn
+                while let Some(i) = patches.iter().position(|p| is_fallback_for(p, &kept)) {
+                    kept.push(patches.remove(i));
+                }
+
+                patches
+            })
+            .collect::<Vec<_>>();
+        candidates.sort_by(|a, b| (&a.root, &a.patch_parts).cmp(&(&b.root, &b.patch_parts)));
+
+        candidates
+    }
+}
+
+/// Whether `patch` holds the only copy of a file one of the `kept` patches falls back to
+fn is_fallback_for(patch: &CachedPatch, kept: &[&CachedPatch]) -> bool {
+    let files = |p: &CachedPatch| {
+        p.files
+            .iter()
+            .map(|f| (f.path.clone(), f.etag.clone()))
+            .collect::<HashSet<_>>()
+    };
+    let provided = files(patch);
+    let kept_files = kept.iter().flat_map(|p| files(p)).collect::<HashSet<_>>();
+
+    kept.iter()
+        .flat_map(|p| &p.fallbacks)
+        .any(|f| provided.contains(f) && !kept_files.contains(f))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write_cached(root: &Path, version: &str, stub: &str, etag: &str) {
+        let path = root.join(version).join(stub);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, etag).unwrap();
+        fs::write(path.with_added_extension("etag"), etag).unwrap();
+    }
+
+    #[test]
+    fn scan_and_prune() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let root = temp.path().join("patch.poecdn.com");
+        write_cached(&root, "3.1.0.0", "Bundles2/a.bundle.bin", "a1");
+        write_cached(&root, "3.1.0.0", "Bundles2/b.bundle.bin", "b1");
+        write_cached(&root, "3.10.0.0", "Bundles2/a.bundle.bin", "a1");
+        write_cached(&root, "3.2.0.0", "Bundles2/b.bundle.bin", "b2");
+        fs::create_dir_all(temp.path().join("cdn_url")).unwrap();
+
+        let cache = CDNCache::scan(temp.path()).unwrap();
+        let versions = cache.patches.iter().map(|p| p.version.as_str());
+        assert_eq!(
+            versions.collect::<Vec<_>>(),
+            ["3.1.0.0", "3.2.0.0", "3.10.0.0"]
+        );
+
+        let oldest = &cache.patches[0];
+        assert_eq!(oldest.files.len(), 2);
+        let shared = cache
+            .shared(oldest)
+            .map(|f| f.path.clone())
+            .collect::<Vec<_>>();
+        assert_eq!(shared, [PathBuf::from("Bundles2/a.bundle.bin")]);
+
+        let prune = cache.prune_candidates(2);
+        assert_eq!(prune.len(), 1);
+        prune[0].remove().unwrap();
+        assert_eq!(CDNCache::scan(temp.path()).unwrap().patches.len(), 2);
+
+        // Never removes everything
+        assert_eq!(cache.prune_candidates(0).len(), 2);
+    }
+
+    #[test]
+    fn prune_keeps_fallbacks() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let root = temp.path().join("patch.poecdn.com");
+        write_cached(&root, "3.1.0.0", "Bundles2/a.bundle.bin", "a1");
+        write_cached(&root, "3.2.0.0", "Bundles2/b.bundle.bin", "b1");
+        write_cached(&root, "3.3.0.0", "Bundles2/c.bundle.bin", "c1");
+
+        // 3.3 only recorded the etag for a, relying on 3.1's copy
+        let etag = root.join("3.3.0.0/Bundles2/a.bundle.bin.etag");
+        fs::write(etag, "a1").unwrap();
+
+        let cache = CDNCache::scan(temp.path()).unwrap();
+        assert_eq!(
+            cache.patches[2].fallbacks,
+            [(PathBuf::from("Bundles2/a.bundle.bin"), "a1".to_owned())]
+        );
+
+        let prune = cache.prune_candidates(1);
+        let versions = prune.iter().map(|p| p.version.as_str());
+        assert_eq!(versions.collect::<Vec<_>>(), ["3.2.0.0"]);
+    }
+}
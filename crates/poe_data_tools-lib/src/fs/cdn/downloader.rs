@@ -30,14 +30,21 @@ pub enum CDNError {
     BadEtag,
     #[error("CDN provided no valid URLs")]
     NoUrls,
+    #[error("{0:?} isn't cached, and can't be downloaded in offline mode")]
+    NotCached(String),
+    #[error("no cached CDN URL for version {0:?}, run once while online to cache it")]
+    NoCachedUrl(String),
 }
 
+/// Name of the bundle index, which is specific to each version
+const INDEX_FILE_NAME: &str = "_.index.bin";
+
 /// A cache folder for a specific game version
 #[derive(Debug)]
-struct CacheFolder {
-    path: PathBuf,
+pub(super) struct CacheFolder {
+    pub(super) path: PathBuf,
     /// patch version components [major, minor, patch, ...]
-    patch_parts: Vec<u64>,
+    pub(super) patch_parts: Vec<u64>,
 }
 
 impl PartialEq for CacheFolder {
@@ -61,7 +68,7 @@ impl Ord for CacheFolder {
 }
 
 impl CacheFolder {
-    fn from_path(path: PathBuf) -> Result<Self> {
+    pub(super) fn from_path(path: PathBuf) -> Result<Self> {
         let folder_name = path.file_name().and_then(|f| f.to_str()).ok_or_else(|| {
             FSError::InvalidConfig(format!("Cache path has no/non-utf8 folder: {path:?}"))
         })?;
@@ -175,6 +182,8 @@ pub struct CDNLoader {
     cache: CacheConfig,
     /// Async client
     client: reqwest::Client,
+    /// Only serve files already in the cache
+    offline: bool,
 }
 
 impl CDNLoader {
@@ -200,9 +209,55 @@ impl CDNLoader {
             base_url: base_url.clone(),
             cache,
             client,
+            offline: false,
         })
     }
 
+    /// Never contact the CDN, only serving files from the cache. Files missing from the targetted
+    /// version's cache are only taken from other versions when their etags match, see
+    /// [CDNLoader::load].
+    pub fn with_offline(mut self, offline: bool) -> Self {
+        self.offline = offline;
+        self
+    }
+
+    /// Find a file in other versions' caches without contacting the CDN. Only copies with the
+    /// etag the targetted version recorded for the file are used, so they're known to be
+    /// identical. The index is never taken from another version, as it describes that version's
+    /// bundles.
+    fn search_offline(&self, path_stub: &Path) -> Result<PathBuf> {
+        let not_cached = || CDNError::NotCached(path_stub.to_string_lossy().into_owned());
+
+        if path_stub.file_name() == Some(INDEX_FILE_NAME.as_ref()) {
+            return Err(not_cached().into());
+        }
+
+        let (_, etag_path) = self.cache.primary.get_path(path_stub);
+        let etag = fs::read_to_string(etag_path).map_err(|_| not_cached())?;
+
+        let (cache_path, _) = self
+            .cache
+            .search_fallbacks(path_stub)
+            .find(|(_, etag_path)| {
+                fs::read_to_string(etag_path).is_ok_and(|fallback_etag| fallback_etag == etag)
+            })
+            .ok_or_else(not_cached)?;
+
+        log::debug!(
+            "Offline, using cached bundle from different patch: {:?}",
+            cache_path
+        );
+        Ok(cache_path)
+    }
+
+    /// Game patch the CDN URL is for, eg. "3.25.1.2"
+    pub fn patch_version(&self) -> Option<&str> {
+        self.base_url
+            .path_segments()?
+            .rfind(|s| !s.is_empty())
+            .filter(|s| parse_patch_parts(s).is_ok())
+    }
+
     /// Get the CDN url for a file
     fn build_url(&self, path_stub: &Path) -> Result<Url> {
         self.base_url
@@ -212,8 +267,26 @@ impl CDNLoader {
             .map_err(|e| FSError::InvalidConfig(format!("invalid URL: {e:?}")))
     }
 
+    /// Size of a file in the cache for the targetted version, if it has been downloaded
+    pub fn cached_size(&self, path_stub: &Path) -> Option<u64> {
+        fs::metadata(self.cached_path(path_stub)?)
+            .ok()
+            .map(|m| m.len())
+    }
+
+    /// Location of a file in the cache for the targetted version, if it has been downloaded
+    pub fn cached_path(&self, path_stub: &Path) -> Option<PathBuf> {
+        let (cache_path, _) = self.cache.primary.search(path_stub)?;
+
+        Some(cache_path)
+    }
+
     /// Loads the contents of the bundle file. Either reads from the local cache or from the CDN if
     /// it's not cached.
+    ///
+    /// In offline mode, files not cached for the targetted version are read from the closest
+    /// version that has a copy with the etag recorded for the targetted version, or fail with
+    /// [CDNError::NotCached].
     pub fn load(&self, path_stub: &Path) -> Result<Bytes> {
         let url = self.build_url(path_stub)?;
 
@@ -224,6 +297,11 @@ impl CDNLoader {
             return Ok(Bytes::from(bytes));
         }
 
+        if self.offline {
+            let bytes = fs::read(self.search_offline(path_stub)?)?;
+            return Ok(Bytes::from(bytes));
+        }
+
         // Short timeout for initial connection, but none for transfer to allow for fetching large
         // files on a poor network connection
         let client = Client::builder()
@@ -322,6 +400,11 @@ impl CDNLoader {
             return Ok(Bytes::from(bytes));
         }
 
+        if self.offline {
+            let bytes = tokio::fs::read(self.search_offline(path_stub)?).await?;
+            return Ok(Bytes::from(bytes));
+        }
+
         // Ask server for the etag so we can check against cache
         let etag = {
             let resp = self
@@ -430,16 +513,8 @@ pub fn cdn_base_url(cache_dir: &Path, version: &str) -> Result<Url> {
         "1" => cur_url("patch.pathofexile.com:12995".to_string(), &[1, 6])?,
         // Latest PoE 2
         "2" => cur_url("patch.pathofexile2.com:13060".to_string(), &[1, 7])?,
-        // Specific PoE 1 patch
-        v if v.starts_with("3.") => Url::parse(format!("https://patch.poecdn.com/{}/", v).as_str())
-            .map_err(ParseError::other)?,
-        // Specific PoE 2 patch
-        v if v.starts_with("4.") => {
-            Url::parse(format!("https://patch-poe2.poecdn.com/{}/", v).as_str())
-                .map_err(ParseError::other)?
-        }
-        // Invalid patch
-        _ => panic!("Invalid version provided"),
+        // Specific patch
+        v => specific_patch_url(v).expect("Invalid version provided")?,
     };
 
     fs::create_dir_all(&cache_dir)?;
@@ -448,6 +523,31 @@ pub fn cdn_base_url(cache_dir: &Path, version: &str) -> Result<Url> {
     Ok(url)
 }
 
+/// Offline counterpart to [cdn_base_url], using the last cached URL however old it is. Specific
+/// patch versions don't need the CDN, so always work.
+pub fn cached_cdn_base_url(cache_dir: &Path, version: &str) -> Result<Url> {
+    if let Some(url) = specific_patch_url(version) {
+        return url;
+    }
+
+    let cache_file = cache_dir.join("cdn_url").join(version);
+    let url =
+        fs::read_to_string(&cache_file).map_err(|_| CDNError::NoCachedUrl(version.to_owned()))?;
+
+    Ok(Url::parse(&url).map_err(ParseError::other)?)
+}
+
+/// URL of a specific patch, eg. "3.25.1.2", or None for versions that aren't specific patches
+fn specific_patch_url(version: &str) -> Option<Result<Url>> {
+    let host = match version {
+        v if v.starts_with("3.") => "patch.poecdn.com",
+        v if v.starts_with("4.") => "patch-poe2.poecdn.com",
+        _ => return None,
+    };
+
+    Some(Url::parse(&format!("https://{host}/{version}/")).map_err(|e| ParseError::other(e).into()))
+}
+
 fn parse_response<'a>() -> impl WinnowParser<&'a [u8], Vec<String>> {
     length_repeat(
         terminated(le_u8, take(33_usize)), //
@@ -550,4 +650,43 @@ mod tests {
         );
         assert_eq!(cache.search_fallbacks(Path::new("bing.bong")).count(), 1);
     }
+
+    #[test]
+    fn test_offline() {
+        let temp = TempDir::new().unwrap();
+        let root = temp.path().join("patch.poecdn.com");
+        let cached = |version: &str, stub: &str, etag: &str| {
+            let path = root.join(version).join(stub);
+            write_file(&path, etag);
+            write_file(&path.with_added_extension("etag"), etag);
+        };
+        cached("3.1.0.0", "Bundles2/_.index.bin", "i1");
+        cached("3.1.0.0", "Bundles2/a.bundle.bin", "a1");
+        cached("3.1.0.0", "Bundles2/b.bundle.bin", "b1");
+
+        // Etags recorded for the target patch without the files themselves
+        let target = root.join("3.2.0.0/Bundles2");
+        write_file(&target.join("a.bundle.bin.etag"), "a1");
+        write_file(&target.join("b.bundle.bin.etag"), "b2");
+        write_file(&target.join("_.index.bin.etag"), "i1");
+
+        let url = Url::parse("https://patch.poecdn.com/3.2.0.0/").unwrap();
+        let loader = CDNLoader::new(&url, temp.path().to_str().unwrap())
+            .unwrap()
+            .with_offline(true);
+        assert_eq!(loader.patch_version(), Some("3.2.0.0"));
+
+        let load = |stub: &str| loader.load(Path::new(stub));
+        assert_eq!(load("Bundles2/a.bundle.bin").unwrap(), "a1");
+        for stub in [
+            "Bundles2/b.bundle.bin",
+            "Bundles2/c.bundle.bin",
+            "Bundles2/_.index.bin",
+        ] {
+            assert!(matches!(
+                load(stub),
+                Err(FSError::CDNError(CDNError::NotCached(_)))
+            ));
+        }
+    }
 }
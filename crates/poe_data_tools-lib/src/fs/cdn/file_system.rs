@@ -1,18 +1,30 @@
-use std::{borrow::Cow, collections::HashMap, path::Path, sync::Arc};
+use std::{
+    borrow::Cow, collections::HashMap, fs::File, io::Cursor, path::Path, sync::Arc, time::Instant,
+};
 
 use bytes::Bytes;
-use futures::StreamExt;
+use futures::{StreamExt, stream::BoxStream};
 use iterators_extended::bucket::Bucket;
 use url::Url;
 
-use super::CDNLoader;
+use super::{BatchProgress, CDNLoader, ProgressCallback, SchedulerConfig};
 use crate::{
+    bundle::BundleReader,
     file_parsers::{
         FileParser,
         bundle::{BundleParser, types::BundleFile},
-        bundle_index::{BundleIndexParser, types::BundleIndexFile},
+        bundle_index::{
+            BundleIndexParser,
+            types::{BundleIndexFile, FileInfo},
+        },
+    },
+    fs::{
+        AsyncFileSystem, FileHandle, FileSystem, Result,
+        block_cache::{BlockCache, CacheStats},
+        bundled,
+        error::FSError,
+        stat::FileStat,
     },
-    fs::{FileSystem, Result, error::FSError},
     hasher::murmur64a::{BuildHasherEx, BuildMurmurHash64A},
     path::parse_paths,
 };
@@ -26,18 +38,26 @@ pub struct CDNFS {
     index: BundleIndexFile,
     /// File hash -> index lookup
     lut: HashMap<u64, usize>,
+    /// How batch reads fetch bundles
+    scheduler: SchedulerConfig,
+    /// Decoded blocks kept around for single file reads
+    block_cache: BlockCache,
 }
 
 impl CDNFS {
     /// Create a new filesystem backed by the provided CDN and cache location
     pub fn new(base_url: &Url, cache_dir: &Path) -> Result<Self> {
-        let cdn_loader = CDNLoader::new(
-            base_url,
-            cache_dir.to_str().ok_or_else(|| {
-                FSError::InvalidConfig(format!("invalid cache path: {cache_dir:?}"))
-            })?,
-        )?;
+        Self::from_loader(CDNLoader::new(base_url, cache_dir_str(cache_dir)?)?)
+    }
 
+    /// Create a new filesystem that only reads from the cache, never contacting the CDN. See
+    /// [CDNLoader::with_offline].
+    pub fn new_offline(base_url: &Url, cache_dir: &Path) -> Result<Self> {
+        let cdn_loader = CDNLoader::new(base_url, cache_dir_str(cache_dir)?)?.with_offline(true);
+        Self::from_loader(cdn_loader)
+    }
+
+    fn from_loader(cdn_loader: CDNLoader) -> Result<Self> {
         let index = fetch_index_file(&cdn_loader, Path::new("Bundles2/_.index.bin"))?;
 
         let lut = index
@@ -51,21 +71,50 @@ impl CDNFS {
             cdn_loader: Arc::new(cdn_loader),
             index,
             lut,
+            scheduler: SchedulerConfig::default(),
+            block_cache: BlockCache::default(),
         })
     }
-}
 
-impl FileSystem for CDNFS {
-    fn list(&self) -> Box<dyn Iterator<Item = String> + '_> {
-        Box::new(
-            self.index
-                .paths
-                .iter()
-                .flat_map(|p| parse_paths(&self.index.path_rep_bundle, p).get_paths()),
-        )
+    /// Set the max number of bundles downloaded at once during a batch read
+    pub fn with_concurrency(mut self, concurrency: usize) -> Self {
+        self.scheduler.concurrency = concurrency.max(1);
+        self
     }
 
-    fn read(&self, path: &str) -> Result<Bytes> {
+    /// Set the max number of downloaded bundles that can wait to be decoded during a batch read
+    pub fn with_buffered(mut self, buffered: usize) -> Self {
+        self.scheduler.buffered = buffered.max(1);
+        self
+    }
+
+    /// Report progress after each bundle is processed during a batch read
+    pub fn with_progress_callback(
+        mut self,
+        callback: impl Fn(&BatchProgress) + Send + Sync + 'static,
+    ) -> Self {
+        self.scheduler.on_progress = Some(Box::new(callback) as ProgressCallback);
+        self
+    }
+
+    /// Cap the memory used by cached decoded blocks, in bytes. 0 disables the cache.
+    pub fn with_block_cache(mut self, capacity: usize) -> Self {
+        self.block_cache = BlockCache::new(capacity);
+        self
+    }
+
+    /// Hit/miss counters of the decoded block cache
+    pub fn block_cache_stats(&self) -> CacheStats {
+        self.block_cache.stats()
+    }
+
+    /// Game patch the files are downloaded for, eg. "3.25.1.2"
+    pub fn patch_version(&self) -> Option<&str> {
+        self.cdn_loader.patch_version()
+    }
+
+    /// Look up the index entry for a file
+    fn lookup(&self, path: &str) -> Result<&FileInfo> {
         // Compute the hash of this file path
         let hash = HASHER.hash_one_str(&path.to_lowercase());
 
@@ -74,17 +123,65 @@ impl FileSystem for CDNFS {
             .lut
             .get(&hash)
             .ok_or_else(|| FSError::FileNotFound(path.to_owned()))?;
-        let file = &self.index.files[*file_index];
 
-        // Load the bundle
-        let bundle_path = format!(
+        Ok(&self.index.files[*file_index])
+    }
+
+    /// Path on the CDN of the bundle holding a file
+    fn bundle_path(&self, file: &FileInfo) -> String {
+        format!(
             "Bundles2/{}.bundle.bin",
             self.index.bundles[file.bundle_index as usize].name
-        );
-        let bundle = fetch_bundle_content(&self.cdn_loader, Path::new(&bundle_path))?;
+        )
+    }
+
+    /// Fetch the bundle holding a file
+    fn load_bundle(&self, file: &FileInfo) -> Result<BundleFile> {
+        fetch_bundle_content(&self.cdn_loader, Path::new(&self.bundle_path(file)))
+    }
+
+    /// Fetch a bundle from the CDN (or cache) without blocking
+    async fn load_bundle_async(&self, name: String) -> Result<BundleFile> {
+        let bundle_path = format!("Bundles2/{name}.bundle.bin");
+        let bundle_content = self.cdn_loader.load_async(Path::new(&bundle_path)).await?;
+
+        Ok(BundleParser.parse(&bundle_content)?)
+    }
+}
+
+impl FileSystem for CDNFS {
+    fn list(&self) -> Box<dyn Iterator<Item = String> + '_> {
+        Box::new(
+            self.index
+                .paths
+                .iter()
+                .flat_map(|p| parse_paths(&self.index.path_rep_bundle, p).get_paths()),
+        )
+    }
+
+    fn read(&self, path: &str) -> Result<Bytes> {
+        let file = self.lookup(path)?;
+        if let Some(bytes) = self.block_cache.get(file) {
+            return Ok(bytes);
+        }
 
         // Pull out the file's contents
-        bundle.read_range(file.offset as usize, file.size as usize)
+        let bundle = self.load_bundle(file)?;
+        self.block_cache.read(&bundle, file)
+    }
+
+    fn open(&self, path: &str) -> Result<Box<dyn FileHandle + '_>> {
+        let file = self.lookup(path)?;
+        let bundle_path = self.bundle_path(file);
+
+        // Read blocks straight from the cache if possible, otherwise keep the downloaded bundle
+        let bundle: Box<dyn FileHandle> = match self.cdn_loader.cached_path(Path::new(&bundle_path))
+        {
+            Some(cache_path) => Box::new(File::open(cache_path)?),
+            None => Box::new(Cursor::new(self.cdn_loader.load(Path::new(&bundle_path))?)),
+        };
+
+        Ok(Box::new(BundleReader::new(bundle, file)?))
     }
 
     fn batch_read<'a>(
@@ -109,43 +206,46 @@ impl FileSystem for CDNFS {
             })
             .bucket_result();
 
-        // Batch them into their bundles
-        let fileinfos =
-            fileinfos
-                .into_iter()
-                .fold(HashMap::<_, Vec<_>>::new(), |mut acc, (path, fileinfo)| {
-                    acc.entry(fileinfo.bundle_index)
-                        .or_default()
-                        .push((path, fileinfo));
+        // Batch them into their bundles, most useful bundles first
+        let fileinfos = bundled::group_by_bundle(&self.index, fileinfos, |(_, f)| f.bundle_index);
 
-                    acc
-                });
+        let mut progress = BatchProgress {
+            bundles_total: fileinfos.len(),
+            files_total: fileinfos.iter().map(|(_, files)| files.len()).sum(),
+            bytes_total: fileinfos
+                .iter()
+                .map(|(i, _)| self.index.bundles[*i as usize].uncompressed_size as u64)
+                .sum(),
+            ..Default::default()
+        };
 
         // Prepare async tasks
-        let file_infos = fileinfos
+        let tasks = fileinfos
             .into_iter()
             .map(|(bundle_index, files)| {
                 let bundle_path = format!(
                     "Bundles2/{}.bundle.bin",
                     self.index.bundles[bundle_index as usize].name
                 );
-
-                (bundle_path, files)
-            })
-            .map(|(bundle_path, files)| {
                 let cdn_loader = Arc::clone(&self.cdn_loader);
+
                 async move {
                     // Load the bundle
                     let res = cdn_loader.load_async(Path::new(&bundle_path)).await;
 
-                    (bundle_path, res, files)
+                    (bundle_index, res, files)
                 }
             })
             .collect::<Vec<_>>();
 
-        // Spin up async from here
-        const CONCURRENCY: usize = 16;
-        let (tx, rx) = std::sync::mpsc::sync_channel(CONCURRENCY);
+        // Spin up async from here. Downloads start in priority order, but are passed on as soon as
+        // they complete, so decoding overlaps with the remaining downloads.
+        let SchedulerConfig {
+            concurrency,
+            buffered,
+            ..
+        } = self.scheduler;
+        let (tx, rx) = std::sync::mpsc::sync_channel(buffered);
 
         std::thread::spawn(move || {
             let rt = tokio::runtime::Builder::new_current_thread()
@@ -153,31 +253,47 @@ impl FileSystem for CDNFS {
                 .build()
                 .expect("failed to create async runtime");
 
-            let sender = futures::stream::iter(file_infos)
-                .buffer_unordered(CONCURRENCY)
-                .for_each(|v| async {
-                    tx.send(v).expect("failed to send message");
-                });
+            rt.block_on(async {
+                let mut bundles = futures::stream::iter(tasks).buffer_unordered(concurrency);
 
-            rt.block_on(sender);
+                while let Some(v) = bundles.next().await {
+                    // Receiver has been dropped, nobody wants the rest of the batch
+                    if tx.send(v).is_err() {
+                        break;
+                    }
+                }
+            });
         });
 
-        let file_contents = rx.into_iter().flat_map(|(_bundle_path, bundle, files)| {
-            let bundle = bundle.and_then(|bytes| BundleParser.parse(&bytes).map_err(FSError::from));
-
-            let contents: Box<dyn Iterator<Item = _>> = match bundle {
-                Ok(b) => Box::new(files.into_iter().map(move |(path, file)| {
-                    (path, b.read_range(file.offset as usize, file.size as usize))
-                })),
-                Err(e) => Box::new(
-                    files
-                        .into_iter()
-                        .map(move |(path, _)| (path, Err(e.clone()))),
-                ),
-            };
-
-            contents
-        });
+        let start = Instant::now();
+        let file_contents = rx
+            .into_iter()
+            .flat_map(move |(bundle_index, bundle, files)| {
+                let bundle =
+                    bundle.and_then(|bytes| BundleParser.parse(&bytes).map_err(FSError::from));
+
+                progress.bundles_done += 1;
+                progress.files_done += files.len();
+                progress.bytes_done +=
+                    self.index.bundles[bundle_index as usize].uncompressed_size as u64;
+                progress.elapsed = start.elapsed();
+                if let Some(on_progress) = &self.scheduler.on_progress {
+                    on_progress(&progress);
+                }
+
+                let contents: Box<dyn Iterator<Item = _>> = match bundle {
+                    Ok(b) => Box::new(files.into_iter().map(move |(path, file)| {
+                        (path, b.read_range(file.offset as usize, file.size as usize))
+                    })),
+                    Err(e) => Box::new(
+                        files
+                            .into_iter()
+                            .map(move |(path, _)| (path, Err(e.clone()))),
+                    ),
+                };
+
+                contents
+            });
 
         // Add on previous errors
         Box::new(
@@ -187,6 +303,52 @@ impl FileSystem for CDNFS {
                 .map(|(path, r)| (Cow::Owned(path), r)),
         )
     }
+
+    /// Bundle sizes are only known for bundles that have already been downloaded
+    fn stat(&self, path: &str) -> Result<FileStat> {
+        let file = self.lookup(path)?;
+        let bundle_size = self
+            .cdn_loader
+            .cached_size(Path::new(&self.bundle_path(file)));
+
+        Ok(bundled::stat(&self.index, path, file, bundle_size))
+    }
+
+    fn exists(&self, path: &str) -> bool {
+        self.lookup(path).is_ok()
+    }
+
+    fn walk(&self, dir: &str) -> Box<dyn Iterator<Item = String> + '_> {
+        bundled::walk(&self.index, dir)
+    }
+}
+
+impl AsyncFileSystem for CDNFS {
+    fn list(&self) -> Box<dyn Iterator<Item = String> + '_> {
+        FileSystem::list(self)
+    }
+
+    fn batch_read<'a>(
+        &'a self,
+        paths: &'a [impl AsRef<str> + Sync],
+    ) -> BoxStream<'a, (Cow<'a, str>, Result<Bytes>)> {
+        bundled::batch_read(&self.index, &self.lut, paths, |name| {
+            self.load_bundle_async(name)
+        })
+    }
+
+    async fn read(&self, path: &str) -> Result<Bytes> {
+        bundled::read(&self.index, &self.lut, &self.block_cache, path, |name| {
+            self.load_bundle_async(name)
+        })
+        .await
+    }
+}
+
+fn cache_dir_str(cache_dir: &Path) -> Result<&str> {
+    cache_dir
+        .to_str()
+        .ok_or_else(|| FSError::InvalidConfig(format!("invalid cache path: {cache_dir:?}")))
 }
 
 /// Fetch an index file from the CDN (or cache)
@@ -1,5 +1,8 @@
+pub mod cache;
 mod downloader;
 mod file_system;
+mod scheduler;
 
-pub use downloader::{CDNError, CDNLoader, cdn_base_url};
+pub use downloader::{CDNError, CDNLoader, cached_cdn_base_url, cdn_base_url};
 pub use file_system::CDNFS;
+pub use scheduler::{BatchProgress, ProgressCallback, SchedulerConfig};
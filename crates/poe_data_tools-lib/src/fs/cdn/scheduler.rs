@@ -0,0 +1,60 @@
+use std::time::Duration;
+
+/// Snapshot of a batch read, reported each time a bundle has been processed
+#[derive(Debug, Clone, Default)]
+pub struct BatchProgress {
+    pub bundles_done: usize,
+    pub bundles_total: usize,
+    pub files_done: usize,
+    pub files_total: usize,
+    /// Uncompressed size of the processed bundles
+    pub bytes_done: u64,
+    /// Uncompressed size of all bundles in the batch
+    pub bytes_total: u64,
+    /// Time since the batch started
+    pub elapsed: Duration,
+}
+
+impl BatchProgress {
+    /// Progress through the batch between 0 and 1, weighted by bundle size
+    pub fn fraction(&self) -> f64 {
+        if self.bytes_total == 0 {
+            return 1.;
+        }
+
+        self.bytes_done as f64 / self.bytes_total as f64
+    }
+
+    /// Estimated time remaining, assuming the rate so far stays constant
+    pub fn eta(&self) -> Option<Duration> {
+        let fraction = self.fraction();
+        (fraction > 0.).then(|| self.elapsed.mul_f64((1. - fraction) / fraction))
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.bundles_done == self.bundles_total
+    }
+}
+
+/// Called with the current progress of a batch read
+pub type ProgressCallback = Box<dyn Fn(&BatchProgress) + Send + Sync>;
+
+/// Options for how [CDNFS::batch_read](super::CDNFS) fetches bundles
+pub struct SchedulerConfig {
+    /// Max number of bundles downloading at once
+    pub concurrency: usize,
+    /// Max number of downloaded bundles waiting to be decoded. Downloads pause when this is full
+    /// so memory usage stays bounded when decoding can't keep up.
+    pub buffered: usize,
+    pub on_progress: Option<ProgressCallback>,
+}
+
+impl Default for SchedulerConfig {
+    fn default() -> Self {
+        Self {
+            concurrency: 16,
+            buffered: 16,
+            on_progress: None,
+        }
+    }
+}
@@ -0,0 +1,181 @@
+//! Comparing the files of two file systems, eg. to find what changed between patches
+use std::collections::{BTreeMap, HashMap};
+
+use glob::Pattern;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+use super::{FileSystem, Result};
+
+/// How a file differs between the two file systems
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChangeKind {
+    /// Only in the newer file system
+    Added,
+    /// Only in the older file system
+    Removed,
+    /// In both, with different contents
+    Modified,
+}
+
+/// A file that differs, see [diff]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FileChange {
+    pub path: String,
+    pub kind: ChangeKind,
+    /// Size in the older file system, if present there
+    pub from_size: Option<u64>,
+    /// Size in the newer file system, if present there
+    pub to_size: Option<u64>,
+}
+
+/// Every file that differs between two file systems, sorted by path
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct FileDiff {
+    pub changes: Vec<FileChange>,
+    /// Files present in both with the same contents
+    pub unchanged: usize,
+}
+
+impl FileDiff {
+    /// Changes of a single kind
+    pub fn of_kind(&self, kind: ChangeKind) -> impl Iterator<Item = &FileChange> {
+        self.changes.iter().filter(move |c| c.kind == kind)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+}
+
+/// Matching paths, keyed by their lowercased form as paths are case-insensitive
+fn matching_paths(fs: &impl FileSystem, patterns: &[Pattern]) -> BTreeMap<String, String> {
+    fs.glob(patterns).map(|p| (p.to_lowercase(), p)).collect()
+}
+
+/// Digests of each file's contents
+fn digests(fs: &impl FileSystem, paths: &[&String]) -> Result<HashMap<String, [u8; 32]>> {
+    fs.batch_read(paths)
+        .map(|(path, contents)| Ok((path.to_lowercase(), Sha256::digest(contents?).into())))
+        .collect()
+}
+
+/// Find the files added, removed and modified going from one file system to another, out of
+/// those matching any of the patterns. The file systems can be of any kind, eg. two CDN patches or
+/// a patch and a folder of extracted files.
+///
+/// Files whose sizes differ are modified without reading them, the rest are read from both sides
+/// and compared by a hash of their contents.
+pub fn diff(
+    from: &impl FileSystem,
+    to: &impl FileSystem,
+    patterns: &[Pattern],
+) -> Result<FileDiff> {
+    let from_paths = matching_paths(from, patterns);
+    let to_paths = matching_paths(to, patterns);
+
+    let mut changes = vec![];
+    let mut same_size = vec![];
+    for (key, path) in &from_paths {
+        let from_size = from.stat(path)?.size;
+        let Some(to_path) = to_paths.get(key) else {
+            changes.push(FileChange {
+                path: path.clone(),
+                kind: ChangeKind::Removed,
+                from_size: Some(from_size),
+                to_size: None,
+            });
+            continue;
+        };
+
+        let to_size = to.stat(to_path)?.size;
+        if from_size == to_size {
+            same_size.push((key, path, to_path, from_size));
+        } else {
+            changes.push(FileChange {
+                path: to_path.clone(),
+                kind: ChangeKind::Modified,
+                from_size: Some(from_size),
+                to_size: Some(to_size),
+            });
+        }
+    }
+
+    for (key, path) in &to_paths {
+        if !from_paths.contains_key(key) {
+            changes.push(FileChange {
+                path: path.clone(),
+                kind: ChangeKind::Added,
+                from_size: None,
+                to_size: Some(to.stat(path)?.size),
+            });
+        }
+    }
+
+    // Read one side at a time, so only the digests of the other need to be held
+    let from_digests = digests(from, &same_size.iter().map(|f| f.1).collect::<Vec<_>>())?;
+    let to_digests = digests(to, &same_size.iter().map(|f| f.2).collect::<Vec<_>>())?;
+
+    let mut unchanged = 0;
+    for (key, _, to_path, size) in same_size {
+        if from_digests.get(key) == to_digests.get(key) {
+            unchanged += 1;
+        } else {
+            changes.push(FileChange {
+                path: to_path.clone(),
+                kind: ChangeKind::Modified,
+                from_size: Some(size),
+                to_size: Some(size),
+            });
+        }
+    }
+
+    changes.sort_by_key(|c| c.path.to_lowercase());
+
+    Ok(FileDiff { changes, unchanged })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fs::memory::MemoryFS;
+
+    #[test]
+    fn changes() {
+        let from = MemoryFS::new()
+            .with_file("data/kept.datc64", "same")
+            .with_file("data/edited.datc64", "abcd")
+            .with_file("data/grown.datc64", "ab")
+            .with_file("data/gone.datc64", "x")
+            .with_file("art/ignored.dds", "x");
+        let to = MemoryFS::new()
+            .with_file("Data/Kept.datc64", "same")
+            .with_file("data/edited.datc64", "abce")
+            .with_file("data/grown.datc64", "abc")
+            .with_file("data/new.datc64", "y");
+
+        let patterns = [Pattern::new("**/*.datc64").unwrap()];
+        let diff = diff(&from, &to, &patterns).unwrap();
+
+        let changes = diff
+            .changes
+            .iter()
+            .map(|c| (c.path.as_str(), c.kind))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            changes,
+            [
+                ("data/edited.datc64", ChangeKind::Modified),
+                ("data/gone.datc64", ChangeKind::Removed),
+                ("data/grown.datc64", ChangeKind::Modified),
+                ("data/new.datc64", ChangeKind::Added),
+            ]
+        );
+        assert_eq!(diff.unchanged, 1);
+        assert_eq!(
+            diff.of_kind(ChangeKind::Added).next().unwrap().to_size,
+            Some(1)
+        );
+    }
+}
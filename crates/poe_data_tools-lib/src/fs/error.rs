@@ -29,6 +29,10 @@ pub enum FSError {
     #[error("file not found in virtual file system: {0:?}")]
     FileNotFound(String),
 
+    /// Block index past the end of a bundle
+    #[error("block {index} out of range for bundle with {count} blocks")]
+    BlockOutOfRange { index: usize, count: usize },
+
     /// Issue with interpreting bytes as structured data
     #[error(transparent)]
     Parse(#[from] Arc<crate::file_parsers::error::ParseError>),
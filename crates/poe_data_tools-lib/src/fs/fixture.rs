@@ -0,0 +1,420 @@
+//! Synthetic bundled game data, for exercising the file systems without the real game files
+use std::{
+    collections::BTreeMap,
+    fs,
+    io::{self, Write},
+    path::Path,
+};
+
+use bytes::Bytes;
+use sha2::{Digest, Sha256};
+
+use super::memory::MemoryFS;
+use crate::file_parsers::{
+    bundle::{types::BundleFile, writer::BLOCK_SIZE},
+    bundle_index::types::{BundleIndexFile, BundleInfo, FileInfo},
+};
+
+/// Builds a bundle index and bundles holding a set of files, laid out the same way as the game
+/// install. Bundles are stored uncompressed, see [BundleFile::from_uncompressed].
+#[derive(Debug, Clone)]
+pub struct BundleFixture {
+    files: BTreeMap<String, Bytes>,
+    /// Max uncompressed size of each bundle, files are split across bundles to respect it
+    bundle_size: usize,
+}
+
+impl Default for BundleFixture {
+    fn default() -> Self {
+        Self {
+            files: BTreeMap::new(),
+            bundle_size: 4 * BLOCK_SIZE,
+        }
+    }
+}
+
+/// Output of a [BundleFixture], as paths relative to the root of a game install
+#[derive(Debug, Clone)]
+pub struct BundledFiles {
+    pub files: Vec<(String, Bytes)>,
+}
+
+impl BundleFixture {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a file to be bundled
+    pub fn with_file(mut self, path: impl Into<String>, contents: impl Into<Bytes>) -> Self {
+        self.files.insert(path.into(), contents.into());
+        self
+    }
+
+    /// Set the max uncompressed size of each bundle. A single file larger than this still gets a
+    /// bundle of its own.
+    pub fn with_bundle_size(mut self, bundle_size: usize) -> Self {
+        self.bundle_size = bundle_size.max(1);
+        self
+    }
+
+    /// Encode the bundle index and bundles
+    pub fn build(&self) -> BundledFiles {
+        // Pack files into bundles in path order
+        let mut bundles: Vec<Vec<u8>> = vec![];
+        let mut files = vec![];
+        for (path, contents) in &self.files {
+            let fits = bundles
+                .last()
+                .is_some_and(|data| data.len() + contents.len() <= self.bundle_size);
+            if !fits {
+                bundles.push(vec![]);
+            }
+
+            let bundle_index = bundles.len() - 1;
+            let data = bundles.last_mut().unwrap();
+            files.push((
+                path.as_str(),
+                FileInfo::new(
+                    path,
+                    bundle_index as u32,
+                    data.len() as u32,
+                    contents.len() as u32,
+                ),
+            ));
+            data.extend_from_slice(contents);
+        }
+
+        let bundles = bundles
+            .into_iter()
+            .enumerate()
+            .map(|(i, data)| (format!("fixture_{i}"), data))
+            .collect::<Vec<_>>();
+        let bundle_infos = bundles
+            .iter()
+            .map(|(name, data)| BundleInfo {
+                name: name.clone(),
+                uncompressed_size: data.len() as u32,
+            })
+            .collect();
+        let index = BundleIndexFile::new(bundle_infos, files);
+
+        let files = std::iter::once(("Bundles2/_.index.bin".to_owned(), index.to_bytes()))
+            .chain(
+                bundles
+                    .into_iter()
+                    .map(|(name, data)| (format!("Bundles2/{name}.bundle.bin"), data)),
+            )
+            .map(|(path, data)| {
+                let bundle = BundleFile::from_uncompressed(&data).to_bytes();
+                (path, Bytes::from(bundle))
+            })
+            .collect();
+
+        BundledFiles { files }
+    }
+}
+
+impl<P: Into<String>, C: Into<Bytes>> FromIterator<(P, C)> for BundleFixture {
+    fn from_iter<T: IntoIterator<Item = (P, C)>>(iter: T) -> Self {
+        iter.into_iter()
+            .fold(Self::new(), |fixture, (path, contents)| {
+                fixture.with_file(path, contents)
+            })
+    }
+}
+
+impl BundledFiles {
+    /// Write out as a Steam install, which is also the layout of the CDN
+    pub fn write_dir(&self, dir: &Path) -> io::Result<()> {
+        for (path, contents) in &self.files {
+            let path = dir.join(path);
+            fs::create_dir_all(path.parent().expect("bundle paths have a parent folder"))?;
+            fs::write(path, contents)?;
+        }
+
+        Ok(())
+    }
+
+    /// Write out as a standalone install's Content.ggpk
+    pub fn write_ggpk(&self, path: &Path) -> io::Result<()> {
+        let mut file = io::BufWriter::new(fs::File::create(path)?);
+        file.write_all(&encode_ggpk(&self.files))?;
+        file.flush()
+    }
+
+    /// Raw files held in memory, eg. for serving as a stand-in CDN
+    pub fn to_memory_fs(&self) -> MemoryFS {
+        self.files.iter().cloned().collect()
+    }
+}
+
+fn write_u32(out: &mut Vec<u8>, value: u32) {
+    out.extend_from_slice(&value.to_le_bytes());
+}
+
+enum GGPKNode<'a> {
+    Dir(BTreeMap<&'a str, GGPKNode<'a>>),
+    File(&'a [u8]),
+}
+
+/// Lay files out in a GGPK, with directories written after their children
+fn encode_ggpk(files: &[(String, Bytes)]) -> Vec<u8> {
+    let mut root = BTreeMap::new();
+    for (path, contents) in files {
+        let mut segments = path.split('/').collect::<Vec<_>>();
+        let name = segments.pop().unwrap();
+
+        let mut dir = &mut root;
+        for segment in segments {
+            let GGPKNode::Dir(children) = dir
+                .entry(segment)
+                .or_insert_with(|| GGPKNode::Dir(BTreeMap::new()))
+            else {
+                panic!("{segment:?} is both a file and a folder");
+            };
+            dir = children;
+        }
+        dir.insert(name, GGPKNode::File(&contents[..]));
+    }
+
+    // Header is filled in once the root's offset is known
+    let mut out = vec![0; 28];
+    let free_offset = out.len() as u64;
+    write_u32(&mut out, 16);
+    out.extend_from_slice(b"FREE");
+    out.extend_from_slice(&0_u64.to_le_bytes());
+
+    let (root_offset, _) = write_ggpk_node(&mut out, "", &GGPKNode::Dir(root));
+
+    let mut header = vec![];
+    write_u32(&mut header, 28);
+    header.extend_from_slice(b"GGPK");
+    write_u32(&mut header, 3);
+    header.extend_from_slice(&root_offset.to_le_bytes());
+    header.extend_from_slice(&free_offset.to_le_bytes());
+    out[..28].copy_from_slice(&header);
+
+    out
+}
+
+/// Append an entry, returning its offset and digest
+fn write_ggpk_node(out: &mut Vec<u8>, name: &str, node: &GGPKNode) -> (u64, [u8; 32]) {
+    let name = name.encode_utf16().chain([0]).collect::<Vec<_>>();
+    let name_bytes = name
+        .iter()
+        .flat_map(|c| c.to_le_bytes())
+        .collect::<Vec<_>>();
+
+    let (tag, digest, body): (_, [u8; 32], _) = match node {
+        GGPKNode::File(contents) => ("FILE", Sha256::digest(contents).into(), contents.to_vec()),
+        GGPKNode::Dir(children) => {
+            let children = children
+                .iter()
+                .map(|(name, child)| write_ggpk_node(out, name, child))
+                .collect::<Vec<_>>();
+
+            let mut hasher = Sha256::new();
+            children
+                .iter()
+                .for_each(|(_, digest)| hasher.update(digest));
+
+            // Name hashes aren't used when reading, so are left empty
+            let mut body = vec![];
+            for (offset, _) in &children {
+                write_u32(&mut body, 0);
+                body.extend_from_slice(&offset.to_le_bytes());
+            }
+
+            ("PDIR", hasher.finalize().into(), body)
+        }
+    };
+
+    let offset = out.len() as u64;
+    let mut header = vec![];
+    write_u32(&mut header, name.len() as u32);
+    if let GGPKNode::Dir(children) = node {
+        write_u32(&mut header, children.len() as u32);
+    }
+    header.extend_from_slice(&digest);
+    header.extend_from_slice(&name_bytes);
+
+    write_u32(out, (8 + header.len() + body.len()) as u32);
+    out.extend_from_slice(tag.as_bytes());
+    out.extend_from_slice(&header);
+    out.extend_from_slice(&body);
+
+    (offset, digest)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{BufRead, BufReader, Read, Seek, SeekFrom},
+        net::TcpListener,
+        thread,
+    };
+
+    use glob::Pattern;
+    use tempfile::TempDir;
+    use url::Url;
+
+    use super::*;
+    use crate::fs::{FS, FileSystem, cdn::CDNFS, ggpk::GGPKFS};
+
+    fn fixture() -> BundleFixture {
+        BundleFixture::new()
+            .with_bundle_size(BLOCK_SIZE)
+            .with_file("data/mods.datc64", vec![1; 100])
+            .with_file("data/Stats.datc64", vec![2; 200])
+            // Spans several blocks and gets a bundle of its own
+            .with_file(
+                "art/large.dds",
+                (0..3 * BLOCK_SIZE).map(|i| i as u8).collect::<Vec<_>>(),
+            )
+            .with_file("root.txt", "hello")
+    }
+
+    fn check(fs: &FS) {
+        let fixture = fixture();
+
+        let mut paths = fs.list().collect::<Vec<_>>();
+        paths.sort();
+        assert_eq!(paths, fixture.files.keys().cloned().collect::<Vec<_>>());
+
+        for (path, contents) in &fixture.files {
+            assert_eq!(&fs.read(path).unwrap(), contents, "{path}");
+        }
+        assert_eq!(&fs.read("DATA/STATS.DATC64").unwrap()[..], [2; 200]);
+
+        let read = fs.batch_read(&paths).filter(|(_, r)| r.is_ok()).count();
+        assert_eq!(read, paths.len());
+
+        // Streamed reads across a block boundary
+        let large = &fixture.files["art/large.dds"];
+        let mut file = fs.open("art/large.dds").unwrap();
+        file.seek(SeekFrom::Start(BLOCK_SIZE as u64 - 2)).unwrap();
+        let mut buf = [0; 4];
+        file.read_exact(&mut buf).unwrap();
+        assert_eq!(buf, large[BLOCK_SIZE - 2..BLOCK_SIZE + 2]);
+
+        file.rewind().unwrap();
+        let mut streamed = vec![];
+        file.read_to_end(&mut streamed).unwrap();
+        assert_eq!(streamed, large[..]);
+
+        let stat = fs.stat("DATA/MODS.DATC64").unwrap();
+        assert_eq!((stat.size, stat.offset), (100, Some(200)));
+        assert_eq!(stat.bundle.as_deref(), Some("fixture_1"));
+        // Already read, so also cached for the CDN
+        assert!(stat.bundle_size.is_some());
+
+        // Directory tree
+        assert!(fs.exists("DATA/MODS.DATC64"));
+        assert!(!fs.exists("data"));
+
+        let patterns = [Pattern::new("data/*.datc64").unwrap()];
+        let mut matched = fs.glob(&patterns).collect::<Vec<_>>();
+        matched.sort();
+        assert_eq!(matched, ["data/Stats.datc64", "data/mods.datc64"]);
+
+        let root = fs.read_dir("").unwrap();
+        let root = root
+            .iter()
+            .map(|e| (e.path.as_str(), e.is_dir))
+            .collect::<Vec<_>>();
+        assert_eq!(root, [("art", true), ("data", true), ("root.txt", false)]);
+        assert!(fs.read_dir("missing").is_err());
+    }
+
+    #[test]
+    fn steam() {
+        let dir = TempDir::new().unwrap();
+        fixture().build().write_dir(dir.path()).unwrap();
+
+        check(&FS::from_steam(dir.path().to_owned()).unwrap());
+    }
+
+    #[test]
+    fn ggpk() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("Content.ggpk");
+        fixture().build().write_ggpk(&path).unwrap();
+
+        let fs = FS::from_ggpk(&path).unwrap();
+        let FS::GGPK(ggpk) = &fs else {
+            panic!("Bundle index not found");
+        };
+        assert!(ggpk.verify().unwrap().is_ok());
+        check(&fs);
+
+        // Loose files in the GGPK hash the same with or without the root slash
+        let ggpk = GGPKFS::new(&path).unwrap();
+        let index = ggpk.stat("Bundles2/_.index.bin").unwrap();
+        assert_eq!(ggpk.stat("/Bundles2/_.index.bin").unwrap().hash, index.hash);
+        assert_eq!(
+            index.hash,
+            FileInfo::new("bundles2/_.index.bin", 0, 0, 0).hash
+        );
+    }
+
+    /// Serve files over HTTP, one request per connection
+    fn serve(files: MemoryFS) -> Url {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut reader = BufReader::new(&stream);
+                let mut request = String::new();
+                reader.read_line(&mut request).unwrap();
+
+                // Drain the headers so closing the connection doesn't reset it
+                let mut line = String::new();
+                while reader.read_line(&mut line).unwrap() > 2 {
+                    line.clear();
+                }
+
+                let mut parts = request.split_whitespace();
+                let (method, path) = (parts.next().unwrap(), parts.next().unwrap());
+                let path = path.trim_start_matches("/1.0.0.0/");
+
+                let response = match files.read(path) {
+                    Ok(contents) => {
+                        let head = format!(
+                            "HTTP/1.1 200 OK\r\netag: \"{}\"\r\ncontent-length: {}\r\nconnection: close\r\n\r\n",
+                            path,
+                            contents.len()
+                        );
+                        let body = if method == "HEAD" {
+                            &[][..]
+                        } else {
+                            &contents[..]
+                        };
+                        [head.as_bytes(), body].concat()
+                    }
+                    Err(_) => {
+                        b"HTTP/1.1 404 Not Found\r\ncontent-length: 0\r\nconnection: close\r\n\r\n"
+                            .to_vec()
+                    }
+                };
+                stream.write_all(&response).unwrap();
+            }
+        });
+
+        // Domain is required to name the cache folder
+        Url::parse(&format!("http://localhost:{port}/1.0.0.0/")).unwrap()
+    }
+
+    #[test]
+    fn cdn() {
+        let url = serve(fixture().build().to_memory_fs());
+
+        let cache = TempDir::new().unwrap();
+        let fs = FS::CDN(CDNFS::new(&url, cache.path()).unwrap());
+        check(&fs);
+
+        // Everything is cached after the first pass
+        check(&fs);
+    }
+}
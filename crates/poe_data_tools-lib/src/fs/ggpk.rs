@@ -1,26 +1,40 @@
 use std::{
     borrow::Cow,
-    cell::RefCell,
     collections::HashMap,
     fs::File,
     io::{BufReader, Read, Seek, SeekFrom},
-    path::Path,
+    path::{Path, PathBuf},
+    sync::Mutex,
 };
 
 use bytes::Bytes;
+use futures::{StreamExt, stream::BoxStream};
 use iterators_extended::bucket::Bucket;
+use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
+use sha2::{Digest, Sha256};
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
 
 use crate::{
+    bundle::BundleReader,
     file_parsers::{
         FileParser,
-        bundle::BundleParser,
-        bundle_index::{BundleIndexParser, types::BundleIndexFile},
+        bundle::{BundleParser, types::BundleFile},
+        bundle_index::{
+            BundleIndexParser,
+            types::{BundleIndexFile, FileInfo as IndexFileInfo},
+        },
         ggpk::{
             parser::parse_ggpk,
             types::{Entry, EntryData, GGPKFile},
         },
     },
-    fs::{FileSystem, Result, error::FSError},
+    fs::{
+        AsyncFileSystem, FileHandle, FileSystem, Result,
+        block_cache::{BlockCache, CacheStats},
+        bundled,
+        error::FSError,
+        stat::FileStat,
+    },
     hasher::murmur64a::{BuildHasherEx, BuildMurmurHash64A},
     path::parse_paths,
 };
@@ -29,17 +43,31 @@ use crate::{
 struct FileInfo {
     offset: usize,
     length: usize,
+    sha_digest: [u8; 32],
 }
 
-/// File system using a local Content.ggpk file
+/// File system using a local Content.ggpk file. Paths are relative to the root of the archive,
+/// with or without a leading slash.
+///
+/// Used directly for older archives that store files as-is, see [GGPKBundleFS] for newer ones.
 pub struct GGPKFS {
-    file: RefCell<BufReader<File>>,
+    path: PathBuf,
+    file: Mutex<BufReader<File>>,
     index: GGPKFile,
     lut: HashMap<u64, FileInfo>,
 }
 
 const HASHER: BuildMurmurHash64A = BuildMurmurHash64A { seed: 0x1337b33f };
 
+/// Hash of a path, ignoring case and any leading slash. Matches the bundle index, see
+/// [FileStat::hash].
+fn path_hash(path: &str) -> u64 {
+    HASHER.hash_one_str(&path.trim_start_matches('/').to_lowercase())
+}
+
+/// Only present in archives that store files in bundles
+pub(crate) const BUNDLE_INDEX_PATH: &str = "/Bundles2/_.index.bin";
+
 /// File info + hash of full file path
 fn enumerate_file_info(
     entries: &[Entry],
@@ -63,8 +91,13 @@ fn enumerate_file_info(
                 &EntryData::File { offset, length } => {
                     // NOTE: Using our own full path hashes rather than stored MurmurHash2 values from GGPK
                     // as there are duplicate file name hashes that refer to distinct files
-                    let hash = HASHER.hash_one_str(&name.to_lowercase());
-                    Box::new(std::iter::once((hash, FileInfo { offset, length })))
+                    let hash = path_hash(&name);
+                    let info = FileInfo {
+                        offset,
+                        length,
+                        sha_digest: e.sha_digest,
+                    };
+                    Box::new(std::iter::once((hash, info)))
                 }
             }
         })
@@ -80,15 +113,21 @@ impl GGPKFS {
         let lut = HashMap::from_iter(enumerate_file_info(&index.entries, None));
 
         Ok(Self {
-            file: RefCell::new(file),
+            path: ggpk_path.to_owned(),
+            file: Mutex::new(file),
             index,
             lut,
         })
     }
 
+    /// Look up a file, ignoring case and any leading slash
+    fn lookup(&self, path: &str) -> Option<&FileInfo> {
+        self.lut.get(&path_hash(path))
+    }
+
     /// Seek + Read from underlying file
     fn _read(&self, offset: usize, length: usize) -> std::io::Result<Bytes> {
-        let mut file = self.file.borrow_mut();
+        let mut file = self.file.lock().unwrap_or_else(|e| e.into_inner());
         file.seek(SeekFrom::Start(offset as u64))?;
 
         let mut buf = vec![0; length];
@@ -98,6 +137,58 @@ impl GGPKFS {
 
         Ok(buf)
     }
+
+    /// Seek + Read without blocking. Uses a fresh file handle so reads can happen concurrently.
+    async fn _read_async(&self, offset: usize, length: usize) -> std::io::Result<Bytes> {
+        let mut file = tokio::fs::File::open(&self.path).await?;
+        file.seek(SeekFrom::Start(offset as u64)).await?;
+
+        let mut buf = vec![0; length];
+        file.read_exact(&mut buf).await?;
+
+        Ok(Bytes::from(buf))
+    }
+}
+
+/// Streams a single file's contents out of the archive
+struct FileSection {
+    file: BufReader<File>,
+    /// Location of the file's contents in the archive
+    start: u64,
+    len: u64,
+    /// Cursor position, relative to the start of the file
+    pos: u64,
+}
+
+impl Read for FileSection {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let remaining = self.len.saturating_sub(self.pos);
+        let n = buf.len().min(remaining as usize);
+        let n = self.file.read(&mut buf[..n])?;
+
+        self.pos += n as u64;
+        Ok(n)
+    }
+}
+
+impl Seek for FileSection {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        let pos = match pos {
+            SeekFrom::Start(pos) => Some(pos),
+            SeekFrom::End(delta) => self.len.checked_add_signed(delta),
+            SeekFrom::Current(delta) => self.pos.checked_add_signed(delta),
+        }
+        .ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "invalid seek to a negative or overflowing position",
+            )
+        })?;
+
+        self.file.seek(SeekFrom::Start(self.start + pos))?;
+        self.pos = pos;
+        Ok(pos)
+    }
 }
 
 fn enumerate_file_names(entries: &[Entry], prefix: Option<String>) -> impl Iterator<Item = String> {
@@ -123,7 +214,11 @@ fn enumerate_file_names(entries: &[Entry], prefix: Option<String>) -> impl Itera
 
 impl FileSystem for GGPKFS {
     fn list(&self) -> Box<dyn Iterator<Item = String> + '_> {
-        Box::new(enumerate_file_names(&self.index.entries, None))
+        // Drop the root directory's slash to match the other file systems
+        Box::new(
+            enumerate_file_names(&self.index.entries, None)
+                .map(|p| p.strip_prefix('/').map(ToOwned::to_owned).unwrap_or(p)),
+        )
     }
 
     #[allow(clippy::type_complexity)]
@@ -136,11 +231,9 @@ impl FileSystem for GGPKFS {
             .iter()
             .map(|path| {
                 let path = path.as_ref();
-                // Compute hash
-                let hash = HASHER.hash_one_str(&path.to_lowercase());
 
                 // Look up the file info for this file
-                match self.lut.get(&hash) {
+                match self.lookup(path) {
                     Some(f) => Ok((path, f)),
                     None => Err((path, Err(FSError::FileNotFound(path.to_owned())))),
                 }
@@ -167,13 +260,9 @@ impl FileSystem for GGPKFS {
     }
 
     fn read(&self, path: &str) -> Result<Bytes> {
-        // Compute the hash of this file path
-        let hash = HASHER.hash_one_str(&path.to_lowercase());
-
         // Look up the file info for this file
         let fileinfo = self
-            .lut
-            .get(&hash)
+            .lookup(path)
             .ok_or_else(|| FSError::FileNotFound(path.to_owned()))?;
 
         // Read the contents
@@ -181,6 +270,229 @@ impl FileSystem for GGPKFS {
 
         Ok(bytes)
     }
+
+    fn open(&self, path: &str) -> Result<Box<dyn FileHandle + '_>> {
+        let fileinfo = self
+            .lookup(path)
+            .ok_or_else(|| FSError::FileNotFound(path.to_owned()))?;
+
+        // Separate handle so the reader doesn't hold the lock
+        let mut file = BufReader::new(File::open(&self.path)?);
+        file.seek(SeekFrom::Start(fileinfo.offset as u64))?;
+
+        Ok(Box::new(FileSection {
+            file,
+            start: fileinfo.offset as u64,
+            len: fileinfo.length as u64,
+            pos: 0,
+        }))
+    }
+
+    fn stat(&self, path: &str) -> Result<FileStat> {
+        let fileinfo = self
+            .lookup(path)
+            .ok_or_else(|| FSError::FileNotFound(path.to_owned()))?;
+
+        Ok(FileStat {
+            offset: Some(fileinfo.offset as u64),
+            sha256: Some(fileinfo.sha_digest),
+            ..FileStat::loose(path, fileinfo.length as u64, path_hash(path))
+        })
+    }
+
+    fn exists(&self, path: &str) -> bool {
+        self.lookup(path).is_some()
+    }
+}
+
+impl AsyncFileSystem for GGPKFS {
+    fn list(&self) -> Box<dyn Iterator<Item = String> + '_> {
+        FileSystem::list(self)
+    }
+
+    fn batch_read<'a>(
+        &'a self,
+        paths: &'a [impl AsRef<str> + Sync],
+    ) -> BoxStream<'a, (Cow<'a, str>, Result<Bytes>)> {
+        // Get FileInfo's
+        let (mut fileinfos, errors) = paths
+            .iter()
+            .map(|path| {
+                let path = path.as_ref();
+
+                // Look up the file info for this file
+                match self.lookup(path) {
+                    Some(f) => Ok((path, f)),
+                    None => Err((
+                        Cow::Borrowed(path),
+                        Err(FSError::FileNotFound(path.to_owned())),
+                    )),
+                }
+            })
+            .bucket_result();
+
+        // Order by offset to hopefully get better buffer usage / less seek overhead
+        fileinfos.sort_unstable_by_key(|(_, f)| f.offset);
+
+        let file_contents =
+            futures::stream::iter(fileinfos).then(move |(path, fileinfo)| async move {
+                let res = self
+                    ._read_async(fileinfo.offset, fileinfo.length)
+                    .await
+                    .map_err(FSError::from);
+                (Cow::Borrowed(path), res)
+            });
+
+        // Add on previous errors
+        futures::stream::iter(errors).chain(file_contents).boxed()
+    }
+
+    async fn read(&self, path: &str) -> Result<Bytes> {
+        // Look up the file info for this file
+        let fileinfo = self
+            .lookup(path)
+            .ok_or_else(|| FSError::FileNotFound(path.to_owned()))?;
+
+        // Read the contents
+        let bytes = self._read_async(fileinfo.offset, fileinfo.length).await?;
+
+        Ok(bytes)
+    }
+}
+
+/// Why an entry failed verification
+#[derive(Debug, Clone)]
+pub enum Corruption {
+    /// File contents don't match the stored digest
+    Mismatch,
+    /// File extends past the end of the archive
+    Truncated,
+    /// Digest of the directory's children doesn't match the stored digest
+    Directory,
+    /// File couldn't be read
+    Read(FSError),
+}
+
+/// An entry that failed verification
+#[derive(Debug, Clone)]
+pub struct CorruptEntry {
+    pub path: String,
+    pub corruption: Corruption,
+}
+
+/// Summary of a GGPK verification
+#[derive(Debug, Clone, Default)]
+pub struct VerifyReport {
+    pub files: usize,
+    pub directories: usize,
+    /// Total size of the files checked
+    pub bytes: u64,
+    pub corrupt: Vec<CorruptEntry>,
+}
+
+impl VerifyReport {
+    pub fn is_ok(&self) -> bool {
+        self.corrupt.is_empty()
+    }
+}
+
+/// Flatten the entry tree into full paths. Directories end with a slash.
+fn walk_entries<'a>(entries: &'a [Entry], prefix: &str, out: &mut Vec<(String, &'a Entry)>) {
+    for e in entries {
+        let mut name = format!("{prefix}{}", e.name);
+
+        if let EntryData::Dir(children) = &e.data {
+            name.push('/');
+            walk_entries(children, &name, out);
+        }
+
+        out.push((name, e));
+    }
+}
+
+/// Stream a file's contents through SHA-256
+fn hash_file(file: &mut File, offset: usize, length: usize) -> std::io::Result<[u8; 32]> {
+    file.seek(SeekFrom::Start(offset as u64))?;
+
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut file.take(length as u64), &mut hasher)?;
+
+    Ok(hasher.finalize().into())
+}
+
+impl GGPKFS {
+    /// Check every entry against its stored SHA-256 digest. File digests cover their contents,
+    /// directory digests cover the digests of their children. Files are hashed in parallel.
+    pub fn verify(&self) -> Result<VerifyReport> {
+        let archive_len = std::fs::metadata(&self.path)?.len();
+
+        let mut entries = vec![];
+        walk_entries(&self.index.entries, "", &mut entries);
+
+        let mut report = VerifyReport::default();
+
+        // Directories only need the stored digests, so are cheap to check
+        for (path, entry) in &entries {
+            let EntryData::Dir(children) = &entry.data else {
+                continue;
+            };
+
+            let mut hasher = Sha256::new();
+            children.iter().for_each(|c| hasher.update(c.sha_digest));
+            if <[u8; 32]>::from(hasher.finalize()) != entry.sha_digest {
+                report.corrupt.push(CorruptEntry {
+                    path: path.clone(),
+                    corruption: Corruption::Directory,
+                });
+            }
+            report.directories += 1;
+        }
+
+        let files = entries
+            .iter()
+            .filter_map(|(path, entry)| match entry.data {
+                EntryData::File { offset, length } => Some((path, entry, offset, length)),
+                EntryData::Dir(_) => None,
+            })
+            .collect::<Vec<_>>();
+
+        // Each thread gets its own handle so reads don't contend
+        let corrupt = files
+            .par_iter()
+            .map_init(
+                || File::open(&self.path),
+                |file, (path, entry, offset, length)| {
+                    let corruption = if (offset + length) as u64 > archive_len {
+                        Some(Corruption::Truncated)
+                    } else {
+                        match file
+                            .as_mut()
+                            .map_err(|e| {
+                                FSError::from(std::io::Error::new(e.kind(), e.to_string()))
+                            })
+                            .and_then(|f| Ok(hash_file(f, *offset, *length)?))
+                        {
+                            Ok(digest) if digest == entry.sha_digest => None,
+                            Ok(_) => Some(Corruption::Mismatch),
+                            Err(e) => Some(Corruption::Read(e)),
+                        }
+                    };
+
+                    corruption.map(|corruption| CorruptEntry {
+                        path: path.to_string(),
+                        corruption,
+                    })
+                },
+            )
+            .flatten()
+            .collect::<Vec<_>>();
+
+        report.files = files.len();
+        report.bytes = files.iter().map(|(_, _, _, length)| *length as u64).sum();
+        report.corrupt.extend(corrupt);
+
+        Ok(report)
+    }
 }
 
 /// A file system over the bundles within the Content.ggpk file
@@ -188,13 +500,23 @@ pub struct GGPKBundleFS {
     ggpk: GGPKFS,
     lut: HashMap<u64, usize>,
     index: BundleIndexFile,
+    /// Decoded blocks kept around for single file reads
+    block_cache: BlockCache,
 }
 
 impl GGPKBundleFS {
+    /// Check the integrity of the underlying GGPK, see [GGPKFS::verify]
+    pub fn verify(&self) -> Result<VerifyReport> {
+        self.ggpk.verify()
+    }
+
     pub fn new(ggpk_path: &Path) -> Result<Self> {
-        let ggpk = GGPKFS::new(ggpk_path)?;
+        Self::from_ggpk(GGPKFS::new(ggpk_path)?)
+    }
 
-        let index_bytes = ggpk.read("/Bundles2/_.index.bin")?;
+    /// Read the bundle index from an already opened GGPK
+    pub fn from_ggpk(ggpk: GGPKFS) -> Result<Self> {
+        let index_bytes = FileSystem::read(&ggpk, BUNDLE_INDEX_PATH)?;
         let index_bundle = BundleParser.parse(&index_bytes)?;
         let index = BundleIndexParser.parse(&index_bundle.read_all()?)?;
 
@@ -205,7 +527,60 @@ impl GGPKBundleFS {
             .map(|(i, f)| (f.hash, i))
             .collect();
 
-        Ok(Self { ggpk, lut, index })
+        Ok(Self {
+            ggpk,
+            lut,
+            index,
+            block_cache: BlockCache::default(),
+        })
+    }
+
+    /// Cap the memory used by cached decoded blocks, in bytes. 0 disables the cache.
+    pub fn with_block_cache(mut self, capacity: usize) -> Self {
+        self.block_cache = BlockCache::new(capacity);
+        self
+    }
+
+    /// Hit/miss counters of the decoded block cache
+    pub fn block_cache_stats(&self) -> CacheStats {
+        self.block_cache.stats()
+    }
+
+    /// Look up the index entry for a file
+    fn lookup(&self, path: &str) -> Result<&IndexFileInfo> {
+        // Compute the hash of this file path
+        let hash = HASHER.hash_one_str(&path.to_lowercase());
+
+        // Look up the file info for this file
+        let index = self
+            .lut
+            .get(&hash)
+            .ok_or_else(|| FSError::FileNotFound(path.to_owned()))?;
+
+        Ok(&self.index.files[*index])
+    }
+
+    /// Path within the GGPK of the bundle holding a file
+    fn bundle_path(&self, file: &IndexFileInfo) -> String {
+        format!(
+            "/Bundles2/{}.bundle.bin",
+            self.index.bundles[file.bundle_index as usize].name
+        )
+    }
+
+    /// Load the bundle holding a file
+    fn load_bundle(&self, file: &IndexFileInfo) -> Result<BundleFile> {
+        let bundle_contents = FileSystem::read(&self.ggpk, &self.bundle_path(file))?;
+
+        Ok(BundleParser.parse(&bundle_contents)?)
+    }
+
+    /// Load a bundle from the GGPK without blocking
+    async fn load_bundle_async(&self, name: String) -> Result<BundleFile> {
+        let bundle_path = format!("/Bundles2/{name}.bundle.bin");
+        let bundle_contents = AsyncFileSystem::read(&self.ggpk, &bundle_path).await?;
+
+        Ok(BundleParser.parse(&bundle_contents)?)
     }
 }
 
@@ -260,9 +635,7 @@ impl FileSystem for GGPKBundleFS {
                 self.index.bundles[bundle_index as usize].name
             );
 
-            let bundle = self
-                .ggpk
-                .read(&bundle_path)
+            let bundle = FileSystem::read(&self.ggpk, &bundle_path)
                 .and_then(|bytes| Ok(BundleParser.parse(&bytes)?));
 
             // Read the file contents
@@ -290,26 +663,60 @@ impl FileSystem for GGPKBundleFS {
     }
 
     fn read(&self, path: &str) -> Result<Bytes> {
-        // Compute the hash of this file path
-        let hash = HASHER.hash_one_str(&path.to_lowercase());
+        let file = self.lookup(path)?;
+        if let Some(content) = self.block_cache.get(file) {
+            return Ok(content);
+        }
 
-        // Look up the file info for this file
-        let index = self
-            .lut
-            .get(&hash)
-            .ok_or_else(|| FSError::FileNotFound(path.to_owned()))?;
-        let file = &self.index.files[*index];
+        // Pull out the file's contents
+        let bundle = self.load_bundle(file)?;
+        self.block_cache.read(&bundle, file)
+    }
 
-        // Load the bundle
-        let bundle_path = format!(
-            "/Bundles2/{}.bundle.bin",
-            self.index.bundles[file.bundle_index as usize].name
-        );
-        let bundle_contents = self.ggpk.read(&bundle_path)?;
-        let bundle = BundleParser.parse(&bundle_contents)?;
+    fn open(&self, path: &str) -> Result<Box<dyn FileHandle + '_>> {
+        let file = self.lookup(path)?;
+        let bundle = self.ggpk.open(&self.bundle_path(file))?;
 
-        // Pull out the file's contents
-        let content = bundle.read_range(file.offset as usize, file.size as usize)?;
-        Ok(content)
+        Ok(Box::new(BundleReader::new(bundle, file)?))
+    }
+
+    fn stat(&self, path: &str) -> Result<FileStat> {
+        let file = self.lookup(path)?;
+        let bundle_size = self
+            .ggpk
+            .lookup(&self.bundle_path(file))
+            .map(|f| f.length as u64);
+
+        Ok(bundled::stat(&self.index, path, file, bundle_size))
+    }
+
+    fn exists(&self, path: &str) -> bool {
+        self.lookup(path).is_ok()
+    }
+
+    fn walk(&self, dir: &str) -> Box<dyn Iterator<Item = String> + '_> {
+        bundled::walk(&self.index, dir)
+    }
+}
+
+impl AsyncFileSystem for GGPKBundleFS {
+    fn list(&self) -> Box<dyn Iterator<Item = String> + '_> {
+        FileSystem::list(self)
+    }
+
+    fn batch_read<'a>(
+        &'a self,
+        paths: &'a [impl AsRef<str> + Sync],
+    ) -> BoxStream<'a, (Cow<'a, str>, Result<Bytes>)> {
+        bundled::batch_read(&self.index, &self.lut, paths, |name| {
+            self.load_bundle_async(name)
+        })
+    }
+
+    async fn read(&self, path: &str) -> Result<Bytes> {
+        bundled::read(&self.index, &self.lut, &self.block_cache, path, |name| {
+            self.load_bundle_async(name)
+        })
+        .await
     }
 }
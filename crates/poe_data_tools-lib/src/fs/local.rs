@@ -0,0 +1,152 @@
+use std::{
+    borrow::Cow,
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use bytes::Bytes;
+use futures::{StreamExt, stream::BoxStream};
+
+use super::{AsyncFileSystem, FileHandle, FileSystem, Result, error::FSError, stat::FileStat};
+use crate::hasher::murmur64a::{BuildHasherEx, BuildMurmurHash64A};
+
+const HASHER: BuildMurmurHash64A = BuildMurmurHash64A { seed: 0x1337b33f };
+
+/// Max number of files being read at once
+const CONCURRENCY: usize = 16;
+
+/// File system over a folder of loose files, eg. the output of an extraction. Paths are relative
+/// to the folder and matched case-insensitively.
+pub struct LocalDirFS {
+    /// Virtual path, as listed
+    paths: Vec<String>,
+    /// Hash of lowercased virtual path -> location on disk
+    lut: HashMap<u64, PathBuf>,
+}
+
+/// Recursively collect all files under a folder
+fn walk_dir(dir: &Path, out: &mut Vec<PathBuf>) -> std::io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let file_type = entry.file_type()?;
+
+        if file_type.is_dir() {
+            walk_dir(&entry.path(), out)?;
+        } else if file_type.is_file() {
+            out.push(entry.path());
+        }
+    }
+
+    Ok(())
+}
+
+impl LocalDirFS {
+    /// Index all files under the given folder
+    pub fn new(root: &Path) -> Result<Self> {
+        if !root.is_dir() {
+            return Err(FSError::InvalidConfig(format!(
+                "{root:?} is not a directory"
+            )));
+        }
+
+        let mut files = vec![];
+        walk_dir(root, &mut files)?;
+
+        let (paths, lut) = files
+            .into_iter()
+            .filter_map(|file| {
+                // Use forward slashes regardless of platform to match the game's paths
+                let relative = file.strip_prefix(root).ok()?;
+                let virtual_path = relative
+                    .components()
+                    .map(|c| c.as_os_str().to_string_lossy())
+                    .collect::<Vec<_>>()
+                    .join("/")
+                    .to_lowercase();
+
+                let hash = HASHER.hash_one_str(&virtual_path);
+                Some((virtual_path, (hash, file)))
+            })
+            .unzip::<_, _, Vec<_>, HashMap<_, _>>();
+
+        log::debug!("Found {} files in {root:?}", paths.len());
+
+        Ok(Self { paths, lut })
+    }
+
+    /// Location on disk of a virtual path
+    fn lookup(&self, path: &str) -> Result<&Path> {
+        let hash = HASHER.hash_one_str(&path.to_lowercase());
+
+        self.lut
+            .get(&hash)
+            .map(PathBuf::as_path)
+            .ok_or_else(|| FSError::FileNotFound(path.to_owned()))
+    }
+}
+
+impl FileSystem for LocalDirFS {
+    fn list(&self) -> Box<dyn Iterator<Item = String> + '_> {
+        Box::new(self.paths.iter().cloned())
+    }
+
+    fn batch_read<'a>(
+        &'a self,
+        paths: &'a [impl AsRef<str>],
+    ) -> Box<dyn Iterator<Item = (Cow<'a, str>, Result<Bytes>)> + 'a> {
+        Box::new(paths.iter().map(|path| {
+            let path = path.as_ref();
+            (Cow::Borrowed(path), FileSystem::read(self, path))
+        }))
+    }
+
+    fn read(&self, path: &str) -> Result<Bytes> {
+        let contents = fs::read(self.lookup(path)?)?;
+
+        Ok(Bytes::from(contents))
+    }
+
+    fn open(&self, path: &str) -> Result<Box<dyn FileHandle + '_>> {
+        Ok(Box::new(fs::File::open(self.lookup(path)?)?))
+    }
+
+    fn stat(&self, path: &str) -> Result<FileStat> {
+        let size = fs::metadata(self.lookup(path)?)?.len();
+
+        Ok(FileStat::loose(
+            path,
+            size,
+            HASHER.hash_one_str(&path.to_lowercase()),
+        ))
+    }
+
+    fn exists(&self, path: &str) -> bool {
+        self.lookup(path).is_ok()
+    }
+}
+
+impl AsyncFileSystem for LocalDirFS {
+    fn list(&self) -> Box<dyn Iterator<Item = String> + '_> {
+        FileSystem::list(self)
+    }
+
+    fn batch_read<'a>(
+        &'a self,
+        paths: &'a [impl AsRef<str> + Sync],
+    ) -> BoxStream<'a, (Cow<'a, str>, Result<Bytes>)> {
+        futures::stream::iter(paths)
+            .map(move |path| async move {
+                let path = path.as_ref();
+                (Cow::Borrowed(path), AsyncFileSystem::read(self, path).await)
+            })
+            .buffer_unordered(CONCURRENCY)
+            .boxed()
+    }
+
+    async fn read(&self, path: &str) -> Result<Bytes> {
+        let contents = tokio::fs::read(self.lookup(path)?).await?;
+
+        Ok(Bytes::from(contents))
+    }
+}
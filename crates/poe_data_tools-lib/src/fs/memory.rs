@@ -0,0 +1,113 @@
+use std::{borrow::Cow, collections::HashMap, io::Cursor};
+
+use bytes::Bytes;
+use futures::{StreamExt, stream::BoxStream};
+
+use super::{AsyncFileSystem, FileHandle, FileSystem, Result, error::FSError, stat::FileStat};
+use crate::hasher::murmur64a::{BuildHasherEx, BuildMurmurHash64A};
+
+const HASHER: BuildMurmurHash64A = BuildMurmurHash64A { seed: 0x1337b33f };
+
+/// File system holding its files in memory, eg. for tests. Paths are matched case-insensitively.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryFS {
+    /// Virtual paths, in insertion order
+    paths: Vec<String>,
+    /// Hash of lowercased path -> contents
+    files: HashMap<u64, Bytes>,
+}
+
+impl MemoryFS {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a file, returning the previous contents if it already existed
+    pub fn insert(&mut self, path: impl Into<String>, contents: impl Into<Bytes>) -> Option<Bytes> {
+        let path = path.into();
+        let previous = self
+            .files
+            .insert(HASHER.hash_one_str(&path.to_lowercase()), contents.into());
+
+        if previous.is_none() {
+            self.paths.push(path);
+        }
+
+        previous
+    }
+
+    /// Add a file, builder style
+    pub fn with_file(mut self, path: impl Into<String>, contents: impl Into<Bytes>) -> Self {
+        self.insert(path, contents);
+        self
+    }
+}
+
+impl<P: Into<String>, C: Into<Bytes>> FromIterator<(P, C)> for MemoryFS {
+    fn from_iter<T: IntoIterator<Item = (P, C)>>(iter: T) -> Self {
+        let mut fs = Self::new();
+        iter.into_iter().for_each(|(path, contents)| {
+            fs.insert(path, contents);
+        });
+        fs
+    }
+}
+
+impl FileSystem for MemoryFS {
+    fn list(&self) -> Box<dyn Iterator<Item = String> + '_> {
+        Box::new(self.paths.iter().cloned())
+    }
+
+    fn batch_read<'a>(
+        &'a self,
+        paths: &'a [impl AsRef<str>],
+    ) -> Box<dyn Iterator<Item = (Cow<'a, str>, Result<Bytes>)> + 'a> {
+        Box::new(paths.iter().map(|path| {
+            let path = path.as_ref();
+            (Cow::Borrowed(path), FileSystem::read(self, path))
+        }))
+    }
+
+    fn read(&self, path: &str) -> Result<Bytes> {
+        self.files
+            .get(&HASHER.hash_one_str(&path.to_lowercase()))
+            .cloned()
+            .ok_or_else(|| FSError::FileNotFound(path.to_owned()))
+    }
+
+    fn open(&self, path: &str) -> Result<Box<dyn FileHandle + '_>> {
+        Ok(Box::new(Cursor::new(FileSystem::read(self, path)?)))
+    }
+
+    fn stat(&self, path: &str) -> Result<FileStat> {
+        let size = FileSystem::read(self, path)?.len() as u64;
+
+        Ok(FileStat::loose(
+            path,
+            size,
+            HASHER.hash_one_str(&path.to_lowercase()),
+        ))
+    }
+
+    fn exists(&self, path: &str) -> bool {
+        self.files
+            .contains_key(&HASHER.hash_one_str(&path.to_lowercase()))
+    }
+}
+
+impl AsyncFileSystem for MemoryFS {
+    fn list(&self) -> Box<dyn Iterator<Item = String> + '_> {
+        FileSystem::list(self)
+    }
+
+    fn batch_read<'a>(
+        &'a self,
+        paths: &'a [impl AsRef<str> + Sync],
+    ) -> BoxStream<'a, (Cow<'a, str>, Result<Bytes>)> {
+        futures::stream::iter(FileSystem::batch_read(self, paths).collect::<Vec<_>>()).boxed()
+    }
+
+    async fn read(&self, path: &str) -> Result<Bytes> {
+        FileSystem::read(self, path)
+    }
+}
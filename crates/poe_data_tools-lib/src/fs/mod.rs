@@ -1,21 +1,42 @@
+pub mod block_cache;
+mod bundled;
 pub mod cdn;
+pub mod diff;
 pub mod error;
+pub mod fixture;
 pub mod ggpk;
+pub mod local;
+pub mod memory;
+pub mod overlay;
+pub mod stat;
 pub mod steam;
+pub mod tree;
 
 use std::{
     borrow::Cow,
+    io::{Read, Seek},
     path::{Path, PathBuf},
+    sync::Arc,
 };
 
+use block_cache::CacheStats;
 use bytes::Bytes;
 use cdn::CDNFS;
 use enum_dispatch::enum_dispatch;
-use error::Result;
+use error::{FSError, Result};
+use futures::stream::BoxStream;
+use glob::Pattern;
+use local::LocalDirFS;
+use memory::MemoryFS;
+use overlay::OverlayFS;
+use stat::FileStat;
 use steam::SteamFS;
+use tree::{
+    DirEntry, MATCH_OPTIONS, dir_entries, dir_prefix, glob_roots, is_under, normalise_pattern,
+};
 use url::Url;
 
-use crate::fs::ggpk::GGPKBundleFS;
+use crate::fs::ggpk::{BUNDLE_INDEX_PATH, GGPKBundleFS, GGPKFS};
 
 #[enum_dispatch]
 pub trait FileSystem {
@@ -31,6 +52,72 @@ pub trait FileSystem {
 
     /// Read a single file's contents
     fn read(&self, path: &str) -> Result<Bytes>;
+
+    /// Open a file for streaming, without loading all of its contents into memory at once
+    fn open(&self, path: &str) -> Result<Box<dyn FileHandle + '_>>;
+
+    /// Look up a file's size and where it's stored, without reading it
+    fn stat(&self, path: &str) -> Result<FileStat>;
+
+    /// Whether a file exists
+    fn exists(&self, path: &str) -> bool {
+        self.list().any(|p| p.eq_ignore_ascii_case(path))
+    }
+
+    /// Lists all paths within a directory and its subdirectories. An empty path is the root.
+    fn walk(&self, dir: &str) -> Box<dyn Iterator<Item = String> + '_> {
+        let prefix = dir_prefix(dir);
+        Box::new(self.list().filter(move |p| is_under(p, &prefix)))
+    }
+
+    /// Files and subdirectories directly within a directory, sorted by path
+    fn read_dir(&self, dir: &str) -> Result<Vec<DirEntry>> {
+        let prefix = dir_prefix(dir);
+        let entries = dir_entries(&prefix, self.walk(dir));
+
+        if entries.is_empty() && !prefix.is_empty() {
+            return Err(FSError::FileNotFound(dir.to_owned()));
+        }
+
+        Ok(entries)
+    }
+
+    /// Lists paths matching any of the glob patterns, see [MATCH_OPTIONS]. Only the directories
+    /// the patterns can match within are walked.
+    fn glob<'a>(&'a self, patterns: &'a [Pattern]) -> Box<dyn Iterator<Item = String> + 'a> {
+        // Virtual paths have no leading slash, so patterns need none to match them
+        let patterns = Arc::new(patterns.iter().map(normalise_pattern).collect::<Vec<_>>());
+
+        Box::new(glob_roots(&patterns).into_iter().flat_map(move |root| {
+            let patterns = patterns.clone();
+            self.walk(&root).filter(move |path| {
+                patterns
+                    .iter()
+                    .any(|pattern| pattern.matches_with(path, MATCH_OPTIONS))
+            })
+        }))
+    }
+}
+
+/// Handle to a file's contents, see [FileSystem::open]
+pub trait FileHandle: Read + Seek + Send {}
+
+impl<T: Read + Seek + Send> FileHandle for T {}
+
+/// Non-blocking counterpart to [FileSystem], for use from within an async runtime
+pub trait AsyncFileSystem {
+    /// Lists all paths in the index
+    fn list(&self) -> Box<dyn Iterator<Item = String> + '_>;
+
+    /// Read many files at once, optimising batch loads. Files are yielded as they become
+    /// available, so order of the paths given is not preserved.
+    fn batch_read<'a>(
+        &'a self,
+        paths: &'a [impl AsRef<str> + Sync],
+    ) -> BoxStream<'a, (Cow<'a, str>, Result<Bytes>)>;
+
+    /// Read a single file's contents
+    fn read(&self, path: &str) -> impl Future<Output = Result<Bytes>> + Send;
 }
 
 /// File system over one of several data sources
@@ -42,6 +129,15 @@ pub enum FS {
     CDN(CDNFS),
     /// Locally installed game via standalone installer
     GGPK(GGPKBundleFS),
+    /// Standalone installs from before bundles were introduced (pre-3.11), which store files
+    /// directly in the GGPK
+    LegacyGGPK(GGPKFS),
+    /// Loose files in a local folder, eg. previously extracted data
+    LocalDir(LocalDirFS),
+    /// Several file systems stacked on top of each other
+    Overlay(OverlayFS),
+    /// Files held in memory, eg. for tests
+    Memory(MemoryFS),
 }
 
 impl FS {
@@ -55,8 +151,90 @@ impl FS {
         CDNFS::new(base_url, cache_dir).map(Self::CDN)
     }
 
-    /// Initialise a file system over a standalone GGPK file
+    /// Initialise a file system over a standalone GGPK file. Archives without a bundle index are
+    /// read as legacy GGPKs.
     pub fn from_ggpk(ggpk_path: &Path) -> Result<FS> {
-        GGPKBundleFS::new(ggpk_path).map(Self::GGPK)
+        let ggpk = GGPKFS::new(ggpk_path)?;
+
+        if ggpk.exists(BUNDLE_INDEX_PATH) {
+            GGPKBundleFS::from_ggpk(ggpk).map(Self::GGPK)
+        } else {
+            log::info!("No bundle index found, reading GGPK as a legacy archive");
+            Ok(Self::LegacyGGPK(ggpk))
+        }
+    }
+
+    /// Initialise a file system over a folder of loose files
+    pub fn from_dir(dir: &Path) -> Result<FS> {
+        LocalDirFS::new(dir).map(Self::LocalDir)
+    }
+
+    /// Stack folders of loose files on top of this file system. Later folders take precedence.
+    pub fn with_overlays(self, dirs: &[impl AsRef<Path>]) -> Result<FS> {
+        if dirs.is_empty() {
+            return Ok(self);
+        }
+
+        let mut layers = dirs
+            .iter()
+            .rev()
+            .map(|dir| Self::from_dir(dir.as_ref()))
+            .collect::<Result<Vec<_>>>()?;
+        layers.push(self);
+
+        OverlayFS::new(layers).map(Self::Overlay)
+    }
+
+    /// Hit/miss counters of the decoded block cache, for backends reading from bundles
+    pub fn block_cache_stats(&self) -> Option<CacheStats> {
+        match self {
+            FS::Steam(fs) => Some(fs.block_cache_stats()),
+            FS::CDN(fs) => Some(fs.block_cache_stats()),
+            FS::GGPK(fs) => Some(fs.block_cache_stats()),
+            _ => None,
+        }
+    }
+
+    /// Game patch the files are from, eg. "3.25.1.2", where the backend records it. Overlays use
+    /// the version of their bottom-most layer that has one.
+    pub fn patch_version(&self) -> Option<&str> {
+        match self {
+            FS::CDN(fs) => fs.patch_version(),
+            FS::Overlay(fs) => fs.layers().iter().rev().find_map(|l| l.patch_version()),
+            _ => None,
+        }
+    }
+}
+
+impl AsyncFileSystem for FS {
+    fn list(&self) -> Box<dyn Iterator<Item = String> + '_> {
+        FileSystem::list(self)
+    }
+
+    fn batch_read<'a>(
+        &'a self,
+        paths: &'a [impl AsRef<str> + Sync],
+    ) -> BoxStream<'a, (Cow<'a, str>, Result<Bytes>)> {
+        match self {
+            FS::Steam(fs) => AsyncFileSystem::batch_read(fs, paths),
+            FS::CDN(fs) => AsyncFileSystem::batch_read(fs, paths),
+            FS::GGPK(fs) => AsyncFileSystem::batch_read(fs, paths),
+            FS::LegacyGGPK(fs) => AsyncFileSystem::batch_read(fs, paths),
+            FS::LocalDir(fs) => AsyncFileSystem::batch_read(fs, paths),
+            FS::Overlay(fs) => AsyncFileSystem::batch_read(fs, paths),
+            FS::Memory(fs) => AsyncFileSystem::batch_read(fs, paths),
+        }
+    }
+
+    async fn read(&self, path: &str) -> Result<Bytes> {
+        match self {
+            FS::Steam(fs) => AsyncFileSystem::read(fs, path).await,
+            FS::CDN(fs) => AsyncFileSystem::read(fs, path).await,
+            FS::GGPK(fs) => AsyncFileSystem::read(fs, path).await,
+            FS::LegacyGGPK(fs) => AsyncFileSystem::read(fs, path).await,
+            FS::LocalDir(fs) => AsyncFileSystem::read(fs, path).await,
+            FS::Overlay(fs) => AsyncFileSystem::read(fs, path).await,
+            FS::Memory(fs) => AsyncFileSystem::read(fs, path).await,
+        }
     }
 }
@@ -0,0 +1,356 @@
+use std::{
+    borrow::Cow,
+    collections::{HashMap, hash_map::Entry},
+};
+
+use bytes::Bytes;
+use futures::{
+    FutureExt, SinkExt, Stream, StreamExt,
+    channel::mpsc,
+    stream::{self, BoxStream},
+};
+
+use super::{
+    AsyncFileSystem, FS, FileHandle, FileSystem, Result,
+    error::FSError,
+    stat::FileStat,
+    tree::{dir_prefix, is_under},
+};
+use crate::hasher::murmur64a::{BuildHasherEx, BuildMurmurHash64A};
+
+const HASHER: BuildMurmurHash64A = BuildMurmurHash64A { seed: 0x1337b33f };
+
+/// Stack of file systems, where files in upper layers replace those with the same path in lower
+/// layers. Useful for testing mods or patched files on top of the game's data.
+pub struct OverlayFS {
+    /// Top-most layer first
+    layers: Vec<FS>,
+    /// Merged listing of all layers, using the casing of the top-most layer holding each file
+    paths: Vec<String>,
+    /// Hash of lowercased path -> index of the top-most layer holding it
+    owners: HashMap<u64, usize>,
+}
+
+impl OverlayFS {
+    /// Stack file systems on top of each other. Layers are given top-most first.
+    pub fn new(layers: Vec<FS>) -> Result<Self> {
+        if layers.is_empty() {
+            return Err(FSError::InvalidConfig(
+                "overlay requires at least one layer".to_owned(),
+            ));
+        }
+
+        let mut paths = vec![];
+        let mut owners = HashMap::new();
+        for (i, layer) in layers.iter().enumerate() {
+            for path in FileSystem::list(layer) {
+                if let Entry::Vacant(entry) =
+                    owners.entry(HASHER.hash_one_str(&path.to_lowercase()))
+                {
+                    entry.insert(i);
+                    paths.push(path);
+                }
+            }
+        }
+
+        Ok(Self {
+            layers,
+            paths,
+            owners,
+        })
+    }
+
+    /// Layers, top-most first
+    pub fn layers(&self) -> &[FS] {
+        &self.layers
+    }
+
+    /// Index of the layer a file will be read from
+    fn owner(&self, path: &str) -> Option<usize> {
+        self.owners
+            .get(&HASHER.hash_one_str(&path.to_lowercase()))
+            .copied()
+    }
+
+    /// Group the paths by the layer they're read from, along with those missing from every layer.
+    ///
+    /// Each layer is handed all of its files at once so its own batching still applies, while
+    /// never being asked for files a higher layer overrides.
+    fn group<'a, T: AsRef<str>>(&self, paths: &'a [T]) -> (Vec<Vec<&'a str>>, Vec<&'a str>) {
+        let mut groups = vec![vec![]; self.layers.len()];
+        let mut missing = vec![];
+        for path in paths.iter().map(|p| p.as_ref()) {
+            match self.owner(path) {
+                Some(layer) => groups[layer].push(path),
+                None => missing.push(path),
+            }
+        }
+
+        (groups, missing)
+    }
+}
+
+/// Stream what `send` sends to the channel while driving it. Lets a future own a layer's grouped
+/// paths while streaming the files the layer reads from them, rather than collecting them all.
+fn sent<'a, T: 'a>(
+    rx: mpsc::Receiver<T>,
+    send: impl Future<Output = ()> + 'a,
+) -> impl Stream<Item = T> + 'a {
+    stream::select(rx, send.into_stream().filter_map(|()| async { None }))
+}
+
+/// Error for a requested file not found in any layer
+fn not_found(path: &str) -> (Cow<'_, str>, Result<Bytes>) {
+    (
+        Cow::Borrowed(path),
+        Err(FSError::FileNotFound(path.to_owned())),
+    )
+}
+
+impl FileSystem for OverlayFS {
+    fn list(&self) -> Box<dyn Iterator<Item = String> + '_> {
+        Box::new(self.paths.iter().cloned())
+    }
+
+    fn batch_read<'a>(
+        &'a self,
+        paths: &'a [impl AsRef<str>],
+    ) -> Box<dyn Iterator<Item = (Cow<'a, str>, Result<Bytes>)> + 'a> {
+        let (groups, missing) = self.group(paths);
+
+        let found = groups
+            .into_iter()
+            .zip(&self.layers)
+            .filter(|(group, _)| !group.is_empty())
+            .map(|(group, layer)| {
+                let (mut tx, rx) = mpsc::channel(0);
+                let read = async move {
+                    for (path, contents) in FileSystem::batch_read(layer, &group) {
+                        // Receiver has been dropped, nobody wants the rest of the batch
+                        if tx
+                            .send((Cow::Owned(path.into_owned()), contents))
+                            .await
+                            .is_err()
+                        {
+                            break;
+                        }
+                    }
+                };
+
+                sent(rx, read)
+            });
+
+        // Layers are read one after another. Reading only ever waits for the previous file to be
+        // taken from the channel, so polling always makes progress without an executor, which
+        // couldn't be nested for overlays of overlays.
+        let mut found = stream::iter(found).flatten().boxed_local();
+        let found = std::iter::from_fn(move || {
+            loop {
+                if let Some(next) = found.next().now_or_never() {
+                    return next;
+                }
+            }
+        });
+
+        Box::new(missing.into_iter().map(not_found).chain(found))
+    }
+
+    fn read(&self, path: &str) -> Result<Bytes> {
+        let layer = self
+            .owner(path)
+            .ok_or_else(|| FSError::FileNotFound(path.to_owned()))?;
+
+        FileSystem::read(&self.layers[layer], path)
+    }
+
+    fn open(&self, path: &str) -> Result<Box<dyn FileHandle + '_>> {
+        let layer = self
+            .owner(path)
+            .ok_or_else(|| FSError::FileNotFound(path.to_owned()))?;
+
+        self.layers[layer].open(path)
+    }
+
+    fn stat(&self, path: &str) -> Result<FileStat> {
+        let layer = self
+            .owner(path)
+            .ok_or_else(|| FSError::FileNotFound(path.to_owned()))?;
+
+        self.layers[layer].stat(path)
+    }
+
+    fn exists(&self, path: &str) -> bool {
+        self.owner(path).is_some()
+    }
+
+    fn walk(&self, dir: &str) -> Box<dyn Iterator<Item = String> + '_> {
+        let prefix = dir_prefix(dir);
+        Box::new(
+            self.paths
+                .iter()
+                .filter(move |path| is_under(path, &prefix))
+                .cloned(),
+        )
+    }
+}
+
+impl AsyncFileSystem for OverlayFS {
+    fn list(&self) -> Box<dyn Iterator<Item = String> + '_> {
+        FileSystem::list(self)
+    }
+
+    fn batch_read<'a>(
+        &'a self,
+        paths: &'a [impl AsRef<str> + Sync],
+    ) -> BoxStream<'a, (Cow<'a, str>, Result<Bytes>)> {
+        let (groups, missing) = self.group(paths);
+
+        // Layers are read concurrently
+        let found = groups
+            .into_iter()
+            .zip(&self.layers)
+            .filter(|(group, _)| !group.is_empty())
+            .map(|(group, layer)| {
+                let (mut tx, rx) = mpsc::channel(0);
+                let read = async move {
+                    let mut contents = AsyncFileSystem::batch_read(layer, &group);
+                    while let Some((path, contents)) = contents.next().await {
+                        // Receiver has been dropped, nobody wants the rest of the batch
+                        if tx
+                            .send((Cow::Owned(path.into_owned()), contents))
+                            .await
+                            .is_err()
+                        {
+                            break;
+                        }
+                    }
+                };
+
+                sent(rx, read).boxed()
+            });
+
+        stream::iter(missing.into_iter().map(not_found))
+            .chain(stream::select_all(found))
+            .boxed()
+    }
+
+    async fn read(&self, path: &str) -> Result<Bytes> {
+        let layer = self
+            .owner(path)
+            .ok_or_else(|| FSError::FileNotFound(path.to_owned()))?;
+
+        // Read through the layer's boxed batch stream rather than awaiting its read future
+        // directly, as a layer may itself be an overlay whose future would then contain its own
+        let paths = [path];
+        AsyncFileSystem::batch_read(&self.layers[layer], &paths)
+            .next()
+            .await
+            .map(|(_, contents)| contents)
+            .unwrap_or_else(|| Err(FSError::FileNotFound(path.to_owned())))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::executor::block_on;
+
+    use super::*;
+    use crate::fs::memory::MemoryFS;
+
+    fn overlay() -> FS {
+        let base = MemoryFS::new()
+            .with_file("data/a.datc64", "base a")
+            .with_file("data/b.datc64", "base b")
+            .with_file("data/c.datc64", "base c");
+        let modded = MemoryFS::new().with_file("Data/B.datc64", "mod b");
+
+        let inner = OverlayFS::new(vec![FS::Memory(modded), FS::Memory(base)]).unwrap();
+        let top = MemoryFS::new().with_file("data/c.datc64", "top c");
+
+        FS::Overlay(OverlayFS::new(vec![FS::Memory(top), FS::Overlay(inner)]).unwrap())
+    }
+
+    #[test]
+    fn layers() {
+        let fs = overlay();
+        let paths = [
+            "data/a.datc64",
+            "data/b.datc64",
+            "data/missing.datc64",
+            "data/c.datc64",
+        ];
+
+        let mut contents = FileSystem::batch_read(&fs, &paths)
+            .map(|(path, res)| (path.into_owned(), res.ok()))
+            .collect::<Vec<_>>();
+        contents.sort();
+        assert_eq!(
+            contents,
+            [
+                ("data/a.datc64".to_owned(), Some(Bytes::from("base a"))),
+                ("data/b.datc64".to_owned(), Some(Bytes::from("mod b"))),
+                ("data/c.datc64".to_owned(), Some(Bytes::from("top c"))),
+                ("data/missing.datc64".to_owned(), None),
+            ]
+        );
+
+        let mut streamed = block_on(
+            AsyncFileSystem::batch_read(&fs, &paths)
+                .map(|(path, res)| (path.into_owned(), res.ok()))
+                .collect::<Vec<_>>(),
+        );
+        streamed.sort();
+        assert_eq!(streamed, contents);
+    }
+
+    #[test]
+    fn interleaved() {
+        let base = MemoryFS::new()
+            .with_file("a", "base a")
+            .with_file("b", "base b")
+            .with_file("c", "base c")
+            .with_file("d", "base d");
+        let modded = MemoryFS::new()
+            .with_file("b", "mod b")
+            .with_file("d", "mod d");
+        let fs = OverlayFS::new(vec![FS::Memory(modded), FS::Memory(base)]).unwrap();
+
+        // Each layer reads all of its files in one batch, rather than one per run of overrides
+        let paths = ["a", "b", "c", "d", "e"];
+        let contents = FileSystem::batch_read(&fs, &paths)
+            .map(|(path, res)| (path.into_owned(), res.ok()))
+            .collect::<Vec<_>>();
+        let expected = [
+            ("e", None),
+            ("b", Some("mod b")),
+            ("d", Some("mod d")),
+            ("a", Some("base a")),
+            ("c", Some("base c")),
+        ]
+        .map(|(path, contents)| (path.to_owned(), contents.map(Bytes::from)));
+        assert_eq!(contents, expected);
+
+        let mut streamed = block_on(
+            AsyncFileSystem::batch_read(&fs, &paths)
+                .map(|(path, res)| (path.into_owned(), res.ok()))
+                .collect::<Vec<_>>(),
+        );
+        streamed.sort();
+        let mut expected = expected.to_vec();
+        expected.sort();
+        assert_eq!(streamed, expected);
+    }
+
+    #[test]
+    fn async_read() {
+        let fs = overlay();
+
+        let read = |path| block_on(AsyncFileSystem::read(&fs, path));
+        assert_eq!(read("data/b.datc64").unwrap(), "mod b");
+        assert_eq!(read("DATA/C.datc64").unwrap(), "top c");
+        assert!(matches!(
+            read("data/missing.datc64"),
+            Err(FSError::FileNotFound(_))
+        ));
+    }
+}
@@ -0,0 +1,57 @@
+use serde::{Serialize, Serializer};
+
+/// Metadata about a file that can be gathered without reading it, see
+/// [FileSystem::stat](super::FileSystem::stat)
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FileStat {
+    pub path: String,
+    /// Uncompressed size in bytes
+    pub size: u64,
+    /// Hash of the lowercased path without any leading slash, as used by the bundle index. Files
+    /// stored directly in a GGPK are hashed the same way.
+    pub hash: u64,
+    /// Name of the bundle holding the file, for file systems built on bundles
+    pub bundle: Option<String>,
+    /// Offset within the bundle's uncompressed contents, or within the GGPK for files stored
+    /// directly in one
+    pub offset: Option<u64>,
+    /// Compressed size of the bundle holding the file, where known without fetching it
+    pub bundle_size: Option<u64>,
+    /// Digest stored in the GGPK, for files stored directly in one
+    #[serde(serialize_with = "serialize_hex")]
+    pub sha256: Option<[u8; 32]>,
+}
+
+impl FileStat {
+    /// Stat of a file that's stored as-is
+    pub(crate) fn loose(path: &str, size: u64, hash: u64) -> Self {
+        Self {
+            path: path.to_owned(),
+            size,
+            hash,
+            bundle: None,
+            offset: None,
+            bundle_size: None,
+            sha256: None,
+        }
+    }
+
+    /// [FileStat::sha256] as a hex string
+    pub fn sha256_hex(&self) -> Option<String> {
+        self.sha256.as_ref().map(|digest| to_hex(digest))
+    }
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+fn serialize_hex<S: Serializer>(
+    digest: &Option<[u8; 32]>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    match digest {
+        Some(digest) => serializer.serialize_some(&to_hex(digest)),
+        None => serializer.serialize_none(),
+    }
+}
@@ -6,14 +6,25 @@ use std::{
 };
 
 use bytes::Bytes;
+use futures::stream::BoxStream;
 use iterators_extended::bucket::Bucket;
 
-use super::{FileSystem, Result, error::FSError};
+use super::{
+    AsyncFileSystem, FileHandle, FileSystem, Result,
+    block_cache::{BlockCache, CacheStats},
+    bundled,
+    error::FSError,
+    stat::FileStat,
+};
 use crate::{
+    bundle::BundleReader,
     file_parsers::{
         FileParser,
         bundle::{BundleParser, types::BundleFile},
-        bundle_index::{BundleIndexParser, types::BundleIndexFile},
+        bundle_index::{
+            BundleIndexParser,
+            types::{BundleIndexFile, FileInfo},
+        },
     },
     hasher::murmur64a::{BuildHasherEx, BuildMurmurHash64A},
     path::parse_paths,
@@ -26,6 +37,8 @@ pub struct SteamFS {
     index: BundleIndexFile,
     lut: HashMap<u64, usize>,
     steam_folder: PathBuf,
+    /// Decoded blocks kept around for single file reads
+    block_cache: BlockCache,
 }
 
 impl SteamFS {
@@ -46,8 +59,57 @@ impl SteamFS {
             index,
             lut,
             steam_folder: steam_folder.clone(),
+            block_cache: BlockCache::default(),
         })
     }
+
+    /// Cap the memory used by cached decoded blocks, in bytes. 0 disables the cache.
+    pub fn with_block_cache(mut self, capacity: usize) -> Self {
+        self.block_cache = BlockCache::new(capacity);
+        self
+    }
+
+    /// Hit/miss counters of the decoded block cache
+    pub fn block_cache_stats(&self) -> CacheStats {
+        self.block_cache.stats()
+    }
+
+    /// Look up the index entry for a file
+    fn lookup(&self, path: &str) -> Result<&FileInfo> {
+        // Compute the hash of this file path
+        let hash = HASHER.hash_one_str(&path.to_lowercase());
+
+        // Look up the file info for this file
+        let file_index = self
+            .lut
+            .get(&hash)
+            .ok_or_else(|| FSError::FileNotFound(path.to_owned()))?;
+
+        Ok(&self.index.files[*file_index])
+    }
+
+    /// Location on disk of the bundle holding a file
+    fn bundle_path(&self, file: &FileInfo) -> PathBuf {
+        self.steam_folder.join(format!(
+            "Bundles2/{}.bundle.bin",
+            self.index.bundles[file.bundle_index as usize].name
+        ))
+    }
+
+    /// Load the bundle holding a file
+    fn load_bundle(&self, file: &FileInfo) -> Result<BundleFile> {
+        load_bundle_content(&self.bundle_path(file))
+    }
+
+    /// Load a bundle from disk without blocking
+    async fn load_bundle_async(&self, name: String) -> Result<BundleFile> {
+        let bundle_path = self
+            .steam_folder
+            .join(format!("Bundles2/{name}.bundle.bin"));
+        let bundle_content = tokio::fs::read(bundle_path).await?;
+
+        Ok(BundleParser.parse(&bundle_content)?)
+    }
 }
 
 impl FileSystem for SteamFS {
@@ -128,27 +190,58 @@ impl FileSystem for SteamFS {
     }
 
     fn read(&self, path: &str) -> Result<Bytes> {
-        // Compute the hash of this file path
-        let hash = HASHER.hash_one_str(&path.to_lowercase());
+        let file = self.lookup(path)?;
+        if let Some(bytes) = self.block_cache.get(file) {
+            return Ok(bytes);
+        }
 
-        // Look up the file info for this file
-        let file_index = self
-            .lut
-            .get(&hash)
-            .ok_or_else(|| FSError::FileNotFound(path.to_owned()))?;
-        let file = &self.index.files[*file_index];
+        // Pull out the file's contents
+        let bundle = self.load_bundle(file)?;
+        self.block_cache.read(&bundle, file)
+    }
 
-        // Load the bundle
-        let bundle_path = self.steam_folder.join(format!(
-            "Bundles2/{}.bundle.bin",
-            self.index.bundles[file.bundle_index as usize].name
-        ));
-        let bundle = load_bundle_content(&bundle_path)?;
+    fn open(&self, path: &str) -> Result<Box<dyn FileHandle + '_>> {
+        let file = self.lookup(path)?;
+        let bundle = fs::File::open(self.bundle_path(file))?;
 
-        // Pull out the file's contents
-        let bytes = bundle.read_range(file.offset as usize, file.size as usize)?;
+        Ok(Box::new(BundleReader::new(bundle, file)?))
+    }
+
+    fn stat(&self, path: &str) -> Result<FileStat> {
+        let file = self.lookup(path)?;
+        let bundle_size = fs::metadata(self.bundle_path(file)).ok().map(|m| m.len());
 
-        Ok(bytes)
+        Ok(bundled::stat(&self.index, path, file, bundle_size))
+    }
+
+    fn exists(&self, path: &str) -> bool {
+        self.lookup(path).is_ok()
+    }
+
+    fn walk(&self, dir: &str) -> Box<dyn Iterator<Item = String> + '_> {
+        bundled::walk(&self.index, dir)
+    }
+}
+
+impl AsyncFileSystem for SteamFS {
+    fn list(&self) -> Box<dyn Iterator<Item = String> + '_> {
+        FileSystem::list(self)
+    }
+
+    fn batch_read<'a>(
+        &'a self,
+        paths: &'a [impl AsRef<str> + Sync],
+    ) -> BoxStream<'a, (Cow<'a, str>, Result<Bytes>)> {
+        bundled::batch_read(&self.index, &self.lut, paths, |name| {
+            self.load_bundle_async(name)
+        })
+    }
+
+    async fn read(&self, path: &str) -> Result<Bytes> {
+        bundled::read(&self.index, &self.lut, &self.block_cache, path, |name| {
+            self.load_bundle_async(name)
+        })
+        .await
     }
 }
 
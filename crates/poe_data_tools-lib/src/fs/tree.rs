@@ -0,0 +1,153 @@
+//! Helpers for treating the flat list of virtual paths as a directory tree
+use std::collections::BTreeMap;
+
+use glob::{MatchOptions, Pattern};
+
+/// Options used when matching virtual paths against globs. Wildcards don't cross directories, so
+/// `*` only matches within a single directory and `**` is needed to recurse.
+pub const MATCH_OPTIONS: MatchOptions = MatchOptions {
+    case_sensitive: true,
+    require_literal_separator: true,
+    require_literal_leading_dot: false,
+};
+
+/// Item within a directory, see [FileSystem::read_dir](super::FileSystem::read_dir)
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct DirEntry {
+    /// Full virtual path, directories have no trailing slash
+    pub path: String,
+    pub is_dir: bool,
+}
+
+impl DirEntry {
+    /// Last component of the path
+    pub fn name(&self) -> &str {
+        self.path.rsplit('/').next().unwrap_or(&self.path)
+    }
+}
+
+/// Normalise a directory path into a prefix of the paths within it, eg. `/Data` -> `data/`. The
+/// root directory gives an empty prefix.
+pub(crate) fn dir_prefix(dir: &str) -> String {
+    let dir = dir.trim_matches('/').to_lowercase();
+    if dir.is_empty() { dir } else { dir + "/" }
+}
+
+/// Whether a path is within the directory with the given prefix, see [dir_prefix]
+pub(crate) fn is_under(path: &str, prefix: &str) -> bool {
+    path.get(..prefix.len())
+        .is_some_and(|start| start.eq_ignore_ascii_case(prefix))
+}
+
+/// Entries directly within a directory, given every path under it
+pub(crate) fn dir_entries(prefix: &str, paths: impl Iterator<Item = String>) -> Vec<DirEntry> {
+    let mut entries = BTreeMap::new();
+    for path in paths {
+        match path[prefix.len()..].find('/') {
+            Some(i) => entries.insert(path[..prefix.len() + i].to_owned(), true),
+            None => entries.insert(path, false),
+        };
+    }
+
+    entries
+        .into_iter()
+        .map(|(path, is_dir)| DirEntry { path, is_dir })
+        .collect()
+}
+
+/// Drop any leading slashes from a glob, as virtual paths have none
+pub(crate) fn normalise_pattern(pattern: &Pattern) -> Pattern {
+    match pattern.as_str().strip_prefix('/') {
+        Some(trimmed) => Pattern::new(trimmed.trim_start_matches('/'))
+            .expect("Removing a leading slash keeps the pattern valid"),
+        None => pattern.clone(),
+    }
+}
+
+/// Deepest directory a glob can match within, ie. its leading components without wildcards
+fn literal_dir(pattern: &Pattern) -> &str {
+    let pattern = pattern.as_str();
+    let literal = pattern
+        .find(['*', '?', '['])
+        .map(|i| &pattern[..i])
+        .unwrap_or(pattern);
+
+    literal.rfind('/').map(|i| &literal[..i]).unwrap_or("")
+}
+
+/// Directories to walk to find every match of the patterns. Directories nested in another one
+/// are dropped, so that no path is visited twice.
+pub(crate) fn glob_roots(patterns: &[Pattern]) -> Vec<String> {
+    let mut prefixes = patterns
+        .iter()
+        .map(|p| dir_prefix(literal_dir(&normalise_pattern(p))))
+        .collect::<Vec<_>>();
+    prefixes.sort();
+    prefixes.dedup();
+
+    // Sorted, so any ancestor comes before its descendants
+    let mut roots = Vec::<String>::new();
+    for prefix in prefixes {
+        if !roots.iter().any(|root| prefix.starts_with(root.as_str())) {
+            roots.push(prefix);
+        }
+    }
+
+    roots
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fs::{FileSystem, memory::MemoryFS};
+
+    #[test]
+    fn roots() {
+        let patterns = [
+            "data/*.datc64",
+            "data/balance/**",
+            "art/2d/x.dds",
+            "/art/2dx/*",
+        ]
+        .map(|p| Pattern::new(p).unwrap());
+        assert_eq!(glob_roots(&patterns), ["art/2d/", "art/2dx/", "data/"]);
+
+        let patterns = ["data/*.datc64", "**/*.dds"].map(|p| Pattern::new(p).unwrap());
+        assert_eq!(glob_roots(&patterns), [""]);
+    }
+
+    #[test]
+    fn leading_slash() {
+        let fs = MemoryFS::new()
+            .with_file("art/2dx/a.dds", "")
+            .with_file("art/b.dds", "");
+        let patterns = [Pattern::new("/art/2dx/*").unwrap()];
+
+        assert_eq!(fs.glob(&patterns).collect::<Vec<_>>(), ["art/2dx/a.dds"]);
+    }
+
+    #[test]
+    fn entries() {
+        let paths = [
+            "data/a.datc64",
+            "data/balance/b.datc64",
+            "data/balance/c.datc64",
+        ];
+        let entries = dir_entries("data/", paths.into_iter().map(String::from));
+
+        assert_eq!(
+            entries,
+            [
+                DirEntry {
+                    path: "data/a.datc64".into(),
+                    is_dir: false
+                },
+                DirEntry {
+                    path: "data/balance".into(),
+                    is_dir: true
+                },
+            ]
+        );
+        assert_eq!(entries[1].name(), "balance");
+    }
+}
@@ -0,0 +1,275 @@
+use super::{
+    GLTFError, Result,
+    math::{self, Mat4},
+    skeleton::bone_matrix,
+};
+use crate::file_parsers::ast::types::{ASTFile, Animation, Track};
+
+/// Used when an animation doesn't declare a framerate
+const DEFAULT_FRAMERATE: f32 = 30.;
+
+/// A local transform split into translation, rotation and scale
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TRS {
+    pub translation: [f32; 3],
+    /// Unit quaternion, xyzw
+    pub rotation: [f32; 4],
+    pub scale: [f32; 3],
+}
+
+impl TRS {
+    pub fn from_matrix(m: &Mat4) -> Self {
+        let (translation, rotation, scale) = math::decompose(m);
+
+        Self {
+            translation,
+            rotation,
+            scale,
+        }
+    }
+}
+
+/// Keyframes for a single bone, one for every frame of the clip
+#[derive(Debug, Clone)]
+pub struct BoneAnimation {
+    pub bone: usize,
+    pub frames: Vec<TRS>,
+}
+
+/// An animation sampled at a fixed framerate
+#[derive(Debug, Clone)]
+pub struct Clip {
+    pub name: String,
+    /// Frames per second
+    pub framerate: f32,
+    pub num_frames: usize,
+    /// Only bones with animation data are included
+    pub bones: Vec<BoneAnimation>,
+}
+
+impl Clip {
+    /// Sample every track of an animation once per frame. Components missing from a track are held
+    /// at the bone's bind pose.
+    pub fn sample(ast: &ASTFile, animation: &Animation) -> Result<Self> {
+        let tracks = ast.tracks(animation)?;
+
+        let framerate = match animation.framerate {
+            0 => DEFAULT_FRAMERATE,
+            f => f as f32,
+        };
+
+        // Every key starts with its frame number
+        let last_frame = tracks
+            .iter()
+            .flat_map(|t| {
+                let scales = t.scales.iter().map(|k| k[0]);
+                let rotations = t.rotations.iter().map(|k| k[0]);
+                let positions = t.positions.iter().map(|k| k[0]);
+                scales.chain(rotations).chain(positions)
+            })
+            .fold(0_f32, f32::max);
+        let num_frames = last_frame.ceil() as usize + 1;
+
+        let bones = tracks
+            .iter()
+            .map(|track| {
+                let bone = track.header.index as usize;
+                let bind = ast
+                    .bones
+                    .get(bone)
+                    .ok_or(GLTFError::BoneOutOfBounds {
+                        index: bone,
+                        length: ast.bones.len(),
+                    })
+                    .map(|b| TRS::from_matrix(&bone_matrix(b)))?;
+
+                let frames = (0..num_frames)
+                    .map(|frame| sample_track(track, frame as f32, &bind))
+                    .collect();
+
+                Ok(BoneAnimation { bone, frames })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(Self {
+            name: animation.name.clone(),
+            framerate,
+            num_frames,
+            bones,
+        })
+    }
+
+    /// Time in seconds of each frame
+    pub fn times(&self) -> Vec<f32> {
+        (0..self.num_frames)
+            .map(|f| f as f32 / self.framerate)
+            .collect()
+    }
+
+    /// Length of the clip in seconds
+    pub fn duration(&self) -> f32 {
+        self.num_frames.saturating_sub(1) as f32 / self.framerate
+    }
+}
+
+/// Sample a single track at a (fractional) frame
+fn sample_track(track: &Track, frame: f32, bind: &TRS) -> TRS {
+    TRS {
+        translation: sample_keys(&track.positions, frame, math::lerp).unwrap_or(bind.translation),
+        rotation: sample_keys(&track.rotations, frame, math::slerp)
+            .map(math::normalise_quat)
+            .unwrap_or(bind.rotation),
+        scale: sample_keys(&track.scales, frame, math::lerp).unwrap_or(bind.scale),
+    }
+}
+
+/// Interpolate a value from keys laid out as [frame, values..], clamping outside the key range
+fn sample_keys<const N: usize>(
+    keys: &[impl AsRef<[f32]>],
+    frame: f32,
+    interpolate: impl Fn([f32; N], [f32; N], f32) -> [f32; N],
+) -> Option<[f32; N]> {
+    let value = |key: &[f32]| -> [f32; N] { std::array::from_fn(|i| key[i + 1]) };
+
+    let next = keys.partition_point(|k| k.as_ref()[0] <= frame);
+    match (keys.get(next.wrapping_sub(1)), keys.get(next)) {
+        (Some(a), Some(b)) => {
+            let (a, b) = (a.as_ref(), b.as_ref());
+            let t = (frame - a[0]) / (b[0] - a[0]);
+            Some(interpolate(value(a), value(b), t))
+        }
+        (Some(k), None) | (None, Some(k)) => Some(value(k.as_ref())),
+        (None, None) => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::file_parsers::ast::types::{Bone, Header, TrackHeader};
+
+    fn bone(name: &str, transform: [[f32; 4]; 4]) -> Bone {
+        Bone {
+            sibling: None,
+            child: None,
+            transform,
+            name_length: name.len() as u8,
+            unk1: None,
+            name: name.to_owned(),
+        }
+    }
+
+    fn track(bone: u32, scales: Vec<[f32; 4]>, positions: Vec<[f32; 4]>) -> Track {
+        Track {
+            header: TrackHeader {
+                unk1: 0,
+                index: bone,
+                num_scales: scales.len() as u32,
+                num_rotations: 0,
+                num_positions: positions.len() as u32,
+                num_unk2: 0,
+                num_unk3: 0,
+                num_unk4: 0,
+                unk5: None,
+            },
+            scales,
+            rotations: vec![],
+            positions,
+            unk2s: vec![],
+            unk3s: vec![],
+            unk4s: vec![],
+        }
+    }
+
+    #[test]
+    fn sample() {
+        let identity = [
+            [1., 0., 0., 0.],
+            [0., 1., 0., 0.],
+            [0., 0., 1., 0.],
+            [0., 0., 0., 1.],
+        ];
+        // Scaled by 2 and moved to (7, 8, 9)
+        let scaled = [
+            [2., 0., 0., 0.],
+            [0., 2., 0., 0.],
+            [0., 0., 2., 0.],
+            [7., 8., 9., 1.],
+        ];
+
+        let mut animation = Animation {
+            num_tracks: 2,
+            unk1: 0,
+            framerate: 0,
+            unk2: 0,
+            unk3: None,
+            name_length: 4,
+            parent_name_length: None,
+            data_location: None,
+            name: "walk".to_owned(),
+            parent_name: None,
+            data: Some(vec![
+                track(0, vec![[2., 3., 3., 3.]], vec![]),
+                track(1, vec![], vec![[0., 0., 0., 0.], [4., 4., 8., 12.]]),
+            ]),
+        };
+        let ast = ASTFile {
+            header: Header {
+                version: 7,
+                num_bones: 2,
+                unk1: 0,
+                num_animations: 1,
+                unk3: 0,
+                unk4: 0,
+                num_lights: 0,
+            },
+            bones: vec![bone("root", identity), bone("arm", scaled)],
+            lights: vec![],
+            animations: vec![],
+            bundle: None,
+        };
+
+        let clip = Clip::sample(&ast, &animation).unwrap();
+        // Frames 0 to 4, at the default framerate
+        assert_eq!(clip.num_frames, 5);
+        assert_eq!(clip.framerate, DEFAULT_FRAMERATE);
+
+        // Only scale keyed, held at its single key
+        let root = &clip.bones[0];
+        assert_eq!(root.bone, 0);
+        assert!(root.frames.iter().all(|f| f.scale == [3., 3., 3.]));
+        assert_eq!(root.frames[0].translation, [0., 0., 0.]);
+        assert_eq!(root.frames[0].rotation, [0., 0., 0., 1.]);
+
+        // Only position keyed, rotation and scale come from the bind pose
+        let arm = &clip.bones[1];
+        assert_eq!(arm.frames[2].translation, [2., 4., 6.]);
+        assert_eq!(arm.frames[2].scale, [2., 2., 2.]);
+        assert_eq!(arm.frames[2].rotation, [0., 0., 0., 1.]);
+
+        // Track for a bone that doesn't exist
+        animation.data.as_mut().unwrap()[0].header.index = 5;
+        assert!(matches!(
+            Clip::sample(&ast, &animation),
+            Err(GLTFError::BoneOutOfBounds {
+                index: 5,
+                length: 2
+            })
+        ));
+
+        // Neither inline nor bundled track data
+        animation.data = None;
+        assert!(Clip::sample(&ast, &animation).is_err());
+    }
+
+    #[test]
+    fn test_sample_keys() {
+        let keys = [[0., 0., 0., 0.], [10., 10., 20., 30.]];
+
+        assert_eq!(sample_keys(&keys, 5., math::lerp), Some([5., 10., 15.]));
+        // Clamped outside the key range
+        assert_eq!(sample_keys(&keys, -1., math::lerp), Some([0., 0., 0.]));
+        assert_eq!(sample_keys(&keys, 20., math::lerp), Some([10., 20., 30.]));
+        assert_eq!(sample_keys::<3>(&[] as &[[f32; 4]], 0., math::lerp), None);
+    }
+}
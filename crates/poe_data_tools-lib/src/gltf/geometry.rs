@@ -0,0 +1,335 @@
+use super::{GLTFError, Result};
+use crate::file_parsers::{
+    dolm::types::{Dolm, DolmVertex, IndexBuffer},
+    fmt::{self, types::FMTFile},
+    smd::{self, types::SMDFile},
+    tgm::{self, types::TGMFile},
+};
+
+/// Format-agnostic vertex, with attributes already converted to their glTF representation
+#[derive(Debug, Clone, Default)]
+pub struct Vertex {
+    pub pos: [f32; 3],
+    pub normal: Option<[f32; 3]>,
+    pub tangent: Option<[f32; 4]>,
+    pub tex_coord0: Option<[f32; 2]>,
+    pub tex_coord1: Option<[f32; 2]>,
+    /// Indices into the skeleton's bone list
+    pub joints: Option<[u8; 4]>,
+    /// Normalised 0-255 weights for each joint
+    pub weights: Option<[u8; 4]>,
+}
+
+/// Decode a signed normalised byte vector
+fn unpack_snorm(v: &[i8]) -> impl Iterator<Item = f32> + '_ {
+    v.iter().map(|&c| (c as f32 / 127.).max(-1.))
+}
+
+/// glTF requires normals to be unit length
+fn normalise(v: [f32; 3]) -> Option<[f32; 3]> {
+    let length = v.iter().map(|c| c * c).sum::<f32>().sqrt();
+
+    (length > f32::EPSILON).then(|| v.map(|c| c / length))
+}
+
+impl From<&DolmVertex> for Vertex {
+    fn from(v: &DolmVertex) -> Self {
+        let mut normal = [0.; 3];
+        normal
+            .iter_mut()
+            .zip(unpack_snorm(&v.normal))
+            .for_each(|(n, c)| *n = c);
+
+        let mut tangent = [0.; 3];
+        tangent
+            .iter_mut()
+            .zip(unpack_snorm(&v.tangent))
+            .for_each(|(t, c)| *t = c);
+        // W component only holds the handedness of the bitangent
+        let handedness = if v.tangent[3] < 0 { -1. } else { 1. };
+        let tangent = normalise(tangent).map(|[x, y, z]| [x, y, z, handedness]);
+
+        Self {
+            pos: v.pos,
+            normal: normalise(normal),
+            tangent,
+            tex_coord0: v.tex_coord0.map(|uv| uv.map(|c| c as f32)),
+            tex_coord1: v.tex_coord1.map(|uv| uv.map(|c| c as f32)),
+            joints: v.skin_bones,
+            weights: v.skin_weights,
+        }
+    }
+}
+
+impl From<&fmt::types::Vertex> for Vertex {
+    fn from(v: &fmt::types::Vertex) -> Self {
+        Self {
+            pos: v.pos,
+            tex_coord0: Some(v.uv.map(|c| c as f32)),
+            tex_coord1: v.uv2.map(|uv| uv.map(|c| c as f32)),
+            ..Default::default()
+        }
+    }
+}
+
+impl IndexBuffer {
+    /// Widen the indices to u32
+    pub fn to_u32(&self) -> Vec<u32> {
+        match self {
+            IndexBuffer::U16(indices) => indices.iter().map(|&i| i as u32).collect(),
+            IndexBuffer::U32(indices) => indices.clone(),
+        }
+    }
+
+    /// Number of indices in the buffer
+    pub fn len(&self) -> usize {
+        match self {
+            IndexBuffer::U16(indices) => indices.len(),
+            IndexBuffer::U32(indices) => indices.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// A contiguous range of the index buffer drawn with a single material
+#[derive(Debug, Clone)]
+pub struct Shape {
+    pub name: Option<String>,
+    /// Game material path (.mat)
+    pub material: Option<String>,
+    pub start_index: u32,
+    pub count_index: u32,
+}
+
+/// A single mesh, ready to be written out as a set of glTF primitives
+#[derive(Debug, Clone)]
+pub struct Geometry {
+    pub name: Option<String>,
+    pub indices: Vec<u32>,
+    pub vertices: Vec<Vertex>,
+    pub shapes: Vec<Shape>,
+}
+
+impl Geometry {
+    /// Pull out a single level of detail from a DOLm mesh. Shape names are matched to shapes by
+    /// position.
+    pub fn from_dolm(dolm: &Dolm, lod: usize, shape_names: &[String]) -> Result<Self> {
+        let mesh = dolm.lods.get(lod).ok_or(GLTFError::MissingLod {
+            lod,
+            num_lods: dolm.lods.len(),
+        })?;
+
+        let shapes = mesh
+            .shape_extents
+            .iter()
+            .enumerate()
+            .map(|(i, s)| Shape {
+                name: shape_names.get(i).cloned(),
+                material: None,
+                start_index: s.start_index,
+                count_index: s.count_index,
+            })
+            .collect();
+
+        Ok(Self {
+            name: None,
+            indices: mesh.indices.to_u32(),
+            vertices: mesh.vertices.iter().map(Vertex::from).collect(),
+            shapes,
+        })
+    }
+
+    /// Assign game materials to shapes by position
+    pub fn with_materials<S: AsRef<str>>(
+        mut self,
+        materials: impl IntoIterator<Item = Option<S>>,
+    ) -> Self {
+        self.shapes
+            .iter_mut()
+            .zip(materials)
+            .for_each(|(shape, material)| {
+                shape.material = material.map(|m| m.as_ref().to_owned());
+            });
+
+        self
+    }
+
+    pub fn with_name(mut self, name: impl Into<String>) -> Self {
+        self.name = Some(name.into());
+        self
+    }
+
+    /// Check that all shapes and indices refer to valid data
+    pub fn validate(&self) -> Result<()> {
+        if let Some(&index) = self
+            .indices
+            .iter()
+            .find(|&&i| i as usize >= self.vertices.len())
+        {
+            return Err(GLTFError::IndexOutOfBounds {
+                index: index as usize,
+                length: self.vertices.len(),
+            });
+        }
+
+        if let Some(shape) = self.shapes.iter().find(|s| {
+            s.start_index
+                .checked_add(s.count_index)
+                .is_none_or(|end| end as usize > self.indices.len())
+        }) {
+            return Err(GLTFError::IndexOutOfBounds {
+                index: (shape.start_index as usize).saturating_add(shape.count_index as usize),
+                length: self.indices.len(),
+            });
+        }
+
+        Ok(())
+    }
+}
+
+/// Convert shape start offsets (in triangles) into index ranges
+fn shapes_from_triangle_starts(
+    starts: impl IntoIterator<Item = (Option<String>, Option<String>, u32)>,
+    num_indices: usize,
+) -> Vec<Shape> {
+    let starts = starts.into_iter().collect::<Vec<_>>();
+
+    let ends = starts
+        .iter()
+        .skip(1)
+        .map(|(_, _, t)| *t * 3)
+        .chain(std::iter::once(num_indices as u32))
+        .collect::<Vec<_>>();
+
+    starts
+        .into_iter()
+        .zip(ends)
+        .map(|((name, material, triangle_start), end)| Shape {
+            name,
+            material,
+            start_index: triangle_start * 3,
+            count_index: end.saturating_sub(triangle_start * 3),
+        })
+        .collect()
+}
+
+impl SMDFile {
+    /// Build the renderable geometry for a level of detail. Older files only have a single LOD.
+    pub fn geometry(&self, lod: usize) -> Result<Geometry> {
+        match &self.section {
+            smd::types::Section::V3(section) => {
+                Geometry::from_dolm(&section.dolm, lod, &section.shape_names)
+            }
+            smd::types::Section::V2(section) => {
+                if lod > 0 {
+                    return Err(GLTFError::MissingLod { lod, num_lods: 1 });
+                }
+
+                let shapes = shapes_from_triangle_starts(
+                    section
+                        .shape_extents
+                        .iter()
+                        .map(|s| (Some(s.name.clone()), None, s.triangle_index)),
+                    section.index_buffer.len(),
+                );
+
+                Ok(Geometry {
+                    name: None,
+                    indices: section.index_buffer.to_u32(),
+                    vertices: section.vertex_buffer.iter().map(Vertex::from).collect(),
+                    shapes,
+                })
+            }
+        }
+    }
+}
+
+impl FMTFile {
+    /// Build the renderable geometry for a level of detail, with materials taken from the shapes
+    pub fn geometry(&self, lod: usize) -> Result<Geometry> {
+        match &self.section {
+            fmt::types::Section::V9(dolm) => {
+                let names = self
+                    .shapes
+                    .iter()
+                    .map(|s| s.name.clone())
+                    .collect::<Vec<_>>();
+
+                Ok(Geometry::from_dolm(dolm, lod, &names)?
+                    .with_materials(self.shapes.iter().map(|s| Some(&s.material))))
+            }
+            fmt::types::Section::V8(section) => {
+                if lod > 0 {
+                    return Err(GLTFError::MissingLod { lod, num_lods: 1 });
+                }
+
+                let shapes = shapes_from_triangle_starts(
+                    self.shapes.iter().map(|s| {
+                        (
+                            Some(s.name.clone()),
+                            Some(s.material.clone()),
+                            s.triangle_start,
+                        )
+                    }),
+                    section.index_buffer.len(),
+                );
+
+                Ok(Geometry {
+                    name: None,
+                    indices: section.index_buffer.to_u32(),
+                    vertices: section.vertex_buffer.iter().map(Vertex::from).collect(),
+                    shapes,
+                })
+            }
+        }
+    }
+}
+
+impl TGMFile {
+    /// Build the renderable geometry for each mesh in the tile (main mesh, ground mesh)
+    pub fn geometries(&self, lod: usize) -> Result<Vec<Geometry>> {
+        let names = ["main", "ground"];
+
+        match &self.section {
+            tgm::types::Section::V8(section) => {
+                if lod > 0 {
+                    return Err(GLTFError::MissingLod { lod, num_lods: 1 });
+                }
+
+                let geometries = section
+                    .meshes
+                    .iter()
+                    .zip(names)
+                    .map(|(mesh, name)| Geometry {
+                        name: Some(name.to_owned()),
+                        indices: mesh.indices.to_u32(),
+                        vertices: mesh.vertices.iter().map(Vertex::from).collect(),
+                        shapes: mesh
+                            .shape_extents
+                            .iter()
+                            .map(|s| Shape {
+                                name: None,
+                                material: None,
+                                start_index: s.index_base,
+                                count_index: s.index_count,
+                            })
+                            .collect(),
+                    })
+                    .collect();
+
+                Ok(geometries)
+            }
+            tgm::types::Section::V9(section) => section
+                .geometries
+                .iter()
+                .zip(names)
+                .map(|(geometry, name)| {
+                    Ok(Geometry::from_dolm(&geometry.dolm, lod, &[])?.with_name(name))
+                })
+                .collect(),
+        }
+    }
+}
@@ -0,0 +1,251 @@
+//! Minimal column-major 4x4 matrix helpers
+//! Element (row r, column c) is stored at index `c * 4 + r`, matching glTF.
+
+pub type Mat4 = [f32; 16];
+
+pub const IDENTITY: Mat4 = [
+    1., 0., 0., 0., //
+    0., 1., 0., 0., //
+    0., 0., 1., 0., //
+    0., 0., 0., 1.,
+];
+
+/// a * b
+pub fn mul(a: &Mat4, b: &Mat4) -> Mat4 {
+    let mut out = [0.; 16];
+    for c in 0..4 {
+        for r in 0..4 {
+            out[c * 4 + r] = (0..4).map(|k| a[k * 4 + r] * b[c * 4 + k]).sum();
+        }
+    }
+
+    out
+}
+
+/// Invert a matrix, returning None if it is singular
+pub fn invert(m: &Mat4) -> Option<Mat4> {
+    let mut inv = [0.; 16];
+
+    inv[0] = m[5] * m[10] * m[15] - m[5] * m[11] * m[14] - m[9] * m[6] * m[15]
+        + m[9] * m[7] * m[14]
+        + m[13] * m[6] * m[11]
+        - m[13] * m[7] * m[10];
+    inv[4] = -m[4] * m[10] * m[15] + m[4] * m[11] * m[14] + m[8] * m[6] * m[15]
+        - m[8] * m[7] * m[14]
+        - m[12] * m[6] * m[11]
+        + m[12] * m[7] * m[10];
+    inv[8] = m[4] * m[9] * m[15] - m[4] * m[11] * m[13] - m[8] * m[5] * m[15]
+        + m[8] * m[7] * m[13]
+        + m[12] * m[5] * m[11]
+        - m[12] * m[7] * m[9];
+    inv[12] = -m[4] * m[9] * m[14] + m[4] * m[10] * m[13] + m[8] * m[5] * m[14]
+        - m[8] * m[6] * m[13]
+        - m[12] * m[5] * m[10]
+        + m[12] * m[6] * m[9];
+    inv[1] = -m[1] * m[10] * m[15] + m[1] * m[11] * m[14] + m[9] * m[2] * m[15]
+        - m[9] * m[3] * m[14]
+        - m[13] * m[2] * m[11]
+        + m[13] * m[3] * m[10];
+    inv[5] = m[0] * m[10] * m[15] - m[0] * m[11] * m[14] - m[8] * m[2] * m[15]
+        + m[8] * m[3] * m[14]
+        + m[12] * m[2] * m[11]
+        - m[12] * m[3] * m[10];
+    inv[9] = -m[0] * m[9] * m[15] + m[0] * m[11] * m[13] + m[8] * m[1] * m[15]
+        - m[8] * m[3] * m[13]
+        - m[12] * m[1] * m[11]
+        + m[12] * m[3] * m[9];
+    inv[13] = m[0] * m[9] * m[14] - m[0] * m[10] * m[13] - m[8] * m[1] * m[14]
+        + m[8] * m[2] * m[13]
+        + m[12] * m[1] * m[10]
+        - m[12] * m[2] * m[9];
+    inv[2] = m[1] * m[6] * m[15] - m[1] * m[7] * m[14] - m[5] * m[2] * m[15]
+        + m[5] * m[3] * m[14]
+        + m[13] * m[2] * m[7]
+        - m[13] * m[3] * m[6];
+    inv[6] = -m[0] * m[6] * m[15] + m[0] * m[7] * m[14] + m[4] * m[2] * m[15]
+        - m[4] * m[3] * m[14]
+        - m[12] * m[2] * m[7]
+        + m[12] * m[3] * m[6];
+    inv[10] = m[0] * m[5] * m[15] - m[0] * m[7] * m[13] - m[4] * m[1] * m[15]
+        + m[4] * m[3] * m[13]
+        + m[12] * m[1] * m[7]
+        - m[12] * m[3] * m[5];
+    inv[14] = -m[0] * m[5] * m[14] + m[0] * m[6] * m[13] + m[4] * m[1] * m[14]
+        - m[4] * m[2] * m[13]
+        - m[12] * m[1] * m[6]
+        + m[12] * m[2] * m[5];
+    inv[3] = -m[1] * m[6] * m[11] + m[1] * m[7] * m[10] + m[5] * m[2] * m[11]
+        - m[5] * m[3] * m[10]
+        - m[9] * m[2] * m[7]
+        + m[9] * m[3] * m[6];
+    inv[7] = m[0] * m[6] * m[11] - m[0] * m[7] * m[10] - m[4] * m[2] * m[11]
+        + m[4] * m[3] * m[10]
+        + m[8] * m[2] * m[7]
+        - m[8] * m[3] * m[6];
+    inv[11] = -m[0] * m[5] * m[11] + m[0] * m[7] * m[9] + m[4] * m[1] * m[11]
+        - m[4] * m[3] * m[9]
+        - m[8] * m[1] * m[7]
+        + m[8] * m[3] * m[5];
+    inv[15] = m[0] * m[5] * m[10] - m[0] * m[6] * m[9] - m[4] * m[1] * m[10]
+        + m[4] * m[2] * m[9]
+        + m[8] * m[1] * m[6]
+        - m[8] * m[2] * m[5];
+
+    let det = m[0] * inv[0] + m[1] * inv[4] + m[2] * inv[8] + m[3] * inv[12];
+    if det.abs() < f32::EPSILON {
+        return None;
+    }
+
+    Some(inv.map(|x| x / det))
+}
+
+/// Split an affine transform into translation, rotation (xyzw quaternion) and scale
+pub fn decompose(m: &Mat4) -> ([f32; 3], [f32; 4], [f32; 3]) {
+    let translation = [m[12], m[13], m[14]];
+
+    let column = |c: usize| [m[c * 4], m[c * 4 + 1], m[c * 4 + 2]];
+    let length = |v: [f32; 3]| v.iter().map(|c| c * c).sum::<f32>().sqrt();
+
+    let mut scale = [length(column(0)), length(column(1)), length(column(2))];
+    // Mirrored transforms are represented with a negative x scale
+    let [x, y, z] = [column(0), column(1), column(2)];
+    let det = x[0] * (y[1] * z[2] - y[2] * z[1]) - y[0] * (x[1] * z[2] - x[2] * z[1])
+        + z[0] * (x[1] * y[2] - x[2] * y[1]);
+    if det < 0. {
+        scale[0] = -scale[0];
+    }
+
+    // Pure rotation matrix, r[row][column]
+    let mut r = [[0.; 3]; 3];
+    for (c, s) in scale.iter().enumerate() {
+        for (row, value) in r.iter_mut().zip(column(c)) {
+            row[c] = if s.abs() > f32::EPSILON {
+                value / s
+            } else {
+                0.
+            };
+        }
+    }
+
+    // https://www.euclideanspace.com/maths/geometry/rotations/conversions/matrixToQuaternion/
+    let trace = r[0][0] + r[1][1] + r[2][2];
+    let rotation = if trace > 0. {
+        let s = (trace + 1.).sqrt() * 2.;
+        [
+            (r[2][1] - r[1][2]) / s,
+            (r[0][2] - r[2][0]) / s,
+            (r[1][0] - r[0][1]) / s,
+            0.25 * s,
+        ]
+    } else if r[0][0] > r[1][1] && r[0][0] > r[2][2] {
+        let s = (1. + r[0][0] - r[1][1] - r[2][2]).sqrt() * 2.;
+        [
+            0.25 * s,
+            (r[0][1] + r[1][0]) / s,
+            (r[0][2] + r[2][0]) / s,
+            (r[2][1] - r[1][2]) / s,
+        ]
+    } else if r[1][1] > r[2][2] {
+        let s = (1. + r[1][1] - r[0][0] - r[2][2]).sqrt() * 2.;
+        [
+            (r[0][1] + r[1][0]) / s,
+            0.25 * s,
+            (r[1][2] + r[2][1]) / s,
+            (r[0][2] - r[2][0]) / s,
+        ]
+    } else {
+        let s = (1. + r[2][2] - r[0][0] - r[1][1]).sqrt() * 2.;
+        [
+            (r[0][2] + r[2][0]) / s,
+            (r[1][2] + r[2][1]) / s,
+            0.25 * s,
+            (r[1][0] - r[0][1]) / s,
+        ]
+    };
+
+    (translation, normalise_quat(rotation), scale)
+}
+
+/// Scale a quaternion to unit length, falling back to identity for degenerate input
+pub fn normalise_quat(q: [f32; 4]) -> [f32; 4] {
+    let length = q.iter().map(|c| c * c).sum::<f32>().sqrt();
+    if length > f32::EPSILON {
+        q.map(|c| c / length)
+    } else {
+        [0., 0., 0., 1.]
+    }
+}
+
+/// Linear interpolation between two vectors
+pub fn lerp<const N: usize>(a: [f32; N], b: [f32; N], t: f32) -> [f32; N] {
+    std::array::from_fn(|i| a[i] + (b[i] - a[i]) * t)
+}
+
+/// Spherical linear interpolation between two unit quaternions, taking the shortest path
+pub fn slerp(a: [f32; 4], b: [f32; 4], t: f32) -> [f32; 4] {
+    let mut dot = (0..4).map(|i| a[i] * b[i]).sum::<f32>();
+    let b = if dot < 0. {
+        dot = -dot;
+        b.map(|c| -c)
+    } else {
+        b
+    };
+
+    // Nearly parallel, fall back to lerp to avoid dividing by ~0
+    if dot > 0.9995 {
+        return normalise_quat(lerp(a, b, t));
+    }
+
+    let theta = dot.acos();
+    let sin_theta = theta.sin();
+    let wa = ((1. - t) * theta).sin() / sin_theta;
+    let wb = (t * theta).sin() / sin_theta;
+
+    std::array::from_fn(|i| a[i] * wa + b[i] * wb)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_invert() {
+        let m = [
+            2., 0., 0., 0., //
+            0., 4., 0., 0., //
+            0., 0., 8., 0., //
+            1., 2., 3., 1.,
+        ];
+
+        let inv = invert(&m).unwrap();
+        let product = mul(&m, &inv);
+
+        product
+            .iter()
+            .zip(IDENTITY)
+            .for_each(|(a, b)| assert!((a - b).abs() < 1e-6));
+
+        assert!(invert(&[0.; 16]).is_none());
+    }
+
+    #[test]
+    fn test_decompose() {
+        // 90 degrees about z, scaled by 2 and translated
+        let m = [
+            0., 2., 0., 0., //
+            -2., 0., 0., 0., //
+            0., 0., 2., 0., //
+            1., 2., 3., 1.,
+        ];
+
+        let (translation, rotation, scale) = decompose(&m);
+        let half = std::f32::consts::FRAC_1_SQRT_2;
+
+        assert_eq!(translation, [1., 2., 3.]);
+        scale.iter().for_each(|s| assert!((s - 2.).abs() < 1e-6));
+        rotation
+            .iter()
+            .zip([0., 0., half, half])
+            .for_each(|(a, b)| assert!((a - b).abs() < 1e-6));
+    }
+}
@@ -0,0 +1,648 @@
+//! Export of meshes, skeletons & animations to binary glTF 2.0 (.glb)
+mod animation;
+mod geometry;
+mod math;
+mod skeleton;
+pub mod types;
+
+use std::collections::{BTreeMap, HashMap};
+
+pub use animation::{BoneAnimation, Clip, TRS};
+pub use geometry::{Geometry, Shape, Vertex};
+use serde_json::json;
+pub use skeleton::Skeleton;
+use types::*;
+
+use crate::{
+    file_parsers::{
+        ast::types::ASTFile, error::ParseError, sm::types::SMFile, smd::types::SMDFile,
+    },
+    fs::error::FSError,
+};
+
+#[derive(Debug, thiserror::Error)]
+pub enum GLTFError {
+    #[error("level of detail {lod} requested, but mesh only has {num_lods}")]
+    MissingLod { lod: usize, num_lods: usize },
+
+    #[error("index out of bounds: {index}, length: {length}")]
+    IndexOutOfBounds { index: usize, length: usize },
+
+    #[error("bone index out of bounds: {index}, number of bones: {length}")]
+    BoneOutOfBounds { index: usize, length: usize },
+
+    #[error("bone hierarchy contains a cycle")]
+    BoneCycle,
+
+    #[error(transparent)]
+    Parse(#[from] ParseError),
+
+    #[error(transparent)]
+    FS(#[from] FSError),
+
+    #[error(transparent)]
+    Serde(#[from] serde_json::Error),
+}
+
+pub type Result<T, E = GLTFError> = std::result::Result<T, E>;
+
+/// A skeleton that has been written to the document
+#[derive(Debug, Clone)]
+pub struct SkinHandle {
+    /// Index of the glTF skin
+    pub skin: usize,
+    /// Node index for each bone
+    pub joints: Vec<usize>,
+}
+
+/// Incrementally builds a glTF document along with its single binary buffer
+#[derive(Debug, Default)]
+pub struct GLTFBuilder {
+    root: Root,
+    buffer: Vec<u8>,
+    /// Game material path -> glTF material index
+    materials: HashMap<String, usize>,
+    /// Nodes at the root of the scene
+    scene_nodes: Vec<usize>,
+}
+
+impl GLTFBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Append raw bytes to the buffer, returning the buffer view index
+    fn push_view(&mut self, bytes: &[u8], target: Option<Target>) -> usize {
+        // Accessors need their data aligned to the component size, 4 covers all cases
+        self.buffer.resize(self.buffer.len().next_multiple_of(4), 0);
+
+        let view = BufferView {
+            buffer: 0,
+            byte_offset: self.buffer.len(),
+            byte_length: bytes.len(),
+            target,
+        };
+        self.buffer.extend_from_slice(bytes);
+
+        self.root.buffer_views.push(view);
+        self.root.buffer_views.len() - 1
+    }
+
+    /// Write a tightly packed accessor, returning its index
+    fn push_accessor<T: Copy, const N: usize>(
+        &mut self,
+        data: &[[T; N]],
+        component_type: ComponentType,
+        accessor_type: AccessorType,
+        normalized: bool,
+        target: Option<Target>,
+        to_le_bytes: impl Fn(T) -> Vec<u8>,
+    ) -> usize {
+        debug_assert_eq!(N, accessor_type.num_components());
+
+        let bytes = data
+            .iter()
+            .flatten()
+            .flat_map(|&c| to_le_bytes(c))
+            .collect::<Vec<_>>();
+        debug_assert_eq!(bytes.len(), data.len() * N * component_type.size());
+
+        let buffer_view = self.push_view(&bytes, target);
+
+        self.root.accessors.push(Accessor {
+            buffer_view,
+            component_type,
+            normalized,
+            count: data.len(),
+            accessor_type,
+            min: None,
+            max: None,
+        });
+        self.root.accessors.len() - 1
+    }
+
+    fn push_f32s<const N: usize>(
+        &mut self,
+        data: &[[f32; N]],
+        accessor_type: AccessorType,
+    ) -> usize {
+        self.push_accessor(
+            data,
+            ComponentType::Float,
+            accessor_type,
+            false,
+            Some(Target::ArrayBuffer),
+            |c| c.to_le_bytes().to_vec(),
+        )
+    }
+
+    /// Get or create the material for a game material path
+    fn material(&mut self, mat_file: &str) -> usize {
+        if let Some(&index) = self.materials.get(mat_file) {
+            return index;
+        }
+
+        let name = mat_file
+            .rsplit('/')
+            .next()
+            .unwrap_or(mat_file)
+            .trim_end_matches(".mat");
+        self.root.materials.push(Material {
+            name: Some(name.to_owned()),
+            double_sided: false,
+            extras: Some(json!({ "mat_file": mat_file })),
+        });
+
+        let index = self.root.materials.len() - 1;
+        self.materials.insert(mat_file.to_owned(), index);
+
+        index
+    }
+
+    /// Write vertex attributes, only including those that are present for every vertex
+    fn push_attributes(&mut self, vertices: &[Vertex]) -> BTreeMap<String, usize> {
+        let mut attributes = BTreeMap::new();
+
+        // Positions need bounds for the accessor to be valid
+        let positions = vertices.iter().map(|v| v.pos).collect::<Vec<_>>();
+        let position = self.push_f32s(&positions, AccessorType::Vec3);
+        let (min, max) = positions.iter().fold(
+            ([f32::INFINITY; 3], [f32::NEG_INFINITY; 3]),
+            |(mut min, mut max), p| {
+                (0..3).for_each(|i| {
+                    min[i] = min[i].min(p[i]);
+                    max[i] = max[i].max(p[i]);
+                });
+                (min, max)
+            },
+        );
+        if !positions.is_empty() {
+            self.root.accessors[position].min = Some(min.to_vec());
+            self.root.accessors[position].max = Some(max.to_vec());
+        }
+        attributes.insert("POSITION".to_owned(), position);
+
+        if let Some(normals) = vertices
+            .iter()
+            .map(|v| v.normal)
+            .collect::<Option<Vec<_>>>()
+        {
+            let normal = self.push_f32s(&normals, AccessorType::Vec3);
+            attributes.insert("NORMAL".to_owned(), normal);
+        }
+
+        if let Some(tangents) = vertices
+            .iter()
+            .map(|v| v.tangent)
+            .collect::<Option<Vec<_>>>()
+        {
+            let tangent = self.push_f32s(&tangents, AccessorType::Vec4);
+            attributes.insert("TANGENT".to_owned(), tangent);
+        }
+
+        if let Some(uvs) = vertices
+            .iter()
+            .map(|v| v.tex_coord0)
+            .collect::<Option<Vec<_>>>()
+        {
+            let uv = self.push_f32s(&uvs, AccessorType::Vec2);
+            attributes.insert("TEXCOORD_0".to_owned(), uv);
+        }
+
+        if let Some(uvs) = vertices
+            .iter()
+            .map(|v| v.tex_coord1)
+            .collect::<Option<Vec<_>>>()
+        {
+            let uv = self.push_f32s(&uvs, AccessorType::Vec2);
+            attributes.insert("TEXCOORD_1".to_owned(), uv);
+        }
+
+        if let (Some(joints), Some(weights)) = (
+            vertices
+                .iter()
+                .map(|v| v.joints)
+                .collect::<Option<Vec<_>>>(),
+            vertices
+                .iter()
+                .map(|v| v.weights)
+                .collect::<Option<Vec<_>>>(),
+        ) {
+            let joints = self.push_accessor(
+                &joints,
+                ComponentType::UnsignedByte,
+                AccessorType::Vec4,
+                false,
+                Some(Target::ArrayBuffer),
+                |c| vec![c],
+            );
+            let weights = self.push_accessor(
+                &weights,
+                ComponentType::UnsignedByte,
+                AccessorType::Vec4,
+                true,
+                Some(Target::ArrayBuffer),
+                |c| vec![c],
+            );
+            attributes.insert("JOINTS_0".to_owned(), joints);
+            attributes.insert("WEIGHTS_0".to_owned(), weights);
+        }
+
+        attributes
+    }
+
+    /// Add a mesh to the document, with one primitive per shape. Returns the node index.
+    pub fn add_geometry(
+        &mut self,
+        geometry: &Geometry,
+        skin: Option<&SkinHandle>,
+    ) -> Result<usize> {
+        geometry.validate()?;
+
+        let attributes = self.push_attributes(&geometry.vertices);
+
+        // Fall back to drawing everything if there's no shape information
+        let whole = [Shape {
+            name: None,
+            material: None,
+            start_index: 0,
+            count_index: geometry.indices.len() as u32,
+        }];
+        let shapes = if geometry.shapes.is_empty() {
+            whole.as_slice()
+        } else {
+            geometry.shapes.as_slice()
+        };
+
+        let primitives = shapes
+            .iter()
+            .filter(|s| s.count_index > 0)
+            .map(|shape| {
+                let start = shape.start_index as usize;
+                let end = start + shape.count_index as usize;
+                let indices = geometry.indices[start..end]
+                    .iter()
+                    .map(|&i| [i])
+                    .collect::<Vec<_>>();
+                let indices = self.push_accessor(
+                    &indices,
+                    ComponentType::UnsignedInt,
+                    AccessorType::Scalar,
+                    false,
+                    Some(Target::ElementArrayBuffer),
+                    |c| c.to_le_bytes().to_vec(),
+                );
+
+                let material = shape.material.as_deref().map(|m| self.material(m));
+
+                Primitive {
+                    attributes: attributes.clone(),
+                    indices: Some(indices),
+                    material,
+                }
+            })
+            .collect();
+
+        self.root.meshes.push(Mesh {
+            name: geometry.name.clone(),
+            primitives,
+        });
+
+        self.root.nodes.push(Node {
+            name: geometry.name.clone(),
+            mesh: Some(self.root.meshes.len() - 1),
+            skin: skin.map(|s| s.skin),
+            ..Default::default()
+        });
+        let node = self.root.nodes.len() - 1;
+        self.scene_nodes.push(node);
+
+        Ok(node)
+    }
+
+    /// Add a bone hierarchy as nodes, along with a skin that meshes can be bound to
+    pub fn add_skeleton(&mut self, skeleton: &Skeleton) -> SkinHandle {
+        let first = self.root.nodes.len();
+        let joints = (first..first + skeleton.names.len()).collect::<Vec<_>>();
+
+        skeleton
+            .names
+            .iter()
+            .zip(&skeleton.local)
+            .zip(&skeleton.children)
+            .for_each(|((name, local), children)| {
+                // Animated nodes can't use a matrix, so store the bind pose as TRS
+                let trs = TRS::from_matrix(local);
+                self.root.nodes.push(Node {
+                    name: Some(name.clone()),
+                    children: children.iter().map(|c| joints[*c]).collect(),
+                    translation: Some(trs.translation),
+                    rotation: Some(trs.rotation),
+                    scale: Some(trs.scale),
+                    ..Default::default()
+                })
+            });
+        self.scene_nodes
+            .extend(skeleton.roots.iter().map(|r| joints[*r]));
+
+        let inverse_bind_matrices = self.push_accessor(
+            &skeleton.inverse_bind_matrices(),
+            ComponentType::Float,
+            AccessorType::Mat4,
+            false,
+            None,
+            |c| c.to_le_bytes().to_vec(),
+        );
+
+        self.root.skins.push(Skin {
+            name: None,
+            inverse_bind_matrices: Some(inverse_bind_matrices),
+            skeleton: skeleton.roots.first().map(|r| joints[*r]),
+            joints: joints.clone(),
+        });
+
+        SkinHandle {
+            skin: self.root.skins.len() - 1,
+            joints,
+        }
+    }
+
+    /// Add a sampled animation driving the joints of a skin. Returns the animation index.
+    pub fn add_animation(&mut self, clip: &Clip, skin: &SkinHandle) -> Result<usize> {
+        let times = clip.times().into_iter().map(|t| [t]).collect::<Vec<_>>();
+        let input = self.push_animation_data(&times, AccessorType::Scalar);
+        self.root.accessors[input].min = Some(vec![0.]);
+        self.root.accessors[input].max = Some(vec![clip.duration()]);
+
+        let mut animation = types::Animation {
+            name: Some(clip.name.clone()),
+            ..Default::default()
+        };
+
+        for bone in &clip.bones {
+            let node = *skin
+                .joints
+                .get(bone.bone)
+                .ok_or(GLTFError::BoneOutOfBounds {
+                    index: bone.bone,
+                    length: skin.joints.len(),
+                })?;
+
+            let translations = bone
+                .frames
+                .iter()
+                .map(|f| f.translation)
+                .collect::<Vec<_>>();
+            let rotations = bone.frames.iter().map(|f| f.rotation).collect::<Vec<_>>();
+            let scales = bone.frames.iter().map(|f| f.scale).collect::<Vec<_>>();
+
+            let outputs = [
+                (
+                    TargetPath::Translation,
+                    self.push_animation_data(&translations, AccessorType::Vec3),
+                ),
+                (
+                    TargetPath::Rotation,
+                    self.push_animation_data(&rotations, AccessorType::Vec4),
+                ),
+                (
+                    TargetPath::Scale,
+                    self.push_animation_data(&scales, AccessorType::Vec3),
+                ),
+            ];
+
+            for (path, output) in outputs {
+                animation.samplers.push(AnimationSampler {
+                    input,
+                    interpolation: Interpolation::Linear,
+                    output,
+                });
+                animation.channels.push(Channel {
+                    sampler: animation.samplers.len() - 1,
+                    target: ChannelTarget { node, path },
+                });
+            }
+        }
+
+        self.root.animations.push(animation);
+        Ok(self.root.animations.len() - 1)
+    }
+
+    /// Sample and add every animation in a skeleton file, skipping those that can't be decoded
+    pub fn add_animations(&mut self, ast: &ASTFile, skin: &SkinHandle) -> Result<Vec<usize>> {
+        ast.animations
+            .iter()
+            .filter_map(|animation| match Clip::sample(ast, animation) {
+                Ok(clip) => Some(clip),
+                Err(e) => {
+                    log::warn!("Skipping animation {:?}: {e}", animation.name);
+                    None
+                }
+            })
+            .map(|clip| self.add_animation(&clip, skin))
+            .collect()
+    }
+
+    /// Animation inputs & outputs aren't bound to a GPU buffer
+    fn push_animation_data<const N: usize>(
+        &mut self,
+        data: &[[f32; N]],
+        accessor_type: AccessorType,
+    ) -> usize {
+        self.push_accessor(
+            data,
+            ComponentType::Float,
+            accessor_type,
+            false,
+            None,
+            |c| c.to_le_bytes().to_vec(),
+        )
+    }
+
+    /// Finalise the document as a glTF JSON structure and binary buffer
+    pub fn finish(mut self) -> (Root, Vec<u8>) {
+        self.buffer.resize(self.buffer.len().next_multiple_of(4), 0);
+        if !self.buffer.is_empty() {
+            self.root.buffers = vec![Buffer {
+                byte_length: self.buffer.len(),
+            }];
+        }
+
+        self.root.scenes = vec![Scene {
+            name: None,
+            nodes: self.scene_nodes,
+        }];
+        self.root.scene = Some(0);
+
+        (self.root, self.buffer)
+    }
+
+    /// Finalise the document as a binary glTF (.glb) file
+    /// https://registry.khronos.org/glTF/specs/2.0/glTF-2.0.html#binary-gltf-layout
+    pub fn to_glb(self) -> Result<Vec<u8>> {
+        let (root, buffer) = self.finish();
+
+        let mut json = serde_json::to_vec(&root)?;
+        json.resize(json.len().next_multiple_of(4), b' ');
+
+        let mut chunks = vec![(*b"JSON", json)];
+        if !buffer.is_empty() {
+            chunks.push((*b"BIN\0", buffer));
+        }
+
+        let total_length = 12 + chunks.iter().map(|(_, c)| 8 + c.len()).sum::<usize>();
+
+        let mut glb = Vec::with_capacity(total_length);
+        glb.extend_from_slice(b"glTF");
+        glb.extend_from_slice(&2_u32.to_le_bytes());
+        glb.extend_from_slice(&(total_length as u32).to_le_bytes());
+        for (chunk_type, chunk) in chunks {
+            glb.extend_from_slice(&(chunk.len() as u32).to_le_bytes());
+            glb.extend_from_slice(&chunk_type);
+            glb.extend_from_slice(&chunk);
+        }
+
+        Ok(glb)
+    }
+}
+
+/// Export a skinned mesh (.sm + .smd) to .glb, optionally bound to a skeleton (.ast) along with
+/// its animations
+pub fn skinned_mesh_to_glb(
+    sm: &SMFile,
+    smd: &SMDFile,
+    skeleton: Option<&ASTFile>,
+    lod: usize,
+    animations: bool,
+) -> Result<Vec<u8>> {
+    let mut builder = GLTFBuilder::new();
+
+    let skin = match skeleton {
+        Some(ast) => {
+            let skin = builder.add_skeleton(&Skeleton::from_bones(&ast.bones)?);
+            if animations {
+                builder.add_animations(ast, &skin)?;
+            }
+            Some(skin)
+        }
+        None => None,
+    };
+
+    let geometry = smd
+        .geometry(lod)?
+        .with_materials(sm.materials.iter().map(|m| m.mat_file.as_ref()));
+    builder.add_geometry(&geometry, skin.as_ref())?;
+
+    builder.to_glb()
+}
+
+/// Export a skeleton (.ast) and all of its animations to .glb
+pub fn skeleton_to_glb(ast: &ASTFile) -> Result<Vec<u8>> {
+    let mut builder = GLTFBuilder::new();
+
+    let skin = builder.add_skeleton(&Skeleton::from_bones(&ast.bones)?);
+    builder.add_animations(ast, &skin)?;
+
+    builder.to_glb()
+}
+
+/// Export a set of static meshes (.smd, .fmt, .tgm) to .glb
+pub fn geometries_to_glb(geometries: &[Geometry]) -> Result<Vec<u8>> {
+    let mut builder = GLTFBuilder::new();
+
+    for geometry in geometries {
+        builder.add_geometry(geometry, None)?;
+    }
+
+    builder.to_glb()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::file_parsers::ast::types::Bone;
+
+    #[test]
+    fn test_glb_layout() {
+        let geometry = Geometry {
+            name: Some("triangle".to_owned()),
+            indices: vec![0, 1, 2],
+            vertices: [[0., 0., 0.], [1., 0., 0.], [0., 1., 0.]]
+                .into_iter()
+                .map(|pos| Vertex {
+                    pos,
+                    ..Default::default()
+                })
+                .collect(),
+            shapes: vec![],
+        };
+
+        let glb = geometries_to_glb(&[geometry]).unwrap();
+
+        assert_eq!(&glb[..4], b"glTF");
+        assert_eq!(
+            u32::from_le_bytes(glb[8..12].try_into().unwrap()) as usize,
+            glb.len()
+        );
+        assert_eq!(glb.len() % 4, 0);
+
+        let json_length = u32::from_le_bytes(glb[12..16].try_into().unwrap()) as usize;
+        assert_eq!(&glb[16..20], b"JSON");
+        let json: serde_json::Value = serde_json::from_slice(&glb[20..20 + json_length]).unwrap();
+        assert_eq!(json["meshes"][0]["primitives"].as_array().unwrap().len(), 1);
+        assert_eq!(json["accessors"][0]["max"], json!([1., 1., 0.]));
+
+        let bin = &glb[20 + json_length..];
+        assert_eq!(&bin[4..8], b"BIN\0");
+        assert_eq!(
+            u32::from_le_bytes(bin[..4].try_into().unwrap()) as u64,
+            json["buffers"][0]["byteLength"].as_u64().unwrap()
+        );
+    }
+
+    #[test]
+    fn test_malformed() {
+        let geometry = Geometry {
+            name: None,
+            indices: vec![0, 0, 0],
+            vertices: vec![Vertex::default()],
+            shapes: vec![Shape {
+                name: None,
+                material: None,
+                start_index: u32::MAX,
+                count_index: 3,
+            }],
+        };
+        assert!(matches!(
+            geometry.validate(),
+            Err(GLTFError::IndexOutOfBounds { length: 3, .. })
+        ));
+
+        let bone = |child: Option<u8>, sibling: Option<u8>| Bone {
+            sibling,
+            child,
+            transform: [[0.; 4]; 4],
+            name_length: 0,
+            unk1: None,
+            name: String::new(),
+        };
+
+        // Root and its child are each other's children
+        let bones = [bone(Some(1), None), bone(Some(0), None)];
+        assert!(matches!(
+            Skeleton::from_bones(&bones),
+            Err(GLTFError::BoneCycle)
+        ));
+
+        // A loop hanging off nothing
+        let bones = [bone(None, None), bone(Some(2), None), bone(Some(1), None)];
+        assert!(matches!(
+            Skeleton::from_bones(&bones),
+            Err(GLTFError::BoneCycle)
+        ));
+
+        let bones = [bone(Some(1), None), bone(None, Some(2)), bone(None, None)];
+        let skeleton = Skeleton::from_bones(&bones).unwrap();
+        assert_eq!(skeleton.children, [vec![1, 2], vec![], vec![]]);
+    }
+}
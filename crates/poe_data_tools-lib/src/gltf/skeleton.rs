@@ -0,0 +1,127 @@
+use super::{
+    GLTFError, Result,
+    math::{self, Mat4},
+};
+use crate::file_parsers::ast::types::Bone;
+
+/// Parent-relative transform of a bone in glTF layout
+pub(super) fn bone_matrix(bone: &Bone) -> Mat4 {
+    // NOTE: AST matrices are stored row-major for row vectors (DirectX style), which lays out the
+    // same as column-major for column vectors
+    let mut m = [0.; 16];
+    m.iter_mut()
+        .zip(bone.transform.as_flattened())
+        .for_each(|(a, b)| *a = *b);
+
+    m
+}
+
+/// Bone hierarchy with transforms in glTF (column-major) layout
+#[derive(Debug, Clone)]
+pub struct Skeleton {
+    pub names: Vec<String>,
+    /// Parent-relative transforms
+    pub local: Vec<Mat4>,
+    pub children: Vec<Vec<usize>>,
+    /// Bones without a parent
+    pub roots: Vec<usize>,
+}
+
+impl Skeleton {
+    /// Build the hierarchy from the first-child/next-sibling links stored in the AST file
+    pub fn from_bones(bones: &[Bone]) -> Result<Self> {
+        let get_index = |i: Option<u8>| -> Result<Option<usize>> {
+            match i.map(|i| i as usize) {
+                Some(i) if i >= bones.len() => Err(GLTFError::BoneOutOfBounds {
+                    index: i,
+                    length: bones.len(),
+                }),
+                i => Ok(i),
+            }
+        };
+
+        // Follow a chain of siblings, guarding against malformed cycles
+        let siblings = |first: Option<usize>| -> Result<Vec<usize>> {
+            let mut chain = vec![];
+            let mut next = first;
+            while let Some(i) = next {
+                if chain.len() > bones.len() {
+                    return Err(GLTFError::BoneCycle);
+                }
+                chain.push(i);
+                next = get_index(bones[i].sibling)?;
+            }
+
+            Ok(chain)
+        };
+
+        let children = bones
+            .iter()
+            .map(|b| siblings(get_index(b.child)?))
+            .collect::<Result<Vec<_>>>()?;
+        let roots = if bones.is_empty() {
+            vec![]
+        } else {
+            siblings(Some(0))?
+        };
+
+        // Every bone has at most one parent, and following parents has to end at a bone without
+        // one, otherwise the hierarchy loops back on itself
+        let mut parents = vec![None; bones.len()];
+        for (parent, child) in children
+            .iter()
+            .enumerate()
+            .flat_map(|(parent, c)| c.iter().map(move |&child| (parent, child)))
+        {
+            if roots.contains(&child) || parents[child].replace(parent).is_some() {
+                return Err(GLTFError::BoneCycle);
+            }
+        }
+        for bone in 0..bones.len() {
+            let mut ancestor = parents[bone];
+            for _ in 0..bones.len() {
+                let Some(a) = ancestor else { break };
+                ancestor = parents[a];
+            }
+
+            if ancestor.is_some() {
+                return Err(GLTFError::BoneCycle);
+            }
+        }
+
+        let local = bones.iter().map(bone_matrix).collect();
+
+        Ok(Self {
+            names: bones.iter().map(|b| b.name.clone()).collect(),
+            local,
+            children,
+            roots,
+        })
+    }
+
+    /// Model-space transform of every bone
+    pub fn global_transforms(&self) -> Vec<Mat4> {
+        let mut global = vec![math::IDENTITY; self.local.len()];
+
+        let mut stack = self
+            .roots
+            .iter()
+            .map(|&r| (r, math::IDENTITY))
+            .collect::<Vec<_>>();
+        while let Some((bone, parent)) = stack.pop() {
+            global[bone] = math::mul(&parent, &self.local[bone]);
+
+            stack.extend(self.children[bone].iter().map(|&c| (c, global[bone])));
+        }
+
+        global
+    }
+
+    /// Inverse of the bind pose, used to bring vertices into bone space
+    pub fn inverse_bind_matrices(&self) -> Vec<Mat4> {
+        self.global_transforms()
+            .iter()
+            .map(|m| math::invert(m).unwrap_or(math::IDENTITY))
+            .collect()
+    }
+}
@@ -0,0 +1,271 @@
+//! Subset of the glTF 2.0 JSON schema needed for exporting game assets
+//! https://registry.khronos.org/glTF/specs/2.0/glTF-2.0.html
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Asset {
+    pub version: String,
+    pub generator: String,
+}
+
+impl Default for Asset {
+    fn default() -> Self {
+        Self {
+            version: "2.0".to_owned(),
+            generator: format!("poe_data_tools v{}", env!("CARGO_PKG_VERSION")),
+        }
+    }
+}
+
+/// Top level glTF document
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Root {
+    pub asset: Asset,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scene: Option<usize>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub scenes: Vec<Scene>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub nodes: Vec<Node>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub meshes: Vec<Mesh>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub materials: Vec<Material>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub skins: Vec<Skin>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub animations: Vec<Animation>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub accessors: Vec<Accessor>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub buffer_views: Vec<BufferView>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub buffers: Vec<Buffer>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Scene {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    pub nodes: Vec<usize>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Node {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub children: Vec<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mesh: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub skin: Option<usize>,
+    /// Column-major local transform
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub matrix: Option<[f32; 16]>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub translation: Option<[f32; 3]>,
+    /// Unit quaternion, xyzw
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rotation: Option<[f32; 4]>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scale: Option<[f32; 3]>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Mesh {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    pub primitives: Vec<Primitive>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Primitive {
+    /// Attribute semantic -> accessor index
+    pub attributes: BTreeMap<String, usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub indices: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub material: Option<usize>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Material {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    pub double_sided: bool,
+    /// Free-form application data, used to carry the source game material path
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub extras: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Skin {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub inverse_bind_matrices: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub skeleton: Option<usize>,
+    pub joints: Vec<usize>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Animation {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    pub channels: Vec<Channel>,
+    pub samplers: Vec<AnimationSampler>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Channel {
+    pub sampler: usize,
+    pub target: ChannelTarget,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ChannelTarget {
+    pub node: usize,
+    pub path: TargetPath,
+}
+
+/// Node property driven by an animation channel
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TargetPath {
+    Translation,
+    Rotation,
+    Scale,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum Interpolation {
+    Linear,
+    Step,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct AnimationSampler {
+    /// Accessor of keyframe times in seconds
+    pub input: usize,
+    pub interpolation: Interpolation,
+    /// Accessor of keyframe values
+    pub output: usize,
+}
+
+/// Data type of each component in an accessor
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ComponentType {
+    UnsignedByte,
+    UnsignedShort,
+    UnsignedInt,
+    Float,
+}
+
+impl ComponentType {
+    /// Size in bytes of a single component
+    pub fn size(&self) -> usize {
+        use ComponentType::*;
+        match self {
+            UnsignedByte => 1,
+            UnsignedShort => 2,
+            UnsignedInt | Float => 4,
+        }
+    }
+}
+
+impl Serialize for ComponentType {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use ComponentType::*;
+        let code = match self {
+            UnsignedByte => 5121,
+            UnsignedShort => 5123,
+            UnsignedInt => 5125,
+            Float => 5126,
+        };
+
+        serializer.serialize_u32(code)
+    }
+}
+
+/// Number of components in each accessor element
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum AccessorType {
+    Scalar,
+    Vec2,
+    Vec3,
+    Vec4,
+    Mat4,
+}
+
+impl AccessorType {
+    /// Number of components in a single element
+    pub fn num_components(&self) -> usize {
+        use AccessorType::*;
+        match self {
+            Scalar => 1,
+            Vec2 => 2,
+            Vec3 => 3,
+            Vec4 => 4,
+            Mat4 => 16,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Accessor {
+    pub buffer_view: usize,
+    pub component_type: ComponentType,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub normalized: bool,
+    pub count: usize,
+    #[serde(rename = "type")]
+    pub accessor_type: AccessorType,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min: Option<Vec<f32>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max: Option<Vec<f32>>,
+}
+
+/// Intended GPU buffer binding for a buffer view
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Target {
+    ArrayBuffer,
+    ElementArrayBuffer,
+}
+
+impl Serialize for Target {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let code = match self {
+            Target::ArrayBuffer => 34962,
+            Target::ElementArrayBuffer => 34963,
+        };
+
+        serializer.serialize_u32(code)
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BufferView {
+    pub buffer: usize,
+    pub byte_offset: usize,
+    pub byte_length: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub target: Option<Target>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Buffer {
+    pub byte_length: usize,
+}
@@ -6,6 +6,7 @@ mod bundle;
 pub mod dat;
 pub mod file_parsers;
 pub mod fs;
+pub mod gltf;
 pub mod hasher;
 mod path;
 
@@ -30,7 +31,7 @@ impl Patch {
             One => 1,
             Two => 2,
             Specific(s) if s.starts_with("3.") => 1,
-            Specific(s) if s.starts_with("4.") => 1,
+            Specific(s) if s.starts_with("4.") => 2,
             Specific(s) => panic!("Invalid major patch version {s:?}"),
         }
     }
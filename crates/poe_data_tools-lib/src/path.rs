@@ -1,4 +1,12 @@
-use crate::file_parsers::bundle_index::types::PathRep;
+use std::collections::BTreeMap;
+
+use crate::{
+    file_parsers::bundle_index::types::PathRep,
+    fs::tree::is_under,
+    hasher::murmur64a::{BuildHasherEx, BuildMurmurHash64A},
+};
+
+const HASHER: BuildMurmurHash64A = BuildMurmurHash64A { seed: 0x1337b33f };
 
 /// Pull out the path components from the bundle blob into a more usable structure
 pub fn parse_paths(path_rep_bytes: &[u8], path_rep: &PathRep) -> ParsedPathRep {
@@ -69,6 +77,133 @@ pub fn parse_paths(path_rep_bytes: &[u8], path_rep: &PathRep) -> ParsedPathRep {
     ParsedPathRep { bases, leaves }
 }
 
+/// Enumerate the full paths of files under a directory, given as a lowercase prefix without a
+/// leading slash, eg. `data/balance/`.
+///
+/// Sections are keyed by the hash of their directory, and a section's
+/// [recursive_size](PathRep::recursive_size) spans the sections of all of its subdirectories. So
+/// only the sections within the closest indexed ancestor of the directory need parsing.
+///
+/// Every section is searched instead if the index doesn't look laid out that way: no section
+/// spans any others, no ancestor has a section holding only paths within it, or the ancestor's
+/// span runs past the end of the index. Otherwise an empty result is trusted, so looking for a
+/// missing directory stays cheap.
+pub fn paths_under<'a>(
+    path_rep_bytes: &'a [u8],
+    path_reps: &'a [PathRep],
+    prefix: &str,
+) -> Box<dyn Iterator<Item = String> + 'a> {
+    let owned_prefix = prefix.to_owned();
+    let everything = Box::new(
+        path_reps
+            .iter()
+            .flat_map(|p| parse_paths(path_rep_bytes, p).get_paths())
+            .filter(move |path| is_under(path, &owned_prefix)),
+    );
+
+    if !path_reps.iter().any(|p| p.recursive_size > p.size) {
+        return everything;
+    }
+
+    // Try the directory itself, then each of its parents
+    let ancestor = std::iter::successors(Some(prefix.trim_end_matches('/')), |dir| {
+        (!dir.is_empty()).then(|| dir.rfind('/').map(|i| &dir[..i]).unwrap_or(""))
+    })
+    .filter(|dir| !dir.is_empty())
+    .find_map(|dir| {
+        let hash = HASHER.hash_one_str(dir);
+        let section = path_reps.iter().find(|p| p.hash == hash)?;
+
+        // Make sure the section is really the directory's, rather than the hash meaning
+        // something else
+        let dir_prefix = format!("{dir}/");
+        parse_paths(path_rep_bytes, section)
+            .get_paths()
+            .iter()
+            .all(|path| is_under(path, &dir_prefix))
+            .then_some(section)
+    });
+
+    let range = ancestor.and_then(|ancestor| {
+        let end = ancestor.offset.checked_add(ancestor.recursive_size)?;
+        (end as usize <= path_rep_bytes.len()).then_some(ancestor.offset..end)
+    });
+    let Some(range) = range else {
+        return everything;
+    };
+
+    let prefix = prefix.to_owned();
+    Box::new(
+        path_reps
+            .iter()
+            .filter(move |p| range.contains(&p.offset))
+            .flat_map(|p| parse_paths(path_rep_bytes, p).get_paths())
+            .filter(move |path| is_under(path, &prefix)),
+    )
+}
+
+/// Encode paths into the bundle index's path blob, the inverse of [parse_paths]. Files are
+/// grouped into one section per directory, which declares the directory as its only base and each
+/// file in it as a leaf. Sections are ordered so that each directory's
+/// [recursive_size](PathRep::recursive_size) spans those of its subdirectories, with empty
+/// sections for directories only holding other directories.
+pub fn encode_paths<'a>(paths: impl IntoIterator<Item = &'a str>) -> (Vec<PathRep>, Vec<u8>) {
+    let mut directories = BTreeMap::<_, Vec<_>>::new();
+    for path in paths {
+        let (directory, name) = match path.rfind('/') {
+            Some(i) => path.split_at(i + 1),
+            None => ("", path),
+        };
+        directories.entry(directory).or_default().push(name);
+
+        // Make sure every ancestor has a section to nest under
+        for (i, _) in directory.match_indices('/') {
+            directories.entry(&directory[..i + 1]).or_default();
+        }
+    }
+
+    let mut path_reps = vec![];
+    let mut bytes = vec![];
+    for (directory, names) in &directories {
+        let offset = bytes.len();
+
+        // Zero switches between declaring bases and leaves. Indices are 1-based references to the
+        // base to prefix with, so the directory itself refers to a base that doesn't exist yet.
+        bytes.extend_from_slice(&0_u32.to_le_bytes());
+        encode_segment(&mut bytes, 1, directory);
+        bytes.extend_from_slice(&0_u32.to_le_bytes());
+        for name in names {
+            encode_segment(&mut bytes, 1, name);
+        }
+
+        let size = (bytes.len() - offset) as u32;
+        path_reps.push(PathRep {
+            hash: HASHER.hash_one_str(&directory.trim_end_matches('/').to_lowercase()),
+            offset: offset as u32,
+            size,
+            recursive_size: size,
+        });
+    }
+
+    // Sorted by directory, so subdirectories directly follow their parent
+    let directories = directories.keys().collect::<Vec<_>>();
+    for i in 0..path_reps.len() {
+        let end = (i + 1..path_reps.len())
+            .find(|&j| !directories[j].starts_with(directories[i]))
+            .map(|j| path_reps[j].offset)
+            .unwrap_or(bytes.len() as u32);
+        path_reps[i].recursive_size = end - path_reps[i].offset;
+    }
+
+    (path_reps, bytes)
+}
+
+fn encode_segment(bytes: &mut Vec<u8>, index: u32, segment: &str) {
+    bytes.extend_from_slice(&index.to_le_bytes());
+    bytes.extend_from_slice(segment.as_bytes());
+    bytes.push(0);
+}
+
 pub struct ParsedPathRep {
     pub bases: Vec<PathSegment>,
     pub leaves: Vec<PathSegment>,
@@ -102,3 +237,115 @@ pub struct PathSegment {
     pub is_leaf: bool,
     pub parent_index: Option<usize>,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let paths = [
+            "data/mods.datc64",
+            "data/balance/stats.datc64",
+            "data/stats.datc64",
+            "root.txt",
+        ];
+        let (path_reps, bytes) = encode_paths(paths);
+        assert_eq!(path_reps.len(), 3);
+        // The root spans everything, data/ spans data/balance/
+        assert_eq!(path_reps[0].recursive_size as usize, bytes.len());
+        assert_eq!(
+            path_reps[1].recursive_size,
+            path_reps[1].size + path_reps[2].size
+        );
+
+        let mut decoded = path_reps
+            .iter()
+            .flat_map(|p| parse_paths(&bytes, p).get_paths())
+            .collect::<Vec<_>>();
+        decoded.sort();
+
+        let mut paths = paths.to_vec();
+        paths.sort();
+        assert_eq!(decoded, paths);
+    }
+
+    #[test]
+    fn prune() {
+        let paths = [
+            "art/2d/a.dds",
+            "data/balance/stats.datc64",
+            "data/mods.datc64",
+            "root.txt",
+        ];
+        let (path_reps, bytes) = encode_paths(paths);
+
+        let mut under = paths_under(&bytes, &path_reps, "data/balance/").collect::<Vec<_>>();
+        assert_eq!(under, ["data/balance/stats.datc64"]);
+
+        under = paths_under(&bytes, &path_reps, "data/").collect();
+        under.sort();
+        assert_eq!(under, ["data/balance/stats.datc64", "data/mods.datc64"]);
+
+        // Unknown directories are searched for within the closest indexed parent
+        assert_eq!(
+            paths_under(&bytes, &path_reps, "art/2d/missing/").count(),
+            0
+        );
+        assert_eq!(paths_under(&bytes, &path_reps, "").count(), 4);
+    }
+
+    #[test]
+    fn prune_fallback() {
+        let paths = ["data/balance/stats.datc64", "data/mods.datc64", "root.txt"];
+        let under = |path_reps: &[PathRep], bytes: &[u8]| {
+            let mut under = paths_under(bytes, path_reps, "data/").collect::<Vec<_>>();
+            under.sort();
+            under
+        };
+        let expected = ["data/balance/stats.datc64", "data/mods.datc64"];
+
+        // Sections that don't span their subdirectories
+        let (mut path_reps, bytes) = encode_paths(paths);
+        path_reps.iter_mut().for_each(|p| p.recursive_size = p.size);
+        assert_eq!(under(&path_reps, &bytes), expected);
+
+        // Directories hashed some other way
+        let (mut path_reps, bytes) = encode_paths(paths);
+        path_reps.iter_mut().for_each(|p| p.hash = !p.hash);
+        assert_eq!(under(&path_reps, &bytes), expected);
+
+        // A directory's hash pointing at another directory's section
+        let (mut path_reps, bytes) = encode_paths(paths);
+        let data = HASHER.hash_one_str("data");
+        path_reps
+            .iter_mut()
+            .for_each(|p| p.hash = if p.hash == data { 0 } else { p.hash });
+        let root = path_reps.iter().position(|p| p.offset == 0).unwrap();
+        path_reps[root].hash = data;
+        assert_eq!(under(&path_reps, &bytes), expected);
+
+        // A directory spanning past the end of the index
+        let (mut path_reps, bytes) = encode_paths(paths);
+        path_reps
+            .iter_mut()
+            .for_each(|p| p.recursive_size = u32::MAX);
+        assert_eq!(under(&path_reps, &bytes), expected);
+    }
+
+    #[test]
+    fn prune_trusted() {
+        let paths = ["data/balance/stats.datc64", "data/mods.datc64", "root.txt"];
+
+        // A valid span is trusted even when nothing is found within it, rather than searching
+        // every section
+        let (mut path_reps, bytes) = encode_paths(paths);
+        let data = HASHER.hash_one_str("data");
+        let data = path_reps.iter_mut().find(|p| p.hash == data).unwrap();
+        data.recursive_size = data.size;
+        let under = paths_under(&bytes, &path_reps, "data/").collect::<Vec<_>>();
+        assert_eq!(under, ["data/mods.datc64"]);
+
+        assert_eq!(paths_under(&bytes, &path_reps, "data/missing/").count(), 0);
+    }
+}